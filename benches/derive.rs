@@ -0,0 +1,38 @@
+//! Key derivation throughput per attempt: raw keys vs BIP-39 mnemonics, which pay a
+//! 2048-round PBKDF2 and a BIP-32 path per attempt.
+//!
+//! Run with `cargo bench --bench derive`.
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use vanity::keys::{derive_candidates, KeyMode, KeyStream};
+
+fn derive(c: &mut Criterion) {
+    let stream = KeyStream::from_seed(1);
+    let mnemonic = |scan_indices| KeyMode::Mnemonic {
+        path: "m/84'/0'/0'/0/0".parse().unwrap(),
+        path_string: "m/84'/0'/0'/0/0".to_string(),
+        words: 12,
+        passphrase: String::new(),
+        scan_indices,
+    };
+    let mut group = c.benchmark_group("derive");
+    group.throughput(Throughput::Elements(1));
+    for (name, mode) in [
+        ("raw", KeyMode::Raw),
+        ("mnemonic", mnemonic(1)),
+        ("mnemonic-scan-20", mnemonic(20)),
+    ] {
+        let mut out = Vec::new();
+        let mut attempt = 0u64;
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                attempt += 1;
+                derive_candidates(&stream, attempt, &mode, &mut out);
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, derive);
+criterion_main!(benches);
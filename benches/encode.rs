@@ -0,0 +1,45 @@
+//! Address encoding throughput: fresh buffers per call vs one reused `EncodeScratch`.
+//!
+//! Run with `cargo bench --bench encode`.
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use secp256k1::{PublicKey, SecretKey};
+use vanity::address::{
+    encode_public_address, encode_public_address_with, AddressFormat, EncodeScratch, Network,
+    SECP256K1,
+};
+
+fn encode(c: &mut Criterion) {
+    let network = Network::Mainnet.params();
+    let public = PublicKey::from_secret_key(&SECP256K1, &SecretKey::from_slice(&[7; 32]).unwrap());
+    for (name, format, version) in [
+        ("p2pkh", AddressFormat::P2pkh, 0),
+        ("bech32-v0", AddressFormat::Bech32, 0),
+        ("bech32-v1", AddressFormat::Bech32, 1),
+    ] {
+        let mut group = c.benchmark_group(name);
+        group.throughput(Throughput::Elements(1));
+        let mut out = String::with_capacity(64);
+        group.bench_function("fresh-buffers", |b| {
+            b.iter(|| encode_public_address(&public, format, version, true, &network, &mut out))
+        });
+        let mut scratch = EncodeScratch::default();
+        group.bench_function("reused-scratch", |b| {
+            b.iter(|| {
+                encode_public_address_with(
+                    &public,
+                    format,
+                    version,
+                    true,
+                    &network,
+                    &mut scratch,
+                    &mut out,
+                )
+            })
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, encode);
+criterion_main!(benches);
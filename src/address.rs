@@ -0,0 +1,1205 @@
+//! Bitcoin address and WIF encoders shared by the search loop and the CLI.
+
+use std::cmp::Ordering;
+
+use anyhow::{anyhow, Result};
+use bech32::{self, FromBase32, ToBase32, Variant};
+use clap::ValueEnum;
+use once_cell::sync::Lazy;
+use ripemd::Ripemd160;
+use secp256k1::{All, PublicKey as SecpPublicKey, Scalar, Secp256k1, SecretKey, XOnlyPublicKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::error::VanityError;
+
+const BASE58_ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+pub static SECP256K1: Lazy<Secp256k1<All>> = Lazy::new(Secp256k1::new);
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+#[value(rename_all = "lowercase")]
+pub enum AddressFormat {
+    P2pkh,
+    #[value(name = "p2sh-p2wpkh")]
+    P2shP2wpkh,
+    Bech32,
+    // Native SegWit v0 script hash of the 1-of-1 witness script `<pubkey> OP_CHECKSIG`.
+    P2wsh,
+}
+
+impl AddressFormat {
+    /// Native SegWit formats, encoded as Bech32/Bech32m with an HRP.
+    pub fn is_bech32(self) -> bool {
+        matches!(self, AddressFormat::Bech32 | AddressFormat::P2wsh)
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+#[value(rename_all = "lowercase")]
+pub enum Network {
+    Mainnet,
+    Testnet,
+    Regtest,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NetworkParams {
+    pub p2pkh_version: u8,
+    pub p2sh_version: u8,
+    pub wif_version: u8,
+    pub hrp: String,
+}
+
+impl Network {
+    pub fn params(self) -> NetworkParams {
+        match self {
+            Network::Mainnet => NetworkParams {
+                p2pkh_version: 0x00,
+                p2sh_version: 0x05,
+                wif_version: 0x80,
+                hrp: "bc".to_string(),
+            },
+            Network::Testnet => NetworkParams {
+                p2pkh_version: 0x6f,
+                p2sh_version: 0xc4,
+                wif_version: 0xef,
+                hrp: "tb".to_string(),
+            },
+            Network::Regtest => NetworkParams {
+                p2pkh_version: 0x6f,
+                p2sh_version: 0xc4,
+                wif_version: 0xef,
+                hrp: "bcrt".to_string(),
+            },
+        }
+    }
+}
+
+impl NetworkParams {
+    /// The preset these parameters match, if any; `None` for custom `--hrp`/version bytes.
+    pub fn preset(&self) -> Option<Network> {
+        [Network::Mainnet, Network::Testnet, Network::Regtest]
+            .into_iter()
+            .find(|network| network.params() == *self)
+    }
+
+    /// `Mainnet`/`Testnet`/`Regtest`, or `Custom` for overridden parameters.
+    pub fn name(&self) -> String {
+        self.preset()
+            .map_or_else(|| "Custom".to_string(), |network| format!("{:?}", network))
+    }
+}
+
+/// BIP-173 human-readable part: 1-83 printable ASCII characters, lowercase only.
+pub fn validate_hrp(hrp: &str) -> Result<()> {
+    if hrp.is_empty() || hrp.len() > 83 {
+        return Err(anyhow!("HRP must be 1-83 characters, got {}", hrp.len()));
+    }
+    if let Some(c) = hrp
+        .chars()
+        .find(|c| !matches!(c, '!'..='~') || c.is_ascii_uppercase())
+    {
+        return Err(anyhow!(
+            "HRP '{}' contains '{}': use lowercase printable ASCII",
+            hrp,
+            c
+        ));
+    }
+    Ok(())
+}
+
+pub fn encode_address(
+    secret: &SecretKey,
+    format: AddressFormat,
+    witness_version: u8,
+    compressed: bool,
+    network: &NetworkParams,
+    out: &mut String,
+) -> Result<(), VanityError> {
+    let public = SecpPublicKey::from_secret_key(&SECP256K1, secret);
+    encode_public_address(&public, format, witness_version, compressed, network, out)
+}
+
+/// Reusable encoder buffers. Keep one per worker and pass it to `encode_public_address_with`
+/// so the steady-state hot loop never touches the heap.
+#[derive(Default)]
+pub struct EncodeScratch {
+    limbs: Vec<u32>,
+    data: Vec<bech32::u5>,
+}
+
+pub fn encode_public_address(
+    public: &SecpPublicKey,
+    format: AddressFormat,
+    witness_version: u8,
+    compressed: bool,
+    network: &NetworkParams,
+    out: &mut String,
+) -> Result<(), VanityError> {
+    encode_public_address_with(
+        public,
+        format,
+        witness_version,
+        compressed,
+        network,
+        &mut EncodeScratch::default(),
+        out,
+    )
+}
+
+pub fn encode_public_address_with(
+    public: &SecpPublicKey,
+    format: AddressFormat,
+    witness_version: u8,
+    compressed: bool,
+    network: &NetworkParams,
+    scratch: &mut EncodeScratch,
+    out: &mut String,
+) -> Result<(), VanityError> {
+    let mut payload = [0u8; 32];
+    let len = address_payload(public, format, witness_version, compressed, &mut payload)?;
+    encode_payload(
+        &payload[..len],
+        format,
+        witness_version,
+        network,
+        scratch,
+        out,
+    )
+}
+
+/// Bytes of the payload an address of `format` encodes: 20 for P2PKH, P2SH-P2WPKH and
+/// Bech32 v0, 32 for P2WSH and Bech32 v1+.
+pub fn payload_len(format: AddressFormat, witness_version: u8) -> usize {
+    match format {
+        AddressFormat::P2pkh | AddressFormat::P2shP2wpkh => 20,
+        AddressFormat::Bech32 if witness_version == 0 => 20,
+        AddressFormat::Bech32 | AddressFormat::P2wsh => 32,
+    }
+}
+
+/// The hash or witness program an address of `format` commits `public` to, written to the
+/// front of `out`: the hash160 of the key (P2PKH, Bech32 v0), of its redeem script
+/// (P2SH-P2WPKH), the SHA-256 of its witness script (P2WSH) or the x-only output key
+/// (Bech32 v1+). Returns its length; `encode_payload` turns it into the address.
+pub fn address_payload(
+    public: &SecpPublicKey,
+    format: AddressFormat,
+    witness_version: u8,
+    compressed: bool,
+    out: &mut [u8; 32],
+) -> Result<usize, VanityError> {
+    if !compressed && format != AddressFormat::P2pkh {
+        return Err(VanityError::UncompressedKey(format));
+    }
+    match format {
+        AddressFormat::P2pkh => {
+            let rip = if compressed {
+                Ripemd160::digest(Sha256::digest(public.serialize()))
+            } else {
+                Ripemd160::digest(Sha256::digest(public.serialize_uncompressed()))
+            };
+            out[..20].copy_from_slice(&rip);
+            Ok(20)
+        }
+        AddressFormat::P2shP2wpkh => {
+            let key_hash = Ripemd160::digest(Sha256::digest(public.serialize()));
+            // redeemScript = OP_0 PUSH20 <hash160(pubkey)>
+            let mut redeem_script = [0u8; 22];
+            redeem_script[0] = 0x00;
+            redeem_script[1] = 0x14;
+            redeem_script[2..].copy_from_slice(&key_hash);
+            out[..20].copy_from_slice(&Ripemd160::digest(Sha256::digest(redeem_script)));
+            Ok(20)
+        }
+        AddressFormat::P2wsh => {
+            if witness_version != 0 {
+                return Err(VanityError::UnsupportedWitnessVersion {
+                    format,
+                    version: witness_version,
+                });
+            }
+            out.copy_from_slice(&Sha256::digest(p2wsh_witness_script(public)));
+            Ok(32)
+        }
+        AddressFormat::Bech32 => match witness_version {
+            0 => {
+                let sha = Sha256::digest(public.serialize());
+                out[..20].copy_from_slice(&Ripemd160::digest(sha));
+                Ok(20)
+            }
+            1 => {
+                let (internal, _) = public.x_only_public_key();
+                *out = taproot_output_key(&internal)?.serialize();
+                Ok(32)
+            }
+            // No standard program is defined for v2..=16 yet; commit to the raw x-only key
+            // as a 32-byte program so experimental formats are at least well-formed.
+            2..=16 => {
+                *out = public.x_only_public_key().0.serialize();
+                Ok(32)
+            }
+            other => Err(VanityError::UnsupportedWitnessVersion {
+                format,
+                version: other,
+            }),
+        },
+    }
+}
+
+/// The `format` address of a payload from `address_payload`.
+pub fn encode_payload(
+    payload: &[u8],
+    format: AddressFormat,
+    witness_version: u8,
+    network: &NetworkParams,
+    scratch: &mut EncodeScratch,
+    out: &mut String,
+) -> Result<(), VanityError> {
+    match format {
+        AddressFormat::P2pkh => {
+            base58check_address(network.p2pkh_version, payload, &mut scratch.limbs, out);
+            Ok(())
+        }
+        AddressFormat::P2shP2wpkh => {
+            base58check_address(network.p2sh_version, payload, &mut scratch.limbs, out);
+            Ok(())
+        }
+        AddressFormat::P2wsh => encode_witness_program(network, 0, payload, scratch, out),
+        AddressFormat::Bech32 => {
+            encode_witness_program(network, witness_version, payload, scratch, out)
+        }
+    }
+}
+
+/// The P2WSH witness script committing to `public`: `PUSH33 <pubkey> OP_CHECKSIG`.
+pub fn p2wsh_witness_script(public: &SecpPublicKey) -> [u8; 35] {
+    let mut script = [0u8; 35];
+    script[0] = 0x21;
+    script[1..34].copy_from_slice(&public.serialize());
+    script[34] = 0xac;
+    script
+}
+
+fn encode_witness_program(
+    network: &NetworkParams,
+    witness_version: u8,
+    program: &[u8],
+    scratch: &mut EncodeScratch,
+    out: &mut String,
+) -> Result<(), VanityError> {
+    let variant = if witness_version == 0 {
+        Variant::Bech32
+    } else {
+        Variant::Bech32m
+    };
+    let version_u5 = bech32::u5::try_from_u8(witness_version).map_err(|_| {
+        VanityError::UnsupportedWitnessVersion {
+            format: AddressFormat::Bech32,
+            version: witness_version,
+        }
+    })?;
+    let data = &mut scratch.data;
+    data.clear();
+    data.push(version_u5);
+    program
+        .write_base32(data)
+        .map_err(|_| VanityError::Encoding("base32 conversion failed".into()))?;
+    out.clear();
+    bech32::encode_to_fmt(out, &network.hrp, &data[..], variant)
+        .map_err(|e| VanityError::Encoding(format!("bech32 encode failed: {e}")))?
+        .map_err(|e| VanityError::Encoding(format!("bech32 encode failed: {e}")))?;
+    Ok(())
+}
+
+pub const BECH32_CHARSET: &str = "qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+/// HRP, separator and witness-version character shared by every Bech32 address of this
+/// network/version, e.g. `bc1q` for mainnet v0 or `tb1p` for testnet v1.
+pub fn bech32_fixed_prefix(network: &NetworkParams, witness_version: u8) -> String {
+    let version = BECH32_CHARSET.as_bytes()[usize::from(witness_version & 0x1f)] as char;
+    format!("{}1{}", network.hrp, version)
+}
+
+/// Number of leading address characters pinned by the version byte or HRP.
+pub fn fixed_prefix_len(format: AddressFormat, network: &NetworkParams) -> usize {
+    match format {
+        // Version byte pins the leading Base58 character (`1`, `3`, `m`/`n`, `2`).
+        AddressFormat::P2pkh | AddressFormat::P2shP2wpkh => 1,
+        // HRP, the `1` separator, and the witness version character.
+        AddressFormat::Bech32 | AddressFormat::P2wsh => network.hrp.len() + 2,
+    }
+}
+
+/// Upper bound on the length of any address of this format/network, or of its WIFs with
+/// `wif`. Bech32 lengths are exact; Base58 ones can be a character shorter for small hashes.
+pub fn max_encoded_len(
+    format: AddressFormat,
+    witness_version: u8,
+    compressed: bool,
+    network: &NetworkParams,
+    wif: bool,
+) -> usize {
+    // The largest payload after the version byte has the longest encoding.
+    let base58_max = |version: u8, len: usize| {
+        let mut payload = vec![0xff; len];
+        payload[0] = version;
+        let mut out = String::new();
+        encode_base58(&payload, &mut out);
+        out.len()
+    };
+    if wif {
+        return base58_max(network.wif_version, if compressed { 38 } else { 37 });
+    }
+    match format {
+        AddressFormat::P2pkh => base58_max(network.p2pkh_version, 25),
+        AddressFormat::P2shP2wpkh => base58_max(network.p2sh_version, 25),
+        AddressFormat::Bech32 | AddressFormat::P2wsh => {
+            let program_bits: usize = match format {
+                AddressFormat::Bech32 if witness_version == 0 => 160,
+                _ => 256,
+            };
+            // HRP, separator, version character, program in 5-bit groups, checksum.
+            network.hrp.len() + 2 + program_bits.div_ceil(5) + 6
+        }
+    }
+}
+
+// BIP-341 key-path-only output: Q = P + H_TapTweak(P)*G, where P is the even-y lift of the
+// x-only internal key. The reported private key stays the internal key (BIP-86 style).
+pub fn taproot_output_key(internal: &XOnlyPublicKey) -> Result<XOnlyPublicKey, VanityError> {
+    let tweak = tagged_hash("TapTweak", &internal.serialize());
+    let scalar = Scalar::from_be_bytes(tweak)
+        .map_err(|_| VanityError::KeyDerivation("TapTweak out of range".into()))?;
+    let (output, _parity) = internal
+        .add_tweak(&SECP256K1, &scalar)
+        .map_err(|e| VanityError::KeyDerivation(format!("Taproot tweak failed: {e}")))?;
+    Ok(output)
+}
+
+fn tagged_hash(tag: &str, msg: &[u8]) -> [u8; 32] {
+    let tag_hash = Sha256::digest(tag.as_bytes());
+    let mut hasher = Sha256::new();
+    hasher.update(tag_hash);
+    hasher.update(tag_hash);
+    hasher.update(msg);
+    let mut out = [0u8; 32];
+    out.copy_from_slice(&hasher.finalize());
+    out
+}
+
+fn base58check_address(version: u8, hash: &[u8], limbs: &mut Vec<u32>, out: &mut String) {
+    let mut payload = [0u8; 25];
+    payload[0] = version;
+    payload[1..21].copy_from_slice(hash);
+    let checksum = double_sha256(&payload[..21]);
+    payload[21..].copy_from_slice(&checksum[..4]);
+    encode_base58_with(&payload, limbs, out);
+}
+
+pub fn wif_from_secret(secret: &SecretKey, network: &NetworkParams, compressed: bool) -> String {
+    let mut out = String::with_capacity(52);
+    encode_wif_with(
+        secret,
+        network,
+        compressed,
+        &mut EncodeScratch::default(),
+        &mut out,
+    );
+    out
+}
+
+/// `wif_from_secret` into a caller-owned buffer, for matching WIFs in the search loop.
+pub fn encode_wif_with(
+    secret: &SecretKey,
+    network: &NetworkParams,
+    compressed: bool,
+    scratch: &mut EncodeScratch,
+    out: &mut String,
+) {
+    let mut payload = [0u8; 38];
+    payload[0] = network.wif_version;
+    payload[1..33].copy_from_slice(&secret.secret_bytes());
+    let len = if compressed {
+        payload[33] = 0x01;
+        34
+    } else {
+        33
+    };
+    let checksum = double_sha256(&payload[..len]);
+    payload[len..len + 4].copy_from_slice(&checksum[..4]);
+    encode_base58_with(&payload[..len + 4], &mut scratch.limbs, out);
+}
+
+/// Inverse of `wif_from_secret`: the version byte, secret and compression flag.
+pub fn decode_wif(wif: &str) -> Result<(u8, SecretKey, bool)> {
+    let payload = decode_base58check(wif)?;
+    let compressed = match payload.len() {
+        33 => false,
+        34 if payload[33] == 0x01 => true,
+        len => return Err(anyhow!("WIF payload has unexpected length {}", len)),
+    };
+    let secret =
+        SecretKey::from_slice(&payload[1..33]).map_err(|_| anyhow!("WIF secret out of range"))?;
+    Ok((payload[0], secret, compressed))
+}
+
+/// Base58 payload with its 4-byte checksum verified and stripped. Decodes with `bs58`, so
+/// it is independent of `encode_base58`.
+pub fn decode_base58check(value: &str) -> Result<Vec<u8>> {
+    let mut payload = bs58::decode(value)
+        .into_vec()
+        .map_err(|e| anyhow!("Invalid Base58 '{}': {e}", value))?;
+    let Some(split) = payload.len().checked_sub(4) else {
+        return Err(anyhow!("Base58 payload too short for a checksum"));
+    };
+    if double_sha256(&payload[..split])[..4] != payload[split..] {
+        return Err(anyhow!("Base58 checksum mismatch in '{}'", value));
+    }
+    payload.truncate(split);
+    Ok(payload)
+}
+
+/// Checks that `address` is a well-formed `format` address on `network`: its checksum,
+/// version byte or HRP, witness version and program length. Says nothing about its key.
+pub fn check_address_format(
+    address: &str,
+    format: AddressFormat,
+    witness_version: u8,
+    network: &NetworkParams,
+) -> Result<()> {
+    if !format.is_bech32() {
+        let payload = decode_base58check(address)?;
+        let version = match format {
+            AddressFormat::P2pkh => network.p2pkh_version,
+            _ => network.p2sh_version,
+        };
+        if payload.len() != 21 || payload[0] != version {
+            return Err(anyhow!(
+                "'{}' is not a {:?} address (expected version byte {:#04x})",
+                address,
+                format,
+                version
+            ));
+        }
+        return Ok(());
+    }
+    let (hrp, data, variant) =
+        bech32::decode(address).map_err(|e| anyhow!("Invalid Bech32 '{}': {e}", address))?;
+    let expected_variant = if witness_version == 0 {
+        Variant::Bech32
+    } else {
+        Variant::Bech32m
+    };
+    let program_len = match format {
+        AddressFormat::Bech32 if witness_version == 0 => 20,
+        _ => 32,
+    };
+    let program = data
+        .get(1..)
+        .map(Vec::<u8>::from_base32)
+        .transpose()
+        .map_err(|e| anyhow!("Invalid witness program in '{}': {e}", address))?;
+    if hrp != network.hrp
+        || variant != expected_variant
+        || data.first().map(|v| v.to_u8()) != Some(witness_version)
+        || program.map(|p| p.len()) != Some(program_len)
+    {
+        return Err(anyhow!(
+            "'{}' is not a {:?} v{} address with HRP {}",
+            address,
+            format,
+            witness_version,
+            network.hrp
+        ));
+    }
+    Ok(())
+}
+
+/// The smallest and largest Base58 encodings of a `len`-byte payload under one version
+/// byte. Payloads sort like their encodings, so these bound every prefix that can occur.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Base58Range {
+    pub low: String,
+    pub high: String,
+    len: usize,
+}
+
+impl Base58Range {
+    /// Characters an encoding in the range can start with.
+    pub fn leading_chars(&self) -> String {
+        leading_chars(&self.low, &self.high)
+    }
+
+    /// Fraction of the payloads in the range whose encodings start with `prefix`, treating
+    /// them as uniform between the bounds; 0.0 when the prefix lies outside them. With
+    /// `ignore_case` every spelling of a folded letter counts.
+    pub fn prefix_fraction(&self, prefix: &str, ignore_case: bool) -> f64 {
+        if !ignore_case {
+            return self.fraction(prefix);
+        }
+        // Spellings are summed exactly for the leading characters, which the version byte
+        // constrains; past a thousand or so the rest is scored as uniform.
+        let mut spellings = vec![String::new()];
+        let mut rest = 1.0;
+        for c in prefix.chars() {
+            let mut cases = vec![c.to_ascii_lowercase(), c.to_ascii_uppercase()];
+            cases.dedup();
+            cases.retain(|c| BASE58_ALPHABET.contains(&(*c as u8)));
+            if rest < 1.0 || spellings.len() * cases.len() > 1024 {
+                rest *= cases.len() as f64 / 58.0;
+                continue;
+            }
+            spellings = spellings
+                .iter()
+                .flat_map(|s| cases.iter().map(move |c| format!("{s}{c}")))
+                .collect();
+        }
+        spellings.iter().map(|s| self.fraction(s)).sum::<f64>() * rest
+    }
+
+    fn fraction(&self, prefix: &str) -> f64 {
+        let (low, high) = (base58_digits(&self.low), base58_digits(&self.high));
+        // Leading `1`s are zero bytes: a prefix with `ones` of them needs exactly that many
+        // (at least, if nothing follows), which clips the payload range.
+        let ones = prefix.bytes().take_while(|&b| b == b'1').count();
+        if ones >= self.len {
+            return 0.0;
+        }
+        let bound = |head: &[u8], fill: u8| {
+            let mut payload = vec![0u8; self.len];
+            payload[ones..ones + head.len()].copy_from_slice(head);
+            payload[ones + head.len()..].fill(fill);
+            let mut out = String::new();
+            encode_base58(&payload, &mut out);
+            base58_digits(&out)
+        };
+        let rest = base58_digits(&prefix[ones..]);
+        let mut from = low.clone();
+        if !rest.is_empty() {
+            from = std::cmp::max_by(from, bound(&[1], 0), |a, b| digits_cmp(a, b));
+        }
+        let to = std::cmp::min_by(high.clone(), bound(&[], 0xff), |a, b| digits_cmp(a, b));
+        digits_in_range(&rest, &from, &to) / digits_in_range(&[], &low, &high)
+    }
+}
+
+/// The range of WIFs under `network`'s version byte, from the smallest key to the largest.
+pub fn wif_range(network: &NetworkParams, compressed: bool) -> Base58Range {
+    let encode = |bytes: [u8; 32]| {
+        let secret = SecretKey::from_slice(&bytes).expect("bound is a valid secret");
+        wif_from_secret(&secret, network, compressed)
+    };
+    let mut one = [0u8; 32];
+    one[31] = 1;
+    let mut top = secp256k1::constants::CURVE_ORDER;
+    top[31] -= 1;
+    Base58Range {
+        low: encode(one),
+        high: encode(top),
+        len: 1 + 32 + usize::from(compressed) + 4,
+    }
+}
+
+/// The range of Base58 (P2PKH or P2SH-P2WPKH) addresses of `network`; `None` for Bech32
+/// formats, whose fixed part is `bech32_fixed_prefix`.
+pub fn address_range(format: AddressFormat, network: &NetworkParams) -> Option<Base58Range> {
+    let version = match format {
+        AddressFormat::P2pkh => network.p2pkh_version,
+        AddressFormat::P2shP2wpkh => network.p2sh_version,
+        AddressFormat::Bech32 | AddressFormat::P2wsh => return None,
+    };
+    // Payloads are the version, hash and checksum.
+    let encode = |fill: u8| {
+        let mut payload = [fill; 25];
+        payload[0] = version;
+        let mut out = String::new();
+        encode_base58(&payload, &mut out);
+        out
+    };
+    Some(Base58Range {
+        low: encode(0),
+        high: encode(0xff),
+        len: 25,
+    })
+}
+
+/// Characters a WIF can start with; `--match-wif` prefixes must begin with one of them.
+pub fn wif_leading_chars(network: &NetworkParams, compressed: bool) -> String {
+    wif_range(network, compressed).leading_chars()
+}
+
+// The first characters of Base58 strings running from `low` to `high`, two encodings of
+// the smallest and largest payload.
+fn leading_chars(low: &str, high: &str) -> String {
+    let alphabet = BASE58_ALPHABET;
+    let position = |s: &str| alphabet.iter().position(|&c| c == s.as_bytes()[0]).unwrap();
+    let (from, to) = (position(low), position(high));
+    // `1` stands for a zero leading byte, which then starts every payload.
+    if from == 0 {
+        return "1".into();
+    }
+    let chars = if low.len() == high.len() {
+        alphabet[from..=to].to_vec()
+    } else {
+        // The range crosses a digit boundary: the shorter encodings run up to `z`, and the
+        // longer ones start over at `2` (`1` only stands for zero bytes).
+        (0..alphabet.len())
+            .filter(|&i| i >= from || (1..=to).contains(&i))
+            .map(|i| alphabet[i])
+            .collect()
+    };
+    String::from_utf8(chars).expect("base58 is ASCII")
+}
+
+/// Characters a Base58 (P2PKH or P2SH-P2WPKH) address of `network` can start with; `None`
+/// for Bech32 formats, whose fixed part is `bech32_fixed_prefix`.
+pub fn address_leading_chars(format: AddressFormat, network: &NetworkParams) -> Option<String> {
+    address_range(format, network).map(|range| range.leading_chars())
+}
+
+// Base58 digit values of `s` with the leading zeros (`1`s) dropped, so longer means larger.
+fn base58_digits(s: &str) -> Vec<u8> {
+    s.bytes()
+        .map(|c| BASE58_ALPHABET.iter().position(|&a| a == c).unwrap_or(0) as u8)
+        .skip_while(|&d| d == 0)
+        .collect()
+}
+
+fn digits_cmp(a: &[u8], b: &[u8]) -> Ordering {
+    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+}
+
+// How many numbers from `from` to `to` (digits as from `base58_digits`) start with the
+// digits `prefix`. Each length is counted on its own against the bounds' leading digits and
+// their tails, so no large values are ever subtracted.
+fn digits_in_range(prefix: &[u8], from: &[u8], to: &[u8]) -> f64 {
+    if digits_cmp(from, to) == Ordering::Greater {
+        return 0.0;
+    }
+    let value = |d: &[u8]| d.iter().fold(0.0, |acc, &d| acc * 58.0 + f64::from(d));
+    let k = prefix.len();
+    (from.len().max(k)..=to.len())
+        .map(|n| {
+            let mut smallest = vec![0u8; n];
+            if let Some(first) = smallest.first_mut() {
+                *first = 1;
+            }
+            let largest = vec![57u8; n];
+            let low = if n == from.len() { from } else { &smallest };
+            let high = if n == to.len() { to } else { &largest };
+            match (prefix.cmp(&low[..k]), prefix.cmp(&high[..k])) {
+                (Ordering::Less, _) | (_, Ordering::Greater) => 0.0,
+                (Ordering::Equal, Ordering::Equal) => value(&high[k..]) - value(&low[k..]) + 1.0,
+                // 58^(n-k) less the tail below `low`, written without the subtraction.
+                (Ordering::Equal, _) => {
+                    let up: Vec<u8> = low[k..].iter().map(|d| 57 - d).collect();
+                    value(&up) + 1.0
+                }
+                (_, Ordering::Equal) => value(&high[k..]) + 1.0,
+                _ => 58f64.powi((n - k) as i32),
+            }
+        })
+        .sum()
+}
+
+pub(crate) fn double_sha256(data: &[u8]) -> [u8; 32] {
+    let first = Sha256::digest(data);
+    let second = Sha256::digest(first);
+    let mut out = [0u8; 32];
+    out.copy_from_slice(&second);
+    out
+}
+
+pub fn encode_base58(input: &[u8], out: &mut String) {
+    encode_base58_with(input, &mut Vec::new(), out);
+}
+
+// Limbs of five Base58 digits (58^5 < 2^30), with the input folded in 32 bits at a time:
+// `limb * 2^32 + carry` stays below 2^63 and each step does a fifth of the divisions.
+const BASE58_LIMB: u64 = 58u64.pow(5);
+
+/// `encode_base58` with a caller-owned limb buffer, reused across calls.
+pub fn encode_base58_with(input: &[u8], limbs: &mut Vec<u32>, out: &mut String) {
+    let zeros = input.iter().take_while(|&&b| b == 0).count();
+    let rest = &input[zeros..];
+    // Least significant limb first. The short chunk, if any, is the most significant one.
+    limbs.clear();
+    let head = rest.len() % 4;
+    let chunks = std::iter::once(&rest[..head])
+        .filter(|chunk| !chunk.is_empty())
+        .chain(rest[head..].chunks_exact(4));
+    for chunk in chunks {
+        let shift = 8 * chunk.len() as u32;
+        let mut carry = chunk.iter().fold(0u64, |acc, &b| (acc << 8) | u64::from(b));
+        for limb in limbs.iter_mut() {
+            let value = (u64::from(*limb) << shift) + carry;
+            *limb = (value % BASE58_LIMB) as u32;
+            carry = value / BASE58_LIMB;
+        }
+        while carry > 0 {
+            limbs.push((carry % BASE58_LIMB) as u32);
+            carry /= BASE58_LIMB;
+        }
+    }
+
+    out.clear();
+    out.reserve(zeros + limbs.len() * 5);
+    for _ in 0..zeros {
+        out.push('1');
+    }
+    for (at, &limb) in limbs.iter().rev().enumerate() {
+        let mut digits = [0u8; 5];
+        let mut value = limb;
+        for digit in digits.iter_mut().rev() {
+            *digit = (value % 58) as u8;
+            value /= 58;
+        }
+        // Only the top limb has leading zero digits to drop (it is never zero itself).
+        let skip = if at == 0 {
+            digits.iter().take_while(|&&d| d == 0).count()
+        } else {
+            0
+        };
+        for &digit in &digits[skip..] {
+            out.push(BASE58_ALPHABET[digit as usize] as char);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use bip32::{DerivationPath, XPrv};
+    use bip39::{Language, Mnemonic};
+    use proptest::prelude::*;
+
+    use super::*;
+
+    fn address_for(secret: &SecretKey, format: AddressFormat, network: Network) -> String {
+        let mut out = String::new();
+        encode_address(secret, format, 0, true, &network.params(), &mut out).unwrap();
+        out
+    }
+
+    fn secret_one() -> SecretKey {
+        let mut bytes = [0u8; 32];
+        bytes[31] = 1;
+        SecretKey::from_slice(&bytes).unwrap()
+    }
+
+    // Private key 1 (generator point G), cross-checked against Bitcoin Core / bitcoinjs.
+    #[test]
+    fn key_one_matches_known_mainnet_encodings() {
+        let secret = secret_one();
+        assert_eq!(
+            address_for(&secret, AddressFormat::P2pkh, Network::Mainnet),
+            "1BgGZ9tcN4rm9KBzDn7KprQz87SZ26SAMH"
+        );
+        assert_eq!(
+            address_for(&secret, AddressFormat::P2shP2wpkh, Network::Mainnet),
+            "3JvL6Ymt8MVWiCNHC7oWU6nLeHNJKLZGLN"
+        );
+        assert_eq!(
+            address_for(&secret, AddressFormat::Bech32, Network::Mainnet),
+            "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4"
+        );
+        // BIP-173's P2WSH vector is exactly key 1's `<pubkey> OP_CHECKSIG` script.
+        assert_eq!(
+            address_for(&secret, AddressFormat::P2wsh, Network::Mainnet),
+            "bc1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3qccfmv3"
+        );
+        assert_eq!(
+            address_for(&secret, AddressFormat::P2wsh, Network::Testnet),
+            "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7"
+        );
+        assert_eq!(
+            wif_from_secret(&secret, &Network::Mainnet.params(), true),
+            "KwDiBf89QgGbjEhKnhXJuH7LrciVrZi3qYjgd9M7rFU73sVHnoWn"
+        );
+    }
+
+    // P2PKH and Bech32 v0 share the key's hash160; P2WSH's program is its script's SHA-256.
+    #[test]
+    fn key_one_payloads_are_what_the_addresses_encode() {
+        let public = SecpPublicKey::from_secret_key(&SECP256K1, &secret_one());
+        let payload_hex = |format, witness_version| {
+            let mut payload = [0u8; 32];
+            let len =
+                address_payload(&public, format, witness_version, true, &mut payload).unwrap();
+            assert_eq!(len, payload_len(format, witness_version));
+            hex::encode(&payload[..len])
+        };
+        let hash160 = "751e76e8199196d454941c45d1b3a323f1433bd6";
+        assert_eq!(payload_hex(AddressFormat::P2pkh, 0), hash160);
+        assert_eq!(payload_hex(AddressFormat::Bech32, 0), hash160);
+        assert_eq!(
+            payload_hex(AddressFormat::P2wsh, 0),
+            "1863143c14c5166804bd19203356da136c985678cd4d27a1b8c6329604903262"
+        );
+        assert_eq!(payload_hex(AddressFormat::Bech32, 1).len(), 64);
+    }
+
+    #[test]
+    fn key_one_matches_known_uncompressed_encodings() {
+        let secret = secret_one();
+        let mut address = String::new();
+        encode_address(
+            &secret,
+            AddressFormat::P2pkh,
+            0,
+            false,
+            &Network::Mainnet.params(),
+            &mut address,
+        )
+        .unwrap();
+        assert_eq!(address, "1EHNa6Q4Jz2uvNExL497mE43ikXhwF6kZm");
+        assert_eq!(
+            wif_from_secret(&secret, &Network::Mainnet.params(), false),
+            "5HpHagT65TZzG1PH3CSu63k8DbpvD8s5ip4nEB3kEsreAnchuDf"
+        );
+    }
+
+    #[test]
+    fn key_one_matches_known_testnet_encodings() {
+        let secret = secret_one();
+        assert_eq!(
+            address_for(&secret, AddressFormat::P2pkh, Network::Testnet),
+            "mrCDrCybB6J1vRfbwM5hemdJz73FwDBC8r"
+        );
+        assert_eq!(
+            address_for(&secret, AddressFormat::Bech32, Network::Testnet),
+            "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx"
+        );
+        assert_eq!(
+            wif_from_secret(&secret, &Network::Testnet.params(), true),
+            "cMahea7zqjxrtgAbB7LSGbcQUr1uX1ojuat9jZodMN87JcbXMTcA"
+        );
+    }
+
+    #[test]
+    fn high_witness_versions_use_bech32m() {
+        let secret = secret_one();
+        for version in 2..=16u8 {
+            let mut address = String::new();
+            encode_address(
+                &secret,
+                AddressFormat::Bech32,
+                version,
+                true,
+                &Network::Mainnet.params(),
+                &mut address,
+            )
+            .unwrap();
+            let (hrp, data, variant) = bech32::decode(&address).unwrap();
+            assert_eq!(hrp, "bc");
+            assert_eq!(variant, Variant::Bech32m);
+            assert_eq!(data[0].to_u8(), version);
+        }
+    }
+
+    #[test]
+    fn encoding_failures_are_typed() {
+        let secret = secret_one();
+        let encode = |format, version, compressed| {
+            let mut out = String::new();
+            let network = Network::Mainnet.params();
+            encode_address(&secret, format, version, compressed, &network, &mut out)
+        };
+        assert!(matches!(
+            encode(AddressFormat::Bech32, 17, true),
+            Err(VanityError::UnsupportedWitnessVersion { version: 17, .. })
+        ));
+        assert!(matches!(
+            encode(AddressFormat::P2wsh, 1, true),
+            Err(VanityError::UnsupportedWitnessVersion {
+                format: AddressFormat::P2wsh,
+                version: 1
+            })
+        ));
+        assert!(matches!(
+            encode(AddressFormat::Bech32, 0, false),
+            Err(VanityError::UncompressedKey(AddressFormat::Bech32))
+        ));
+    }
+
+    // Each leading zero byte must become a leading '1'; the all-zero hash160 is the
+    // well-known mainnet burn address.
+    #[test]
+    fn base58_keeps_leading_zero_bytes() {
+        let mut out = String::new();
+        encode_base58(&[0, 0, 0, 1], &mut out);
+        assert_eq!(out, "1112");
+
+        out.clear();
+        base58check_address(0x00, &[0u8; 20], &mut Vec::new(), &mut out);
+        assert_eq!(out, "1111111111111111111114oLvT2");
+    }
+
+    // A run of leading zero bytes (each one a leading '1') before arbitrary bytes, covering
+    // every length up to a WIF's and beyond, empty and all-zero inputs included.
+    fn base58_input() -> impl Strategy<Value = Vec<u8>> {
+        (0usize..=8, proptest::collection::vec(any::<u8>(), 0..=48)).prop_map(|(zeros, rest)| {
+            let mut input = vec![0u8; zeros];
+            input.extend(rest);
+            input
+        })
+    }
+
+    proptest! {
+        // The limb encoder against `bs58`; proptest shrinks any failure to a minimal input.
+        #[test]
+        fn base58_matches_the_bs58_reference(input in base58_input()) {
+            let (mut limbs, mut out) = (Vec::new(), String::new());
+            encode_base58_with(&input, &mut limbs, &mut out);
+            prop_assert_eq!(out, bs58::encode(&input).into_string());
+        }
+    }
+
+    // A scratch left dirty by one format must not leak into the next encoding.
+    #[test]
+    fn reused_scratch_matches_fresh_encoding() {
+        let network = Network::Mainnet.params();
+        let public =
+            SecpPublicKey::from_secret_key(&SECP256K1, &SecretKey::from_slice(&[7; 32]).unwrap());
+        let mut scratch = EncodeScratch::default();
+        for (format, version) in [
+            (AddressFormat::Bech32, 1),
+            (AddressFormat::P2pkh, 0),
+            (AddressFormat::Bech32, 0),
+            (AddressFormat::P2shP2wpkh, 0),
+        ] {
+            let (mut fresh, mut reused) = (String::new(), String::from("stale"));
+            encode_public_address(&public, format, version, true, &network, &mut fresh).unwrap();
+            encode_public_address_with(
+                &public,
+                format,
+                version,
+                true,
+                &network,
+                &mut scratch,
+                &mut reused,
+            )
+            .unwrap();
+            assert_eq!(fresh, reused);
+        }
+    }
+
+    // BIP-341 wallet-test-vectors.json, scriptPubKey[0] (no script tree).
+    #[test]
+    fn taproot_tweak_matches_bip341_vector() {
+        let internal = XOnlyPublicKey::from_slice(
+            &hex::decode("d6889cb081036e0faefa3a35157ad71086b123b2b144b649798b494c300a961d")
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            hex::encode(tagged_hash("TapTweak", &internal.serialize())),
+            "b86e7be8f39bab32a6f2c0443abbc210f0edac0e2c53d501b36b64437d9c6c70"
+        );
+        assert_eq!(
+            hex::encode(taproot_output_key(&internal).unwrap().serialize()),
+            "53a1f6e454df1aa2776a2814a721372d6258050de330b3c6d10ee8f4e0dda343"
+        );
+    }
+
+    // BIP-86 first receive address for the "abandon ... about" mnemonic.
+    #[test]
+    fn taproot_address_matches_bip86_vector() {
+        let mnemonic = Mnemonic::parse_in(
+            Language::English,
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
+        )
+        .unwrap();
+        let path = DerivationPath::from_str("m/86'/0'/0'/0/0").unwrap();
+        let child = XPrv::derive_from_path(mnemonic.to_seed(""), &path).unwrap();
+        let secret = SecretKey::from_slice(&child.private_key().to_bytes()).unwrap();
+        let mut address = String::new();
+        encode_address(
+            &secret,
+            AddressFormat::Bech32,
+            1,
+            true,
+            &Network::Mainnet.params(),
+            &mut address,
+        )
+        .unwrap();
+        assert_eq!(
+            address,
+            "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr"
+        );
+    }
+
+    #[test]
+    fn address_leading_chars_follow_the_version_byte() {
+        let mainnet = Network::Mainnet.params();
+        let testnet = Network::Testnet.params();
+        let leading =
+            |format, network: &NetworkParams| address_leading_chars(format, network).unwrap();
+        assert_eq!(leading(AddressFormat::P2pkh, &mainnet), "1");
+        assert_eq!(leading(AddressFormat::P2shP2wpkh, &mainnet), "3");
+        assert_eq!(leading(AddressFormat::P2pkh, &testnet), "mn");
+        assert_eq!(leading(AddressFormat::P2shP2wpkh, &testnet), "2");
+        assert_eq!(address_leading_chars(AddressFormat::Bech32, &mainnet), None);
+        // Dogecoin's 0x1e P2PKH byte.
+        let dogecoin = NetworkParams {
+            p2pkh_version: 0x1e,
+            ..mainnet.clone()
+        };
+        assert_eq!(leading(AddressFormat::P2pkh, &dogecoin), "D");
+        // Version 1 straddles a length boundary, so nearly everything is possible.
+        let one = NetworkParams {
+            p2pkh_version: 1,
+            ..mainnet
+        };
+        let chars = leading(AddressFormat::P2pkh, &one);
+        assert!(chars.len() > 2 && !chars.starts_with('1'));
+    }
+
+    #[test]
+    fn wif_leading_chars_follow_the_version_byte() {
+        let mainnet = Network::Mainnet.params();
+        let testnet = Network::Testnet.params();
+        assert_eq!(wif_leading_chars(&mainnet, true), "KL");
+        assert_eq!(wif_leading_chars(&mainnet, false), "5");
+        assert_eq!(wif_leading_chars(&testnet, true), "c");
+        assert_eq!(wif_leading_chars(&testnet, false), "9");
+        // Litecoin's 0xb0 WIF byte.
+        let litecoin = NetworkParams {
+            wif_version: 0xb0,
+            ..mainnet.clone()
+        };
+        assert_eq!(wif_leading_chars(&litecoin, true), "T");
+        // These version bytes straddle a length boundary: the shorter WIFs run up to `z`,
+        // the longer ones start over at `2`.
+        for (version, compressed, expected) in [(0x06, true, "2tuvwxyz"), (0x1d, false, "2yz")] {
+            let network = NetworkParams {
+                wif_version: version,
+                ..mainnet.clone()
+            };
+            let chars = wif_leading_chars(&network, compressed);
+            assert_eq!(chars, expected, "version {version:#04x}");
+        }
+        assert_eq!(litecoin.preset(), None);
+        assert_eq!(testnet.preset(), Some(Network::Testnet));
+    }
+
+    #[test]
+    fn prefix_fractions_follow_the_payload_range() {
+        let mainnet = Network::Mainnet.params();
+        let testnet = Network::Testnet.params();
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+        let p2sh = address_range(AddressFormat::P2shP2wpkh, &mainnet).unwrap();
+        assert!(close(p2sh.prefix_fraction("3", false), 1.0));
+        assert_eq!(p2sh.prefix_fraction("3a", false), 0.0);
+        assert_eq!(p2sh.prefix_fraction("1", false), 0.0);
+        // The second characters split the range between them.
+        let second: f64 = (BASE58_ALPHABET.iter())
+            .map(|&c| p2sh.prefix_fraction(&format!("3{}", c as char), false))
+            .sum();
+        assert!(close(second, 1.0));
+        // Testnet P2PKH runs from `mfW…` to `n4r…`.
+        let p2pkh = address_range(AddressFormat::P2pkh, &testnet).unwrap();
+        let (m, n) = (
+            p2pkh.prefix_fraction("m", false),
+            p2pkh.prefix_fraction("n", false),
+        );
+        assert!(
+            m > 0.5 && n > 0.0 && n < 0.5 && close(m + n, 1.0),
+            "{m} {n}"
+        );
+        assert_eq!(p2pkh.prefix_fraction("nz", false), 0.0);
+        // Mainnet P2PKH: each further `1` is a zero byte, and full-length addresses stop at
+        // `1Q`, so only the rare shorter ones reach `1z`.
+        let p2pkh = address_range(AddressFormat::P2pkh, &mainnet).unwrap();
+        assert!(close(p2pkh.prefix_fraction("1", false), 1.0));
+        assert!(close(p2pkh.prefix_fraction("11", false), 1.0 / 256.0));
+        let (q, z) = (
+            p2pkh.prefix_fraction("1Q", false),
+            p2pkh.prefix_fraction("1z", false),
+        );
+        assert!(z > 0.0 && z < 0.001 && q > 10.0 * z, "{q} {z}");
+        // Compressed WIFs split between `K` and `L`; uncompressed ones are `5H`..`5K`.
+        let wif = wif_range(&mainnet, true);
+        let (k, l) = (
+            wif.prefix_fraction("K", false),
+            wif.prefix_fraction("L", false),
+        );
+        assert!(k > 0.0 && l > 0.0 && close(k + l, 1.0), "{k} {l}");
+        assert_eq!(wif.prefix_fraction("Lz", false), 0.0);
+        assert!(close(wif.prefix_fraction("l", true), l));
+        assert_eq!(wif_range(&mainnet, false).prefix_fraction("5z", false), 0.0);
+    }
+
+    #[test]
+    fn wifs_decode_to_their_secret() {
+        let mainnet = Network::Mainnet.params();
+        for compressed in [true, false] {
+            let wif = wif_from_secret(&secret_one(), &mainnet, compressed);
+            assert_eq!(decode_wif(&wif).unwrap(), (0x80, secret_one(), compressed));
+        }
+        // Last character changed, so the checksum no longer matches.
+        assert!(decode_wif("KwDiBf89QgGbjEhKnhXJuH7LrciVrZi3qYjgd9M7rFU73sVHnoWo").is_err());
+    }
+
+    #[test]
+    fn addresses_are_checked_against_their_format() {
+        let mainnet = Network::Mainnet.params();
+        let check = |address: &str, format, version| {
+            check_address_format(address, format, version, &mainnet).is_ok()
+        };
+        for format in [
+            AddressFormat::P2pkh,
+            AddressFormat::P2shP2wpkh,
+            AddressFormat::Bech32,
+            AddressFormat::P2wsh,
+        ] {
+            let address = address_for(&secret_one(), format, Network::Mainnet);
+            assert!(check(&address, format, 0), "{address}");
+        }
+        assert!(!check(
+            "3JvL6Ymt8MVWiCNHC7oWU6nLeHNJKLZGLN",
+            AddressFormat::P2pkh,
+            0
+        ));
+        assert!(!check(
+            "1BgGZ9tcN4rm9KBzDn7KprQz87SZ26SAMi",
+            AddressFormat::P2pkh,
+            0
+        ));
+        let p2wpkh = "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4";
+        assert!(!check(p2wpkh, AddressFormat::P2wsh, 0));
+        assert!(!check(p2wpkh, AddressFormat::Bech32, 1));
+        let testnet = Network::Testnet.params();
+        assert!(check_address_format(p2wpkh, AddressFormat::Bech32, 0, &testnet).is_err());
+    }
+
+    #[test]
+    fn max_lengths_bound_real_encodings() {
+        let mainnet = Network::Mainnet.params();
+        let max = |format, version, compressed, wif| {
+            max_encoded_len(format, version, compressed, &mainnet, wif)
+        };
+        assert_eq!(max(AddressFormat::P2pkh, 0, true, false), 34);
+        assert_eq!(max(AddressFormat::P2shP2wpkh, 0, true, false), 34);
+        assert_eq!(max(AddressFormat::Bech32, 0, true, false), 42);
+        assert_eq!(max(AddressFormat::Bech32, 1, true, false), 62);
+        assert_eq!(max(AddressFormat::P2wsh, 0, true, false), 62);
+        assert_eq!(max(AddressFormat::P2pkh, 0, true, true), 52);
+        assert_eq!(max(AddressFormat::P2pkh, 0, false, true), 51);
+        for format in [AddressFormat::P2pkh, AddressFormat::Bech32] {
+            let address = address_for(&secret_one(), format, Network::Mainnet);
+            assert!(address.len() <= max(format, 0, true, false));
+        }
+    }
+
+    #[test]
+    fn hrp_must_be_lowercase_printable_ascii() {
+        assert!(validate_hrp("ltc").is_ok());
+        assert!(validate_hrp("").is_err());
+        assert!(validate_hrp("LTC").is_err());
+        assert!(validate_hrp("l tc").is_err());
+    }
+}
@@ -1,9 +1,13 @@
 use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsString,
     fs,
+    io::{self, BufRead, IsTerminal, Write},
+    net::TcpListener,
     path::{Path, PathBuf},
     str::FromStr,
     sync::{
-        atomic::{AtomicBool, AtomicU64, Ordering},
+        atomic::{AtomicBool, Ordering},
         Arc, Mutex,
     },
     thread,
@@ -11,939 +15,3367 @@ use std::{
 };
 
 use anyhow::{anyhow, Context, Result};
-use bech32::{self, ToBase32, Variant};
-use bip32::{DerivationPath, XPrv};
-use bip39::{Language, Mnemonic};
-use clap::{Parser, ValueEnum};
-use once_cell::sync::Lazy;
-use rand::Rng;
-use rayon::ThreadPoolBuilder;
-use ripemd::Ripemd160;
-use secp256k1::{All, PublicKey as SecpPublicKey, Secp256k1, SecretKey};
-use serde::{Deserialize, Serialize};
-use serde_json::Value;
-use sha2::{Digest, Sha256};
-
-const ATTEMPT_BATCH: u64 = 2048;
-const PROGRESS_INTERVAL: u64 = 100_000;
-const BASE58_ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
-
-static SECP256K1: Lazy<Secp256k1<All>> = Lazy::new(Secp256k1::new);
-
-#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
-#[value(rename_all = "lowercase")]
-enum AddressFormat {
-    P2pkh,
-    Bech32,
-}
+use bip32::DerivationPath;
+use clap::{
+    parser::ValueSource, ArgGroup, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum,
+};
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use log::{error, info, warn, LevelFilter};
+use rand::{rngs::OsRng, RngCore};
+use regex::Regex;
+use secp256k1::{PublicKey, SecretKey};
+use serde::Serialize;
+use vanity::{
+    address::{
+        address_leading_chars, address_payload, address_range, bech32_fixed_prefix,
+        check_address_format, encode_public_address, fixed_prefix_len, max_encoded_len,
+        p2wsh_witness_script, payload_len, validate_hrp, wif_from_secret, wif_leading_chars,
+        wif_range, AddressFormat, Network, NetworkParams, SECP256K1,
+    },
+    bip38::encrypt_bip38,
+    checkpoint::{latest_checkpoint, load_checkpoint_file, CheckpointFile, CheckpointWriter},
+    descriptor::{descriptor, ranged_key},
+    keys::{
+        combine_split_key, derive_candidate, extended_keys, hd_path_mismatch,
+        key_material_from_attempt, mnemonic_master, mnemonic_xprv, permute_attempt, CandidateKey,
+        KeyMode, KeyStream, HD_INDICES,
+    },
+    metrics::{serve_metrics, ProgressStats},
+    output::{
+        append_result_file, append_search_miss, config_matches, csv_addresses, decrypt_result_file,
+        for_each_result_entry, gzip_path, is_encrypted_result_file, probe_writable, write_atomic,
+        write_results, OtherCompression, OutputFormat, SearchMiss, VanityResult, RESULT_SCHEMA,
+        RESULT_SCHEMA_VERSION,
+    },
+    pattern::{
+        check_fixed_part, check_lengths, ensure_charset, leading_run, load_wordlist,
+        match_probability, matched_any_prefix, matched_any_suffix, prefix_score,
+        prepare_base58_prefix, prepare_hash_prefix, prepare_pattern, prepare_patterns,
+        prepare_prefix, prepare_wif_prefix, LeadingRun, Pattern,
+    },
+    qr::{self, QrMode},
+    search::{
+        self, config_fingerprint, lock_or_recover, search, ExtraFormat, MatchSink, SearchConfig,
+        SearchMatch, SearchProgress, Shard, DEFAULT_BATCH_SIZE, DEFAULT_MNEMONIC_BATCH_SIZE,
+    },
+    selftest,
+    verify::{verify_entry, Verdict},
+};
+
+const STATS_POLL: Duration = Duration::from_millis(100);
+const DEFAULT_PROGRESS_INTERVAL: u64 = 100_000;
+const ESTIMATE_BENCHMARK: Duration = Duration::from_secs(1);
+// Ten years of expected run time.
+const IMPOSSIBLE_SECS: f64 = 10.0 * 365.25 * 24.0 * 3600.0;
 
 #[derive(Parser, Debug)]
-#[command(name = "vanity_bitcoin")]
+#[command(name = "vanity_bitcoin", args_override_self = true)]
 #[command(about = "Brute force Bitcoin vanity addresses", long_about = None)]
+#[command(group(ArgGroup::new("resume_from").args(["resume", "resume_auto"])))]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// TOML file whose keys mirror the long flags (`prefix = "1Bad"`, `mnemonic = true`);
+    /// flags given on the command line win.
+    #[arg(long, value_name = "FILE.toml")]
+    config: Option<PathBuf>,
+
     #[arg(long, value_enum, default_value_t = AddressFormat::P2pkh)]
     format: AddressFormat,
+
+    /// Encode every candidate in each of these formats and match the pattern against all of
+    /// them (`p2pkh,bech32`); formats the pattern is invalid for are skipped.
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        conflicts_with_all = ["format", "match_wif", "prefix_offset"]
+    )]
+    formats: Vec<AddressFormat>,
     #[arg(long, default_value_t = 0)]
     witness_version: u8,
     #[arg(long)]
+    uncompressed: bool,
+
+    /// With --format p2pkh, also record the address and WIF for the other key encoding.
+    #[arg(long)]
+    emit_both_compressions: bool,
+    #[arg(long, value_enum, default_value_t = Network::Mainnet)]
+    network: Network,
+
+    /// Bech32 human-readable part to use instead of mainnet's `bc` (e.g. `ltc`).
+    #[arg(long, conflicts_with = "network")]
+    hrp: Option<String>,
+
+    /// P2PKH version byte (`0x30` or `48`) to use instead of mainnet's.
+    #[arg(long, value_name = "BYTE", conflicts_with = "network")]
+    p2pkh_version: Option<String>,
+
+    /// P2SH version byte to use instead of mainnet's.
+    #[arg(long, value_name = "BYTE", conflicts_with = "network")]
+    p2sh_version: Option<String>,
+
+    /// WIF version byte to use instead of mainnet's.
+    #[arg(long, value_name = "BYTE", conflicts_with = "network")]
+    wif_version: Option<String>,
+    #[arg(long)]
     prefix: Option<String>,
 
     #[arg(long)]
     suffix: Option<String>,
 
+    /// Read the prefix and suffix from the terminal (or two lines of stdin), not argv.
+    #[arg(long, conflicts_with_all = ["prefix", "suffix", "target"])]
+    prompt: bool,
+
+    /// With --prompt, don't echo the pattern as it is typed.
+    #[arg(long, requires = "prompt")]
+    hide_input: bool,
+
+    /// Print the patterns in the banner as asterisks; the search and results still use them.
+    #[arg(long)]
+    redact_pattern: bool,
+
+    /// Match if the address starts with any of these (repeatable; OR-ed with --any-suffix).
+    #[arg(long, value_name = "PREFIX")]
+    any_prefix: Vec<String>,
+
+    /// Match if the address ends with any of these (repeatable; OR-ed with --any-prefix).
+    #[arg(long, value_name = "SUFFIX")]
+    any_suffix: Vec<String>,
+
+    #[arg(long)]
+    contains: Vec<String>,
+
+    #[arg(long, conflicts_with_all = ["prefix", "suffix", "any_prefix", "any_suffix"])]
+    regex: Option<String>,
+
+    /// Match addresses whose hash160 or witness program starts with these hex digits,
+    /// checked before the address is encoded.
+    #[arg(long, value_name = "HEX", conflicts_with = "match_wif")]
+    hash_prefix: Option<String>,
+
+    /// Reject matches containing this substring past the fixed prefix (repeatable).
+    #[arg(long, value_name = "SUBSTRING")]
+    exclude: Vec<String>,
+
+    /// Bech32: match --prefix this many characters past the fixed `bc1q`-style part.
+    #[arg(
+        long,
+        value_name = "N",
+        requires = "prefix",
+        conflicts_with = "match_wif"
+    )]
+    prefix_offset: Option<usize>,
+
+    /// Match addresses whose variable part starts with a run of this character.
+    #[arg(long)]
+    leading_char: Option<char>,
+
+    /// Minimum run length for --leading-char; with --count, each further hit must beat the last.
+    #[arg(long, requires = "leading_char", default_value_t = 1)]
+    leading_min: usize,
+
+    /// Match Base58 --prefix/--suffix/--contains/--wordlist with ASCII case folding.
+    #[arg(long)]
+    ignore_case: bool,
+
+    /// Match any word from this newline-separated file right after the fixed prefix.
+    #[arg(long, value_name = "FILE")]
+    wordlist: Option<PathBuf>,
+
+    /// Let --wordlist words appear anywhere past the fixed prefix.
+    #[arg(long, requires = "wordlist")]
+    wordlist_contains: bool,
+
+    /// Apply the pattern flags to the private key's WIF instead of the address.
+    #[arg(long, conflicts_with_all = ["split_key", "bip38_only"])]
+    match_wif: bool,
+
+    /// Keep the K non-matching addresses closest to --prefix and report them at the end.
+    #[arg(long, value_name = "K", default_value_t = 0)]
+    track_best: usize,
+
+    /// Score candidates by how many leading characters they share with this existing
+    /// address and report the closest (implies --track-best 1).
+    #[arg(
+        long,
+        value_name = "ADDRESS",
+        conflicts_with_all = [
+            "prefix", "suffix", "any_prefix", "any_suffix", "contains", "regex",
+            "leading_char", "wordlist", "prefix_offset", "formats", "match_wif", "hash_prefix"
+        ]
+    )]
+    target: Option<String>,
+
+    /// With --target, stop once a candidate shares this many leading characters with it.
+    #[arg(long, value_name = "N", requires = "target")]
+    target_chars: Option<usize>,
+
     #[arg(long, default_value_t = 0)]
     attempts: u64,
 
+    /// First attempt index to search; overrides the start a --resume checkpoint gives.
+    #[arg(long, value_name = "N", conflicts_with_all = ["derive_attempt", "benchmark", "estimate"])]
+    start_attempt: Option<u64>,
+
+    /// Stop before this attempt index: the exclusive end of the range, in place of --attempts.
+    #[arg(
+        long,
+        value_name = "N",
+        conflicts_with_all = ["attempts", "derive_attempt", "benchmark", "estimate"]
+    )]
+    end_attempt: Option<u64>,
+
+    /// Stop after this much wall-clock time (`90s`, `30m`, `2h`, `1d`, `1h30m`) and save.
+    #[arg(long, value_name = "DURATION", conflicts_with_all = ["benchmark", "estimate"])]
+    max_time: Option<String>,
+
+    #[arg(long, default_value_t = 1)]
+    count: u64,
+
     #[arg(long)]
     threads: Option<usize>,
 
+    /// Keep a --threads above the available CPUs instead of capping it.
+    #[arg(long)]
+    allow_oversubscribe: bool,
+
+    /// Pin each search thread to its own CPU (Linux; ignored elsewhere).
+    #[arg(long)]
+    pin_threads: bool,
+
+    /// Cap total attempts per second across all threads by pausing workers (0 = no cap).
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    max_rate: u64,
+
+    /// Attempts each thread claims at a time [default: 2048, or 16 with --mnemonic].
+    #[arg(long, value_name = "N")]
+    batch_size: Option<u64>,
+
+    /// Grow or shrink each worker's batch from its measured speed, capped at --batch-size.
+    #[arg(long)]
+    adaptive_batch: bool,
+
+    #[arg(long, default_value_t = DEFAULT_PROGRESS_INTERVAL)]
+    progress_interval: u64,
+
     #[arg(long)]
     seed: Option<u64>,
 
+    /// Use this 256-bit key-stream root (64 hex characters) instead of a --seed.
+    #[arg(long, value_name = "HEX", conflicts_with_all = ["seed", "seed_file"])]
+    seed_hex: Option<String>,
+
+    /// Read the key-stream root from a file: 64 hex characters or 32 raw bytes.
+    #[arg(long, value_name = "PATH", conflicts_with = "seed")]
+    seed_file: Option<PathBuf>,
+
     #[arg(long)]
     output: Option<PathBuf>,
 
+    /// Print the results to stdout (in --output-format) when the search ends; write no files.
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "output", "encrypt_output", "compress", "qr", "checkpoint", "resume", "resume_auto",
+            "track_best", "target"
+        ]
+    )]
+    output_stdout: bool,
+
+    #[arg(long, value_enum, default_value_t = OutputFormat::JsonArray)]
+    output_format: OutputFormat,
+
+    /// Encrypt the result file with a password (Argon2id + XChaCha20-Poly1305).
+    #[arg(long)]
+    encrypt_output: bool,
+
+    /// With --encrypt-output, still allow --checkpoint/--resume-auto, whose plaintext seed
+    /// root re-derives every match.
+    #[arg(long, requires = "encrypt_output")]
+    plaintext_checkpoint: bool,
+
+    /// Print the JSON inside a file written with --encrypt-output, then exit.
+    #[arg(long, value_name = "FILE", conflicts_with = "encrypt_output")]
+    decrypt: Option<PathBuf>,
+
+    /// On a match, render the address and WIF (or BIP-38 key) as QR codes.
+    #[arg(long, value_enum, value_name = "MODE")]
+    qr: Option<QrMode>,
+
+    /// Add a QR code for the mnemonic (mnemonic mode).
+    #[arg(long, requires_all = ["qr", "mnemonic"], conflicts_with = "fixed_mnemonic")]
+    qr_mnemonic: bool,
+
     #[arg(long)]
     checkpoint: Option<PathBuf>,
 
+    /// Gzip the result and checkpoint files (adds `.gz`; a `.gz` path alone does the same).
+    #[arg(long)]
+    compress: bool,
+
     #[arg(long)]
     resume: Option<PathBuf>,
 
+    /// Resume the furthest checkpoint in DIR made by this same search, or start fresh and
+    /// checkpoint into DIR.
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["checkpoint", "derive_attempt"])]
+    resume_auto: Option<PathBuf>,
+
+    /// With --resume, accept an --attempts budget the checkpoint has already used up.
+    #[arg(long, requires = "resume_from")]
+    force: bool,
+
     #[arg(long, default_value_t = 100_000)]
     checkpoint_interval: u64,
 
+    /// Only test attempts with `attempt % --shard-count == I` (same --seed on every machine).
+    #[arg(long, value_name = "I", requires = "shard_count")]
+    shard_index: Option<u64>,
+
+    /// Number of machines splitting the search; see --shard-index.
+    #[arg(long, value_name = "N", requires = "shard_index")]
+    shard_count: Option<u64>,
+
     #[arg(long)]
     mnemonic: bool,
 
     #[arg(long, default_value = "m/44'/0'/0'/0/0")]
     hd_path: String,
 
+    /// Reject an --hd-path that isn't the BIP-44/49/84/86 template for --format (default: warn).
+    #[arg(long, requires = "mnemonic")]
+    strict_path: bool,
+
+    #[arg(long, default_value_t = 24)]
+    mnemonic_words: usize,
+
+    #[arg(long, requires = "mnemonic")]
+    passphrase: Option<String>,
+
+    /// Check children 0..N of each mnemonic (last --hd-path component replaced).
+    #[arg(long, value_name = "N", requires = "mnemonic", default_value_t = 1)]
+    scan_indices: u32,
+
+    /// Search child indices under this existing phrase instead of new mnemonics: attempt N
+    /// replaces the last --hd-path component with N (e.g. m/84'/0'/0'/0/N).
+    #[arg(
+        long,
+        value_name = "PHRASE",
+        requires = "mnemonic",
+        conflicts_with_all = ["scan_indices", "mnemonic_words", "permute"]
+    )]
+    fixed_mnemonic: Option<String>,
+
+    /// Also export each matched key as a BIP-38 encrypted key (6P...) under this passphrase.
+    #[arg(long, value_name = "PASSPHRASE", conflicts_with = "split_key")]
+    bip38_passphrase: Option<String>,
+
+    /// Omit the plaintext hex/WIF key from the console and result file, keeping only BIP-38.
+    #[arg(long, requires = "bip38_passphrase", conflicts_with = "mnemonic")]
+    bip38_only: bool,
+
     #[arg(long)]
     derive_attempt: Option<u64>,
 
+    /// Generate a base keypair for --split-key and exit. Keep the secret; share the pubkey.
+    #[arg(long, conflicts_with_all = ["split_key", "combine"])]
+    new_split_key: bool,
+
+    /// Use secrets base + attempt and batched point addition: much faster, but keys are
+    /// related (one leaked key and its attempt index reveal the rest).
+    #[arg(long, conflicts_with_all = ["mnemonic", "split_key"])]
+    sequential: bool,
+
+    /// Walk the attempts in a keyed pseudo-random order (a Feistel shuffle of the index)
+    /// rather than 0, 1, 2, ...; needed again with --resume and --derive-attempt.
+    #[arg(long, conflicts_with = "sequential")]
+    permute: bool,
+
+    /// Search offsets against this base public key (hex); matches reveal only the offset.
+    #[arg(long, value_name = "BASE_PUBKEY_HEX", conflicts_with = "mnemonic")]
+    split_key: Option<String>,
+
+    /// Add a found split-key offset (hex) to --base-secret and print the final key, then exit.
+    #[arg(long, value_name = "OFFSET_HEX", requires = "base_secret")]
+    combine: Option<String>,
+
+    #[arg(long, value_name = "BASE_SECRET_HEX", requires = "combine")]
+    base_secret: Option<String>,
+
+    /// Measure raw key/address throughput for this many seconds (no pattern), then exit.
+    #[arg(long, value_name = "SECONDS", conflicts_with_all = ["estimate", "derive_attempt", "resume", "resume_auto"])]
+    benchmark: Option<u64>,
+
+    /// Print the match probability and expected run time for the pattern, then exit.
+    #[arg(long)]
+    estimate: bool,
+
     #[arg(long, default_value_t = 5)]
     stats_interval: u64,
 
     #[arg(long)]
     stats_json: bool,
+
+    /// Write stats records to this file (or named pipe) instead of stdout, replacing the
+    /// previous snapshot each time.
+    #[arg(long, value_name = "PATH")]
+    stats_output: Option<PathBuf>,
+
+    /// Append each stats record to --stats-output instead of replacing it.
+    #[arg(long, requires = "stats_output")]
+    stats_append: bool,
+
+    /// Serve Prometheus metrics at http://HOST:PORT/metrics while the search runs.
+    #[arg(long, value_name = "HOST:PORT")]
+    metrics_addr: Option<String>,
+
+    /// Print a one-line JSON summary of the outcome to stderr when the search ends.
+    #[arg(long)]
+    summary_json: bool,
+
+    /// Write the --summary-json object to this file instead of stderr.
+    #[arg(long, value_name = "PATH", requires = "summary_json")]
+    summary_file: Option<PathBuf>,
+
+    /// Exit with status 2 when fewer than --count matches were found.
+    #[arg(long)]
+    fail_on_miss: bool,
+
+    /// When fewer than --count matches were found, append a `found: false` record of the
+    /// session (stop reason, attempts, elapsed, config) to the result file.
+    #[arg(long, conflicts_with = "output_stdout")]
+    record_misses: bool,
+
+    /// Only print results, errors and --stats-json lines (no banner or progress).
+    #[arg(long)]
+    quiet: bool,
+
+    /// Validate every flag and path, print the resolved search and its config hash, and exit.
+    #[arg(long, conflicts_with_all = ["benchmark", "estimate", "derive_attempt", "decrypt"])]
+    check: bool,
+
+    /// Keep the line-based progress output even on a terminal.
+    #[arg(long)]
+    no_progress_bar: bool,
+
+    /// Keep terminal output to ASCII ("unlimited" instead of the infinity sign, plain
+    /// spinner and QR art) for terminals that don't decode UTF-8.
+    #[arg(long)]
+    ascii: bool,
+
+    /// Log every Nth candidate (attempt and address or WIF) from the first worker.
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    sample_every: u64,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Re-derive every entry of a result file and check it reproduces the stored address.
+    Verify {
+        /// A json-array or ndjson result file (prompts for a password if encrypted).
+        file: PathBuf,
+
+        /// BIP-39 passphrase for mnemonic entries recorded with `passphrase_used`.
+        #[arg(long)]
+        passphrase: Option<String>,
+    },
+    /// Print the JSON Schema of a result entry and exit.
+    Schema,
+    /// List every format on each network (or the custom --hrp/version bytes) with its
+    /// version byte or HRP, fixed leading characters, length and pattern charset.
+    Formats,
+    /// Derive and re-decode sample keys for every format, network and key mode, checking
+    /// the build's encoders against known vectors and independent decoders.
+    Selftest {
+        /// Candidates per format/network/key-mode case.
+        #[arg(long, default_value_t = 4)]
+        samples: u64,
+
+        /// Key-stream seed for the samples (random by default; printed for reruns).
+        #[arg(long)]
+        seed: Option<u64>,
+    },
+    /// Show each step of the raw-key schedule for one attempt (root, keystream block,
+    /// 32-byte material, secret, public key, address) so it can be checked independently.
+    DeriveMaterial {
+        /// The search's --seed, expanded into the keystream root.
+        #[arg(
+            long,
+            required_unless_present = "seed_hex",
+            conflicts_with = "seed_hex"
+        )]
+        seed: Option<u64>,
+
+        /// The search's --seed-hex: the 256-bit root itself.
+        #[arg(long, value_name = "HEX")]
+        seed_hex: Option<String>,
+
+        /// The attempt index, e.g. a result's `attempt_index`.
+        #[arg(long)]
+        attempt: u64,
+
+        /// The search ran with --permute.
+        #[arg(long)]
+        permute: bool,
+    },
 }
 
+// --summary-json: the one-shot outcome of a search, for scripts deciding success.
 #[derive(Serialize)]
-struct VanityResult {
-    private_key_hex: String,
-    wif: String,
-    address: String,
-    format: String,
-    witness_version: Option<u8>,
+struct RunSummary {
+    // Every requested match (--count) was found.
+    found: bool,
+    // "found", "exhausted", "interrupted", "time_limit" or "save_failed".
+    stop_reason: &'static str,
+    // Includes matches saved by earlier sessions of a resumed search.
+    matches: u64,
+    matches_target: u64,
+    // Totals across resumed sessions, like the result entries.
     attempts: u64,
-    attempts_limit: Option<u64>,
-    seed: u64,
-    prefix: Option<String>,
-    suffix: Option<String>,
-    mnemonic: Option<String>,
-    hd_path: Option<String>,
+    elapsed_ms: u128,
+    // Attempts per second in this session.
+    rate: f64,
+    // This session's attempts that derived no key; included in `attempts`.
+    discarded: u64,
+    // Set when at least one result was written.
+    result_path: Option<PathBuf>,
+    checkpoint_path: Option<PathBuf>,
 }
 
-#[derive(Serialize, Deserialize)]
-struct CheckpointFile {
-    version: u32,
-    next_attempt: u64,
-    base_seed: u64,
-    config_hash: String,
+#[derive(Serialize)]
+struct BenchmarkReport {
+    format: String,
+    witness_version: Option<u8>,
+    mnemonic: bool,
+    threads: usize,
+    attempts: u64,
+    elapsed_ms: u128,
+    attempts_per_sec: f64,
 }
 
-struct CheckpointWriter {
-    path: PathBuf,
-    config_hash: String,
-    base_seed: u64,
-    interval: u64,
-    next_flush: AtomicU64,
-    lock: Mutex<()>,
-}
+fn main() -> Result<()> {
+    // Diagnostics go through `log`; warnings and errors show unless RUST_LOG says otherwise.
+    let mut logger =
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn"));
+    let (mut args, loaded_config) = parse_args()?;
+    if args.sample_every > 0 {
+        logger.filter_module(search::SAMPLE_LOG_TARGET, LevelFilter::Info);
+    }
+    logger.init();
 
-impl CheckpointWriter {
-    fn new(path: PathBuf, config_hash: String, base_seed: u64, interval: u64) -> Self {
-        Self {
-            path,
-            config_hash,
-            base_seed,
-            interval: interval.max(1),
-            next_flush: AtomicU64::new(0),
-            lock: Mutex::new(()),
-        }
+    if let Some(Command::Schema) = &args.command {
+        print!("{}", RESULT_SCHEMA);
+        return Ok(());
+    }
+    if let Some(Command::Formats) = &args.command {
+        return print_formats(&network_params(&args)?, !args.uncompressed);
+    }
+    if let Some(Command::Verify { file, passphrase }) = &args.command {
+        return run_verify(file, passphrase.as_deref());
+    }
+    if let Some(Command::DeriveMaterial {
+        seed,
+        seed_hex,
+        attempt,
+        permute,
+    }) = &args.command
+    {
+        let stream = match (seed, seed_hex) {
+            (Some(seed), _) => KeyStream::from_seed(*seed),
+            (None, Some(hex)) => KeyStream::from_root_hex(hex).context("Invalid --seed-hex")?,
+            (None, None) => unreachable!("clap requires --seed or --seed-hex"),
+        };
+        return print_material(&args, &stream.permuted(*permute), *attempt);
+    }
+    if let Some(Command::Selftest { samples, seed }) = &args.command {
+        return run_selftest(*samples, seed.unwrap_or_else(|| OsRng.next_u64()));
     }
 
-    fn maybe_write(&self, attempts: u64) {
-        let target = self.next_flush.load(Ordering::Relaxed);
-        if attempts < target {
-            return;
-        }
-        if let Ok(_guard) = self.lock.try_lock() {
-            let target = self.next_flush.load(Ordering::Relaxed);
-            if attempts < target {
-                return;
-            }
-            if let Err(err) = self.write_file(attempts) {
-                eprintln!(
-                    "Failed to write checkpoint {}: {err:?}",
-                    self.path.display()
-                );
-            } else {
-                let next = attempts.saturating_add(self.interval);
-                self.next_flush.store(next, Ordering::Relaxed);
+    // The first of --formats stands in for --format until the patterns are checked.
+    let mut formats = vec![args.format];
+    if !args.formats.is_empty() {
+        formats.clear();
+        for format in &args.formats {
+            if !formats.contains(format) {
+                formats.push(*format);
             }
         }
+        args.format = formats[0];
     }
 
-    fn force_write(&self, attempts: u64) -> Result<()> {
-        let _guard = self.lock.lock().expect("checkpoint mutex poisoned");
-        self.write_file(attempts)?;
-        let next = attempts.saturating_add(self.interval);
-        self.next_flush.store(next, Ordering::Relaxed);
-        Ok(())
+    if args.witness_version > 16 {
+        return Err(anyhow!("--witness-version must be between 0 and 16"));
+    }
+    if !formats.contains(&AddressFormat::Bech32) && args.witness_version != 0 {
+        return Err(anyhow!(
+            "--witness-version only applies when --format bech32"
+        ));
     }
 
-    fn write_file(&self, attempts: u64) -> Result<()> {
-        let payload = CheckpointFile {
-            version: 1,
-            next_attempt: attempts,
-            base_seed: self.base_seed,
-            config_hash: self.config_hash.clone(),
-        };
-        save_checkpoint_file(&self.path, &payload)
+    if args.ignore_case && formats.iter().all(|f| f.is_bech32()) && !args.match_wif {
+        return Err(anyhow!(
+            "--ignore-case only applies to Base58 formats (Bech32 patterns are already case-insensitive)"
+        ));
     }
-}
 
-#[derive(Serialize)]
-struct ProgressStats {
-    attempts: u64,
-    attempts_per_sec: f64,
-    elapsed_ms: u128,
-}
+    if args.uncompressed && formats.iter().any(|f| *f != AddressFormat::P2pkh) {
+        return Err(anyhow!(
+            "--uncompressed only applies to --format p2pkh (SegWit requires compressed keys)"
+        ));
+    }
+    if args.emit_both_compressions && formats.iter().any(|f| *f != AddressFormat::P2pkh) {
+        return Err(anyhow!(
+            "--emit-both-compressions only applies to --format p2pkh (SegWit keys are always compressed)"
+        ));
+    }
+    let compressed = !args.uncompressed;
 
-#[derive(Clone)]
-enum KeyMode {
-    Raw,
-    Mnemonic {
-        path: DerivationPath,
-        path_string: String,
-    },
-}
+    let network = network_params(&args)?;
 
-struct CandidateKey {
-    secret: SecretKey,
-    mnemonic: Option<String>,
-}
+    if args.prompt {
+        (args.prefix, args.suffix) = prompt_pattern(args.hide_input)?;
+    }
 
-impl KeyMode {
-    fn path_string(&self) -> Option<&str> {
-        match self {
-            KeyMode::Raw => None,
-            KeyMode::Mnemonic { path_string, .. } => Some(path_string.as_str()),
+    // --target searches for the target's own leading characters and keeps the nearest misses.
+    if let Some(target) = args.target.clone() {
+        check_address_format(&target, args.format, args.witness_version, &network)
+            .context("Invalid --target")?;
+        let target = if args.format.is_bech32() {
+            target.to_lowercase()
+        } else {
+            target
+        };
+        let fixed = fixed_prefix_len(args.format, &network);
+        let chars = args.target_chars.unwrap_or(target.len());
+        if chars <= fixed || chars > target.len() {
+            return Err(anyhow!(
+                "--target-chars must be between {} and {} for this address",
+                fixed + 1,
+                target.len()
+            ));
         }
+        args.prefix = Some(target[..chars].to_string());
+        args.track_best = args.track_best.max(1);
+        args.target = Some(target);
+    }
+    if args.track_best > 0 && args.prefix.is_none() {
+        return Err(anyhow!("--track-best requires --prefix or --target"));
     }
-}
 
-fn main() -> Result<()> {
-    let args = Args::parse();
+    let max_time = args
+        .max_time
+        .as_deref()
+        .map(parse_duration)
+        .transpose()
+        .context("Invalid --max-time")?;
 
-    if args.witness_version > 16 {
-        return Err(anyhow!("--witness-version must be between 0 and 16"));
+    if args.count == 0 {
+        return Err(anyhow!("--count must be at least 1"));
+    }
+    if args.batch_size == Some(0) {
+        return Err(anyhow!("--batch-size must be at least 1"));
+    }
+    if args.progress_interval == 0 {
+        return Err(anyhow!("--progress-interval must be at least 1"));
+    }
+    if (args.checkpoint.is_some() || args.resume_auto.is_some()) && args.checkpoint_interval == 0 {
+        return Err(anyhow!("--checkpoint-interval must be greater than 0"));
+    }
+
+    let max_attempts = match args.end_attempt {
+        Some(end) => end,
+        None if args.attempts == 0 => u64::MAX,
+        None => args.attempts,
+    };
+    // Named in the errors and banner about the end of the range.
+    let budget_flag = if args.end_attempt.is_some() {
+        "--end-attempt"
+    } else {
+        "--attempts"
+    };
+
+    let cpus = std::thread::available_parallelism().ok().map(|n| n.get());
+    let requested_threads = args.threads.or(cpus).unwrap_or(1).max(1);
+    // Workers beyond the CPUs only add scheduling overhead and contention on the shared
+    // counters, which lowers the rate.
+    let threads = match cpus {
+        Some(cpus) if requested_threads > cpus && !args.allow_oversubscribe => {
+            warn!(
+                "--threads {} exceeds the {} CPUs available; capping at {} (--allow-oversubscribe keeps it)",
+                requested_threads, cpus, cpus
+            );
+            cpus
+        }
+        Some(cpus) if requested_threads > cpus => {
+            warn!(
+                "--threads {} oversubscribes the {} CPUs available, which usually lowers the rate",
+                requested_threads, cpus
+            );
+            requested_threads
+        }
+        _ => requested_threads,
+    };
+    let provided_stream = provided_stream(&args)?;
+    let mut stream = provided_stream
+        .unwrap_or_else(KeyStream::random)
+        .permuted(args.permute);
+    let compressed_path = |path: PathBuf| {
+        if args.compress {
+            gzip_path(&path)
+        } else {
+            path
+        }
+    };
+    let output_path = compressed_path(
+        args.output
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(args.output_format.default_path())),
+    );
+    let mut checkpoint_path = args.checkpoint.clone().map(compressed_path);
+
+    if let Some(path) = &args.decrypt {
+        let password = read_password(false)?;
+        let plaintext = decrypt_result_file(path, &password)?;
+        println!("{}", String::from_utf8(plaintext)?);
+        return Ok(());
+    }
+
+    if args.output_stdout && args.stats_json && args.stats_output.is_none() {
+        return Err(anyhow!(
+            "--stats-json lines would mix with --output-stdout results; add --stats-output"
+        ));
     }
-    if args.format != AddressFormat::Bech32 && args.witness_version != 0 {
+    if args.record_misses && args.output_format == OutputFormat::Csv {
         return Err(anyhow!(
-            "--witness-version only applies when --format bech32"
+            "--record-misses needs --output-format json-array or ndjson"
+        ));
+    }
+    if args.encrypt_output && args.output_format != OutputFormat::JsonArray {
+        return Err(anyhow!(
+            "--encrypt-output requires --output-format json-array"
+        ));
+    }
+    if args.encrypt_output
+        && (args.checkpoint.is_some() || args.resume_auto.is_some())
+        && !args.plaintext_checkpoint
+    {
+        return Err(anyhow!(
+            "--encrypt-output with --checkpoint/--resume-auto would leave the seed root, and so \
+             every match, in a plaintext checkpoint; add --plaintext-checkpoint to accept that"
         ));
     }
 
-    let max_attempts = if args.attempts == 0 {
-        u64::MAX
-    } else {
-        args.attempts
-    };
-
-    let threads = args
-        .threads
-        .or_else(|| std::thread::available_parallelism().ok().map(|n| n.get()))
-        .unwrap_or(1)
-        .max(1);
-    let provided_seed = args.seed;
-    let mut base_seed = provided_seed.unwrap_or_else(|| rand::thread_rng().gen());
-    let output_path = args
-        .output
-        .clone()
-        .unwrap_or_else(|| PathBuf::from("results/vanity-bitcoin.json"));
-
-    let key_mode = if args.mnemonic {
+    if args.new_split_key {
+        let base = loop {
+            let mut bytes = [0u8; 32];
+            OsRng.fill_bytes(&mut bytes);
+            if let Ok(secret) = SecretKey::from_slice(&bytes) {
+                break secret;
+            }
+        };
+        let public = PublicKey::from_secret_key(&SECP256K1, &base);
+        println!(
+            "Base secret : 0x{} (keep private)",
+            hex::encode(base.secret_bytes())
+        );
+        println!("Base pubkey : {}", hex::encode(public.serialize()));
+        println!(
+            "Search with --split-key {}",
+            hex::encode(public.serialize())
+        );
+        return Ok(());
+    }
+
+    if let (Some(offset), Some(base_secret)) = (&args.combine, &args.base_secret) {
+        let offset = parse_secret(offset).context("Invalid --combine offset")?;
+        let base_secret = parse_secret(base_secret).context("Invalid --base-secret")?;
+        let secret = combine_split_key(&base_secret, &offset)?;
+        let candidate = CandidateKey {
+            secret,
+            public: PublicKey::from_secret_key(&SECP256K1, &secret),
+            mnemonic: None,
+            child_index: None,
+        };
+        let mut address_buf = String::with_capacity(64);
+        encode_public_address(
+            &candidate.public,
+            args.format,
+            args.witness_version,
+            compressed,
+            &network,
+            &mut address_buf,
+        )?;
+        println!("Combined split key");
+        let export = export_secret(&candidate.secret, &args, &network, compressed)?;
+        print_candidate(
+            &candidate,
+            &address_buf,
+            &KeyMode::Raw,
+            args.format,
+            args.witness_version,
+            &export,
+            true,
+        );
+        return Ok(());
+    }
+
+    let key_mode = if let Some(base) = &args.split_key {
+        let bytes =
+            hex::decode(base.trim_start_matches("0x")).context("Invalid --split-key hex")?;
+        let base = PublicKey::from_slice(&bytes).context("Invalid --split-key public key")?;
+        KeyMode::SplitKey { base }
+    } else if args.mnemonic {
+        if !matches!(args.mnemonic_words, 12 | 15 | 18 | 21 | 24) {
+            return Err(anyhow!(
+                "--mnemonic-words must be one of 12, 15, 18, 21, 24"
+            ));
+        }
         let path = DerivationPath::from_str(&args.hd_path).with_context(|| {
             format!("Invalid --hd-path '{}': expected BIP32 path", args.hd_path)
         })?;
-        KeyMode::Mnemonic {
-            path,
-            path_string: args.hd_path.clone(),
+        if let Some(problem) =
+            hd_path_mismatch(&path, args.format, args.witness_version, network.preset())
+        {
+            if args.strict_path {
+                return Err(anyhow!("Non-standard --hd-path {}: {}", path, problem));
+            }
+            warn!(
+                "Non-standard --hd-path {}: {}; wallets may not show this address",
+                path, problem
+            );
+        }
+        if args.scan_indices == 0 {
+            return Err(anyhow!("--scan-indices must be at least 1"));
+        }
+        if args.scan_indices > 1 && path.iter().last().is_none_or(|child| child.is_hardened()) {
+            return Err(anyhow!(
+                "--scan-indices replaces the last --hd-path component, which must be non-hardened (e.g. m/44'/0'/0'/0/0)"
+            ));
+        }
+        match &args.fixed_mnemonic {
+            Some(phrase) => KeyMode::fixed_mnemonic(
+                phrase,
+                path,
+                args.hd_path.clone(),
+                args.passphrase.clone().unwrap_or_default(),
+            )?,
+            None => KeyMode::Mnemonic {
+                path,
+                path_string: args.hd_path.clone(),
+                words: args.mnemonic_words,
+                passphrase: args.passphrase.clone().unwrap_or_default(),
+                scan_indices: args.scan_indices,
+            },
         }
+    } else if args.sequential {
+        KeyMode::Sequential
     } else {
         KeyMode::Raw
     };
-    let key_mode = Arc::new(key_mode);
+    // A mnemonic attempt costs a full PBKDF2, so smaller batches keep the work done past
+    // the final match (and before a stop lands) short.
+    let batch_size = args.batch_size.unwrap_or(match key_mode {
+        KeyMode::Mnemonic { .. } => DEFAULT_MNEMONIC_BATCH_SIZE,
+        _ => DEFAULT_BATCH_SIZE,
+    });
+    // A fixed mnemonic has one attempt per non-hardened child index.
+    let max_attempts = match key_mode {
+        KeyMode::FixedMnemonic { .. } => max_attempts.min(HD_INDICES),
+        _ => max_attempts,
+    };
 
     if let Some(target_attempt) = args.derive_attempt {
-        if provided_seed.is_none() {
-            return Err(anyhow!("--derive-attempt requires --seed"));
-        }
-        let candidate = derive_candidate(base_seed, target_attempt, key_mode.as_ref())
+        // The checkpoint holds the run's real seed (or OS random root).
+        let stream = match &args.resume {
+            Some(path) => {
+                let checkpoint = load_checkpoint_file(path)
+                    .with_context(|| format!("Failed to load checkpoint at {}", path.display()))?;
+                checkpoint
+                    .matching_stream(provided_stream.as_ref())?
+                    .permuted(args.permute)
+            }
+            None if provided_stream.is_some() => stream,
+            // Attempt N is child N whatever the stream.
+            None if matches!(key_mode, KeyMode::FixedMnemonic { .. }) => stream,
+            None => {
+                return Err(anyhow!(
+                    "--derive-attempt requires --seed, --seed-hex, --seed-file or --resume"
+                ))
+            }
+        };
+        let candidate = derive_candidate(&stream, target_attempt, &key_mode)
             .ok_or_else(|| anyhow!("Failed to derive attempt {}", target_attempt))?;
         let mut address_buf = String::with_capacity(40);
-        encode_address(
-            &candidate.secret,
+        encode_public_address(
+            &candidate.public,
             args.format,
             args.witness_version,
+            compressed,
+            &network,
             &mut address_buf,
         )?;
         println!("Derived attempt {}", target_attempt);
+        let export = export_secret(&candidate.secret, &args, &network, compressed)?;
         print_candidate(
             &candidate,
             &address_buf,
-            key_mode.as_ref(),
+            &key_mode,
             args.format,
             args.witness_version,
+            &export,
+            true,
         );
         return Ok(());
     }
 
-    let prefix = prepare_pattern(args.prefix.clone(), args.format)?;
-    let suffix = prepare_pattern(args.suffix.clone(), args.format)?;
-    if prefix.is_none() && suffix.is_none() {
-        return Err(anyhow!("Provide --prefix and/or --suffix"));
+    if args.prefix_offset.is_some() && !args.format.is_bech32() {
+        return Err(anyhow!(
+            "--prefix-offset only applies to --format bech32 or p2wsh"
+        ));
     }
-
-    let mut resume_attempt = 0u64;
-    let resume_checkpoint = if let Some(path) = args.resume.as_ref() {
-        Some((
-            path.clone(),
-            load_checkpoint_file(path)
-                .with_context(|| format!("Failed to load checkpoint at {}", path.display()))?,
-        ))
-    } else {
-        None
-    };
-
+    let wordlist = args
+        .wordlist
+        .as_ref()
+        .map(|path| {
+            let raw = fs::read_to_string(path)
+                .with_context(|| format!("Failed to read wordlist {}", path.display()))?;
+            anyhow::Ok((path, raw))
+        })
+        .transpose()?;
+    let build_pattern = |format: AddressFormat| -> Result<Pattern> {
+        // WIFs are Base58 whatever the address format.
+        let pattern_format = if args.match_wif {
+            AddressFormat::P2pkh
+        } else {
+            format
+        };
+        let fixed_len = fixed_prefix_len(pattern_format, &network);
+        let prepare_start = |value: Option<String>| {
+            if args.match_wif {
+                prepare_wif_prefix(value, &wif_range(&network, compressed), args.ignore_case)
+            } else if let Some(range) = address_range(format, &network) {
+                prepare_base58_prefix(
+                    value,
+                    &range,
+                    "Prefix",
+                    &format!("{:?} addresses on {}", format, network.name()),
+                    args.ignore_case,
+                )
+            } else {
+                prepare_prefix(
+                    value,
+                    format,
+                    &bech32_fixed_prefix(&network, args.witness_version),
+                    args.ignore_case,
+                )
+            }
+        };
+        let pattern = Pattern {
+            prefix: if args.prefix_offset.is_some() {
+                // The prefix then sits wholly inside the data part, so it omits `bc1q`.
+                prepare_pattern(args.prefix.clone(), format, false).context("Invalid --prefix")?
+            } else {
+                prepare_start(args.prefix.clone())?
+            },
+            suffix: prepare_pattern(args.suffix.clone(), pattern_format, args.ignore_case)?,
+            // With --formats each format keeps the entries valid for it, so prefixes can be
+            // given per format (`--any-prefix 1abc --any-prefix bc1qabc`).
+            any_prefix: if formats.len() > 1 && !args.any_prefix.is_empty() {
+                let kept: Vec<String> = args
+                    .any_prefix
+                    .iter()
+                    .filter_map(|value| prepare_start(Some(value.clone())).ok().flatten())
+                    .collect();
+                if kept.is_empty() {
+                    return Err(anyhow!("no --any-prefix entry is valid for {:?}", format));
+                }
+                kept
+            } else {
+                args.any_prefix
+                    .iter()
+                    .map(|value| Ok(prepare_start(Some(value.clone()))?.unwrap_or_default()))
+                    .collect::<Result<_>>()
+                    .context("Invalid --any-prefix")?
+            },
+            any_suffix: prepare_patterns(&args.any_suffix, pattern_format, args.ignore_case)
+                .context("Invalid --any-suffix")?,
+            contains: prepare_patterns(&args.contains, pattern_format, args.ignore_case)?,
+            exclude: prepare_patterns(&args.exclude, pattern_format, args.ignore_case)
+                .context("Invalid --exclude")?,
+            regex: args
+                .regex
+                .as_deref()
+                .map(Regex::new)
+                .transpose()
+                .context("Invalid --regex")?,
+            leading: args
+                .leading_char
+                .map(|c| -> Result<LeadingRun> {
+                    let c = if pattern_format.is_bech32() {
+                        c.to_ascii_lowercase()
+                    } else {
+                        c
+                    };
+                    ensure_charset(&c.to_string(), pattern_format)
+                        .context("Invalid --leading-char")?;
+                    Ok(LeadingRun {
+                        char: c,
+                        min: args.leading_min.max(1),
+                    })
+                })
+                .transpose()?,
+            wordlist: wordlist
+                .as_ref()
+                .map(|(path, raw)| {
+                    load_wordlist(
+                        raw,
+                        pattern_format,
+                        args.wordlist_contains,
+                        args.ignore_case,
+                    )
+                    .with_context(|| format!("Invalid wordlist {}", path.display()))
+                })
+                .transpose()?,
+            ignore_case: args.ignore_case,
+            match_wif: args.match_wif,
+            prefix_at: args.prefix_offset.map_or(0, |offset| fixed_len + offset),
+            // A WIF's version byte pins its first character, like a P2PKH address.
+            fixed_len,
+            hash_prefix: prepare_hash_prefix(
+                args.hash_prefix.clone(),
+                payload_len(format, args.witness_version),
+            )
+            .context("Invalid --hash-prefix")?,
+        };
+        let max_len = max_encoded_len(
+            format,
+            args.witness_version,
+            compressed,
+            &network,
+            args.match_wif,
+        );
+        check_lengths(&pattern, max_len).context("The pattern can never match")?;
+        Ok(pattern)
+    };
+    let (pattern, extra_formats) = if formats.len() == 1 {
+        (build_pattern(args.format)?, Vec::new())
+    } else {
+        // --formats also drops formats whose fixed leading part the prefix contradicts.
+        let sample = PublicKey::from_secret_key(&SECP256K1, &SecretKey::from_slice(&[1; 32])?);
+        let fitting = |format: AddressFormat| -> Result<Pattern> {
+            let pattern = build_pattern(format)?;
+            let mut address = String::with_capacity(64);
+            encode_public_address(
+                &sample,
+                format,
+                args.witness_version,
+                compressed,
+                &network,
+                &mut address,
+            )?;
+            check_fixed_part(&pattern, &address)?;
+            Ok(pattern)
+        };
+        let mut valid = Vec::new();
+        let mut last_err = None;
+        for &format in &formats {
+            match fitting(format) {
+                Ok(pattern) => valid.push(ExtraFormat { format, pattern }),
+                Err(err) => {
+                    warn!("Skipping --formats {:?}: {:#}", format, err);
+                    last_err = Some(err);
+                }
+            }
+        }
+        if valid.is_empty() {
+            return Err(last_err
+                .unwrap_or_else(|| anyhow!("--formats is empty"))
+                .context("The pattern is not valid for any of --formats"));
+        }
+        let primary = valid.remove(0);
+        args.format = primary.format;
+        (primary.pattern, valid)
+    };
+
+    // --benchmark and --estimate run this as is; a search fills in its resume point, budget
+    // and shard once they are known.
+    let mut config = SearchConfig {
+        format: args.format,
+        extra_formats,
+        witness_version: args.witness_version,
+        compressed,
+        network: network.clone(),
+        key_mode,
+        stream,
+        pattern,
+        start_attempt: 0,
+        max_attempts: u64::MAX,
+        count: args.count,
+        threads,
+        batch_size,
+        adaptive_batch: args.adaptive_batch,
+        track_best: 0,
+        shard: Shard::default(),
+        sample_every: 0,
+        pin_threads: args.pin_threads,
+        max_rate: args.max_rate,
+    };
+    let (key_mode, pattern, extra_formats) =
+        (&config.key_mode, &config.pattern, &config.extra_formats);
+
+    if let Some(secs) = args.benchmark {
+        if secs == 0 {
+            return Err(anyhow!("--benchmark must be at least 1 second"));
+        }
+        let config = SearchConfig { count: 1, ..config };
+        return run_benchmark(&config, Duration::from_secs(secs), args.stats_json);
+    }
+
+    if pattern.is_empty() {
+        return Err(anyhow!(
+            "Provide --prefix, --suffix, --any-prefix, --any-suffix, --contains, --regex, --leading-char and/or --hash-prefix"
+        ));
+    }
+
+    if args.estimate {
+        return print_estimate(&config);
+    }
+    // Past the benchmark and estimate, this run derives keys meant to be kept.
+    if let Some(seed) = args.seed {
+        warn!(
+            "--seed {} keys are only as secret as that 64-bit seed, which anyone can try; \
+             don't fund them, and use --seed-hex or --seed-file for a 256-bit root",
+            seed
+        );
+    }
+
+    let mut shard = match (args.shard_index, args.shard_count) {
+        (Some(index), Some(count)) => Shard::new(index, count)?,
+        _ => Shard::default(),
+    };
+    let formats: Vec<AddressFormat> = std::iter::once(args.format)
+        .chain(extra_formats.iter().map(|extra| extra.format))
+        .collect();
+    let fingerprint = |stream: &KeyStream| {
+        hex::encode(config_fingerprint(
+            stream,
+            pattern,
+            key_mode,
+            &formats,
+            args.witness_version,
+            compressed,
+            &network,
+        ))
+    };
+    let mut resume_attempt = 0u64;
+    let resume_checkpoint = if let Some(path) = args.resume.as_ref() {
+        Some((
+            path.clone(),
+            load_checkpoint_file(path)
+                .with_context(|| format!("Failed to load checkpoint at {}", path.display()))?,
+        ))
+    } else if let Some(dir) = &args.resume_auto {
+        latest_checkpoint(
+            dir,
+            provided_stream.as_ref(),
+            args.permute,
+            shard,
+            fingerprint,
+        )?
+    } else {
+        None
+    };
+
     if let Some((_, checkpoint)) = &resume_checkpoint {
-        if let Some(seed) = args.seed {
-            if seed != checkpoint.base_seed {
+        stream = checkpoint
+            .matching_stream(provided_stream.as_ref())?
+            .permuted(args.permute);
+        resume_attempt = checkpoint.next_attempt;
+        // A resumed shard stays in its lane even if the flags are left off.
+        if let Some(saved) = checkpoint.shard {
+            if args.shard_index.is_some() && saved != shard {
                 return Err(anyhow!(
-                    "Checkpoint base seed ({}) does not match --seed ({})",
-                    checkpoint.base_seed,
-                    seed
+                    "Checkpoint belongs to shard {} of {}, not {} of {}",
+                    saved.index,
+                    saved.count,
+                    shard.index,
+                    shard.count
                 ));
             }
+            shard = saved;
         }
-        base_seed = checkpoint.base_seed;
-        resume_attempt = checkpoint.next_attempt;
     }
+    if let Some(start) = args.start_attempt {
+        resume_attempt = start;
+    }
+    // Progress counters and the bar count this shard's attempts only.
+    let resume_done = shard.lanes_below(resume_attempt);
+    let lane_budget = if max_attempts == u64::MAX {
+        u64::MAX
+    } else {
+        shard.lanes_below(max_attempts)
+    };
+
+    let config_hash = fingerprint(&stream);
+
+    if let Some((_, checkpoint)) = &resume_checkpoint {
+        checkpoint.ensure_config_hash(&config_hash)?;
+    }
+    // --resume-auto keeps writing the checkpoint it resumed, or starts one named after the
+    // config hash.
+    if let Some(dir) = &args.resume_auto {
+        checkpoint_path = Some(match &resume_checkpoint {
+            Some((path, _)) => path.clone(),
+            None => {
+                compressed_path(dir.join(format!("vanity-bitcoin-{}.json", &config_hash[..16])))
+            }
+        });
+    }
+
+    if args
+        .start_attempt
+        .is_some_and(|start| start >= max_attempts)
+    {
+        return Err(anyhow!(
+            "--start-attempt {} is not below {} {}: the range is empty",
+            resume_attempt,
+            budget_flag,
+            max_attempts
+        ));
+    }
+    // A budget the checkpoint already reached leaves nothing to search, which is almost
+    // always a mistyped --attempts rather than the intent.
+    if resume_attempt >= max_attempts {
+        if !args.force {
+            return Err(anyhow!(
+                "{} {} is not above the checkpoint's next attempt {}, so nothing is left \
+                 to search; raise {} past {}{} to continue, or pass --force to accept the \
+                 smaller budget",
+                budget_flag,
+                max_attempts,
+                resume_attempt,
+                budget_flag,
+                resume_attempt,
+                if args.end_attempt.is_none() {
+                    " (or set 0 for no limit)"
+                } else {
+                    ""
+                }
+            ));
+        }
+        println!(
+            "Checkpoint is at attempt {}, past {} {}; nothing left to search (--force).",
+            resume_attempt, budget_flag, max_attempts
+        );
+        return Ok(());
+    }
+
+    // Asked before the search so a long run never stalls on the prompt after a match.
+    let password = if args.encrypt_output {
+        let password = read_password(!output_path.exists())?;
+        // Fail now rather than lose a match to a wrong password or a plaintext file.
+        if output_path.exists() {
+            decrypt_result_file(&output_path, &password)
+                .with_context(|| format!("Cannot append to {}", output_path.display()))?;
+        }
+        Some(password)
+    } else {
+        if !args.output_stdout && is_encrypted_result_file(&output_path) {
+            return Err(anyhow!(
+                "{} is encrypted; pass --encrypt-output to append to it",
+                output_path.display()
+            ));
+        }
+        None
+    };
+
+    // --count is a total across sessions: a resumed search only looks for the remainder.
+    let prior = match &resume_checkpoint {
+        Some((_, checkpoint)) => prior_matches(
+            &output_path,
+            args.output_format,
+            password.as_deref(),
+            &config_hash,
+            checkpoint,
+        )?,
+        None => PriorMatches::default(),
+    };
+    let already_found = prior.count;
+    if already_found >= args.count {
+        println!(
+            "{} already holds {} of {} matches for this search.",
+            output_path.display(),
+            already_found,
+            args.count
+        );
+        return Ok(());
+    }
+    let remaining = args.count - already_found;
+
+    // Bound up front so a taken port fails before any work (and under --check).
+    let metrics_listener = args
+        .metrics_addr
+        .as_ref()
+        .map(|addr| {
+            TcpListener::bind(addr)
+                .with_context(|| format!("Failed to bind --metrics-addr {}", addr))
+        })
+        .transpose()?;
+
+    if !args.quiet || args.check {
+        if args.check {
+            println!("Checking Bitcoin vanity search ({:?})...", args.format);
+        } else {
+            println!("Searching for Bitcoin vanity key ({:?})...", args.format);
+        }
+        if !extra_formats.is_empty() {
+            let also: Vec<String> = extra_formats
+                .iter()
+                .map(|extra| format!("{:?}", extra.format))
+                .collect();
+            println!(
+                "Also      : {} (same keys, first hit wins)",
+                also.join(", ")
+            );
+        }
+        if let Some(config) = &loaded_config {
+            println!(
+                "Config    : {} ({})",
+                config.path.display(),
+                if config.applied.is_empty() {
+                    "all keys overridden".to_string()
+                } else {
+                    config.applied.join(", ")
+                }
+            );
+        }
+        // --redact-pattern: the same width in asterisks, so the line still shows the length.
+        let shown = |value: &str| {
+            if args.redact_pattern {
+                "*".repeat(value.chars().count())
+            } else {
+                value.to_string()
+            }
+        };
+        if let (Some(target), Some(p)) = (&args.target, &pattern.prefix) {
+            println!("Target    : {}", shown(target));
+            if p.len() < target.len() {
+                println!("Stop at   : {} shared leading characters", p.len());
+            } else {
+                println!("Stop at   : never (only the target's own key shares every character)");
+            }
+        } else if let Some(p) = &pattern.prefix {
+            match args.prefix_offset {
+                Some(offset) => println!(
+                    "Prefix    : {} ({} characters past the fixed part)",
+                    shown(p),
+                    offset
+                ),
+                None => println!("Prefix    : {}", shown(p)),
+            }
+        }
+        if let Some(s) = &pattern.suffix {
+            println!("Suffix    : {}", shown(s));
+        }
+        if !pattern.any_prefix.is_empty() || !pattern.any_suffix.is_empty() {
+            let prefixes = pattern
+                .any_prefix
+                .iter()
+                .map(|p| format!("{}...", shown(p)));
+            let suffixes = pattern
+                .any_suffix
+                .iter()
+                .map(|s| format!("...{}", shown(s)));
+            let alternatives: Vec<String> = prefixes.chain(suffixes).collect();
+            println!("Any of    : {}", alternatives.join(" | "));
+        }
+        for c in &pattern.contains {
+            println!("Contains  : {}", shown(c));
+        }
+        for e in &pattern.exclude {
+            println!("Exclude   : {}", shown(e));
+        }
+        if let Some(re) = &pattern.regex {
+            println!("Regex     : {}", shown(re.as_str()));
+        }
+        if let Some(hash) = &pattern.hash_prefix {
+            println!(
+                "Hash      : {}... (leading hex of the {}-byte address payload)",
+                shown(&hash.hex),
+                payload_len(args.format, args.witness_version)
+            );
+        }
+        if pattern.ignore_case {
+            println!("Case      : ignored (regex and leading char still exact)");
+        }
+        if let Some(list) = &pattern.wordlist {
+            println!(
+                "Wordlist  : {} words ({})",
+                list.words.len(),
+                if list.contains { "anywhere" } else { "prefix" }
+            );
+        }
+        if let Some(run) = pattern.leading {
+            println!(
+                "Leading   : at least {} x '{}'{}",
+                run.min,
+                run.char,
+                if args.count > 1 {
+                    " (each hit must beat the previous run)"
+                } else {
+                    ""
+                }
+            );
+        }
+        let max_display = if max_attempts == u64::MAX {
+            unlimited(args.ascii).to_string()
+        } else {
+            max_attempts.to_string()
+        };
+        println!("Max tries : {}", max_display);
+        if let Some(limit) = max_time {
+            println!("Max time  : {}", format_eta(limit.as_secs_f64()));
+        }
+        if already_found > 0 {
+            println!(
+                "Count     : {} ({} already found, {} to go)",
+                args.count, already_found, remaining
+            );
+        } else if args.count > 1 {
+            println!("Count     : {}", args.count);
+        }
+        match cpus {
+            Some(cpus) if threads < requested_threads => println!(
+                "Threads   : {} (capped from {}: {} CPUs available)",
+                threads, requested_threads, cpus
+            ),
+            Some(cpus) if threads > cpus => {
+                println!("Threads   : {} (oversubscribing {} CPUs)", threads, cpus)
+            }
+            _ => println!("Threads   : {}", threads),
+        }
+        if args.max_rate > 0 {
+            println!("Max rate  : {} attempts/s (throttled)", args.max_rate);
+        }
+        if args.match_wif {
+            println!(
+                "Target    : WIF (always starts with {})",
+                wif_leading_chars(&network, compressed)
+                    .chars()
+                    .map(String::from)
+                    .collect::<Vec<_>>()
+                    .join(" or ")
+            );
+        }
+        if shard.is_sharded() {
+            println!(
+                "Shard     : {} of {} (attempts where attempt % {} == {})",
+                shard.index, shard.count, shard.count, shard.index
+            );
+        }
+        match (&resume_checkpoint, provided_stream) {
+            (Some(_), _) => match stream.seed {
+                Some(seed) => println!("RNG seed  : {} (from checkpoint)", seed),
+                None => println!(
+                    "RNG seed  : 256-bit root {} (from checkpoint)",
+                    stream.root_id()
+                ),
+            },
+            (None, Some(provided)) => match provided.seed {
+                Some(seed) => println!("RNG seed  : {} (user supplied)", seed),
+                None => println!(
+                    "RNG seed  : 256-bit root {} (user supplied)",
+                    provided.root_id()
+                ),
+            },
+            (None, None) => {
+                println!("RNG seed  : 256-bit OS random root (kept in checkpoint only)")
+            }
+        }
+        if network.preset().is_some() {
+            println!("Network   : {:?}", args.network);
+        } else {
+            println!(
+                "Network   : custom (hrp {}, p2pkh 0x{:02x}, p2sh 0x{:02x}, wif 0x{:02x})",
+                network.hrp, network.p2pkh_version, network.p2sh_version, network.wif_version
+            );
+        }
+        if args.uncompressed {
+            println!("Pubkey    : uncompressed");
+        }
+        match args.output_format {
+            _ if args.output_stdout => println!("Output    : stdout, when the search ends"),
+            OutputFormat::JsonArray if args.encrypt_output => {
+                println!("Output    : {} (encrypted)", output_path.display())
+            }
+            OutputFormat::JsonArray => println!("Output    : {}", output_path.display()),
+            OutputFormat::Ndjson => println!("Output    : {} (ndjson)", output_path.display()),
+            OutputFormat::Csv => println!("Output    : {} (csv)", output_path.display()),
+        }
+        match &key_mode {
+            KeyMode::Raw => println!("Mode      : raw private keys"),
+            KeyMode::Sequential => {
+                println!("Mode      : sequential keys (base + attempt, batched point addition)")
+            }
+            KeyMode::SplitKey { base } => println!(
+                "Mode      : split-key offsets (base {})",
+                hex::encode(base.serialize())
+            ),
+            KeyMode::Mnemonic {
+                path,
+                words,
+                scan_indices,
+                ..
+            } => {
+                println!(
+                    "Mode      : BIP-39 mnemonic ({} words, path {})",
+                    words, path
+                );
+                if *scan_indices > 1 {
+                    println!(
+                        "Scan      : indices 0..{} under the parent path",
+                        scan_indices
+                    );
+                }
+            }
+            KeyMode::FixedMnemonic { path_string, .. } => println!(
+                "Mode      : fixed BIP-39 mnemonic (attempt N is index N of {})",
+                path_string
+            ),
+        }
+        if stream.permute {
+            println!("Order     : permuted attempt indices (--permute)");
+        }
+        if args.format.is_bech32() {
+            println!(
+                "Witness   : version {} ({})",
+                args.witness_version,
+                if args.witness_version == 0 {
+                    "Bech32"
+                } else {
+                    "Bech32m"
+                }
+            );
+        }
+        if key_mode.passphrase_used() {
+            println!("Passphrase: set (not shown)");
+        }
+        if args.start_attempt.is_some() || args.end_attempt.is_some() {
+            let end = if max_attempts == u64::MAX {
+                unlimited(args.ascii).to_string()
+            } else {
+                max_attempts.to_string()
+            };
+            println!(
+                "Range     : attempts {}..{}{}",
+                resume_attempt,
+                end,
+                match (max_attempts, &resume_checkpoint) {
+                    (u64::MAX, _) => String::new(),
+                    (_, Some(_)) if args.start_attempt.is_none() => format!(
+                        " ({} left, start from the checkpoint)",
+                        max_attempts - resume_attempt
+                    ),
+                    _ => format!(" ({} to search)", max_attempts - resume_attempt),
+                }
+            );
+        } else if resume_attempt > 0 {
+            println!("Start at  : attempt {}", resume_attempt);
+            if max_attempts != u64::MAX {
+                println!(
+                    "Budget    : attempts {}..{} ({} left of --attempts {})",
+                    resume_attempt,
+                    max_attempts,
+                    max_attempts - resume_attempt,
+                    max_attempts
+                );
+            }
+        }
+        if let (None, Some(dir)) = (&resume_checkpoint, &args.resume_auto) {
+            println!(
+                "Resume    : no checkpoint of this search in {}, starting fresh",
+                dir.display()
+            );
+        }
+        if let Some((path, checkpoint)) = &resume_checkpoint {
+            println!("Resume    : {}", path.display());
+            if checkpoint.version >= 2 {
+                println!(
+                    "History   : {:.2?} spent, {} match(es) found since {}",
+                    Duration::from_millis(checkpoint.elapsed_ms),
+                    checkpoint.matches_found,
+                    checkpoint
+                        .created_at
+                        .as_deref()
+                        .unwrap_or("an unknown time")
+                );
+            }
+        }
+        if let Some(path) = &checkpoint_path {
+            println!(
+                "Checkpoint : {} (every {} attempts)",
+                path.display(),
+                args.checkpoint_interval.max(1)
+            );
+        }
+        if let Some(listener) = &metrics_listener {
+            match listener.local_addr() {
+                Ok(addr) => println!("Metrics   : http://{}/metrics", addr),
+                Err(_) => println!("Metrics   : {:?}", args.metrics_addr),
+            }
+        }
+        if args.stats_interval > 0 {
+            println!(
+                "Stats     : every {}s ({}){}",
+                args.stats_interval,
+                if args.stats_json { "json" } else { "text" },
+                match &args.stats_output {
+                    Some(path) if args.stats_append => format!(" appended to {}", path.display()),
+                    Some(path) => format!(" to {}", path.display()),
+                    None => String::new(),
+                }
+            );
+        }
+    }
+
+    config.stream = stream;
+    config.start_attempt = resume_attempt;
+    config.max_attempts = max_attempts;
+    config.count = remaining;
+    config.track_best = args.track_best;
+    config.shard = shard;
+    config.sample_every = args.sample_every;
+
+    // These are first written once the search is well under way (or over), so an
+    // unwritable location must fail now rather than after hours of work.
+    let output = (!args.output_stdout).then_some(&output_path);
+    for (path, what) in [
+        (output, "output"),
+        (checkpoint_path.as_ref(), "checkpoint"),
+        (args.summary_file.as_ref(), "summary"),
+    ] {
+        if let Some(path) = path {
+            probe_writable(path).with_context(|| format!("Invalid {} path", what))?;
+        }
+    }
+    if args.check {
+        // Opening an existing --stats-output pipe blocks until it has a reader, so only
+        // --check probes it.
+        if let Some(path) = &args.stats_output {
+            probe_writable(path).context("Invalid stats path")?;
+        }
+        match attempt_probability(&config)?.0 {
+            Some(p) if p <= 0.0 => {
+                return Err(anyhow!(
+                    "The pattern can never match: it conflicts with the fixed leading characters"
+                ))
+            }
+            Some(p) => println!("Odds      : {} per attempt", format_odds(p)),
+            None => println!("Odds      : unknown (regex)"),
+        }
+        println!("Config hash: {}", config_hash);
+        println!("Check     : ok, search not started");
+        return Ok(());
+    }
+
+    let checkpoint_writer = if let Some(path) = checkpoint_path.clone() {
+        let mut writer = CheckpointWriter::new(
+            path,
+            config_hash.clone(),
+            stream,
+            shard,
+            args.checkpoint_interval,
+        );
+        if let Some((_, checkpoint)) = &resume_checkpoint {
+            writer = writer.continuing(checkpoint);
+        }
+        writer.force_write(resume_attempt, 0)?;
+        Some(writer)
+    } else {
+        None
+    };
+
+    let progress = Arc::new(SearchProgress::new(resume_done));
+    let interrupted = Arc::new(AtomicBool::new(false));
+    {
+        let interrupted = Arc::clone(&interrupted);
+        let progress = Arc::clone(&progress);
+        ctrlc::set_handler(move || {
+            if interrupted.swap(true, Ordering::AcqRel) {
+                warn!("Second interrupt, exiting without saving.");
+                std::process::exit(130);
+            }
+            progress.stop.store(true, Ordering::Release);
+        })
+        .context("Failed to install Ctrl-C handler")?;
+    }
+    let start = Instant::now();
+    let reporter = StatsReporter {
+        json_mode: args.stats_json,
+        progress: Arc::clone(&progress),
+        resume_attempt: resume_done,
+        matches_target: remaining,
+        probability: attempt_probability(&config)?.0.filter(|p| *p > 0.0),
+        output: args
+            .stats_output
+            .clone()
+            .map(|path| Arc::new(StatsOutput::new(path, args.stats_append))),
+        start,
+    };
+    install_stats_signal(reporter.clone())?;
+    let metrics_handle = metrics_listener
+        .map(|listener| spawn_metrics_server(listener, reporter.clone()))
+        .transpose()?;
+    // The bar already shows the live rate, so the periodic stats lines are dropped with it.
+    // It would also redraw over --sample-every lines.
+    let bar = (!args.quiet
+        && !args.stats_json
+        && !args.no_progress_bar
+        && args.sample_every == 0
+        && io::stdout().is_terminal())
+    .then(|| progress_bar(resume_done, lane_budget, args.ascii));
+    // --quiet keeps JSON stats (they're meant for pipes) but drops the text ones.
+    // A --stats-output file is independent of the console, so it always gets records.
+    let stats_handle =
+        if args.stats_output.is_none() && (bar.is_some() || (args.quiet && !args.stats_json)) {
+            None
+        } else {
+            spawn_stats_thread(args.stats_interval, reporter)
+        };
+
+    let progress_handle = spawn_progress_thread(
+        Arc::clone(&progress),
+        resume_done,
+        args.progress_interval,
+        bar,
+        args.quiet,
+    );
+
+    // The pattern as prepared for the format a match is in.
+    let pattern_for = |format: AddressFormat| {
+        config
+            .extra_formats
+            .iter()
+            .find(|extra| extra.format == format)
+            .map_or(pattern, |extra| &extra.pattern)
+    };
+    let export_for = |candidate: &CandidateKey| match key_mode {
+        KeyMode::SplitKey { .. } => Ok(SecretExport::default()),
+        _ => export_secret(&candidate.secret, &args, &network, compressed),
+    };
+    // What the pattern ran against: the address, or the WIF with --match-wif.
+    let target_of = |m: &SearchMatch| {
+        if args.match_wif {
+            wif_from_secret(&m.candidate.secret, &network, compressed)
+        } else {
+            m.address.clone()
+        }
+    };
+    let report_for = |m: &SearchMatch, export: &SecretExport| {
+        let candidate = &m.candidate;
+        let target = target_of(m);
+        let pattern = pattern_for(m.format);
+        let split_base = match key_mode {
+            KeyMode::SplitKey { base } => Some(hex::encode(base.serialize())),
+            _ => None,
+        };
+        let secret_hex = format!("0x{}", hex::encode(candidate.secret.secret_bytes()));
+        // Extended keys only have xprv/tprv versions, and descriptors would pair them (or
+        // a custom WIF) with Bitcoin's, so custom networks get neither.
+        let preset = network.preset();
+        // A --fixed-mnemonic phrase is the user's whole wallet: a plaintext result keeps the
+        // path to the child, not the phrase or keys above it.
+        let phrase = candidate
+            .mnemonic
+            .as_deref()
+            .filter(|_| args.encrypt_output || !matches!(key_mode, KeyMode::FixedMnemonic { .. }));
+        let extended = phrase
+            .and_then(|phrase| mnemonic_xprv(phrase, key_mode, candidate.child_index))
+            .zip(preset)
+            .map(|(xprv, preset)| extended_keys(&xprv, preset));
+        // Mnemonic keys import as their whole branch; derived keys are always compressed.
+        let descriptor_key = match (phrase, key_mode, preset) {
+            (_, _, None) => None,
+            (
+                Some(phrase),
+                KeyMode::Mnemonic { path, .. } | KeyMode::FixedMnemonic { path, .. },
+                Some(preset),
+            ) if compressed => mnemonic_master(phrase, key_mode)
+                .map(|master| ranged_key(&extended_keys(&master, preset).0, path)),
+            _ => export.wif.clone(),
+        };
+        // The pattern only ever ran on the primary encoding; this is a record for recovery.
+        let other_compression = args
+            .emit_both_compressions
+            .then(|| {
+                let mut address = String::new();
+                encode_public_address(
+                    &candidate.public,
+                    AddressFormat::P2pkh,
+                    0,
+                    !compressed,
+                    &network,
+                    &mut address,
+                )
+                .ok()?;
+                Some(OtherCompression {
+                    compressed: !compressed,
+                    address,
+                    public_key_hex: if compressed {
+                        hex::encode(candidate.public.serialize_uncompressed())
+                    } else {
+                        hex::encode(candidate.public.serialize())
+                    },
+                    wif: export
+                        .wif
+                        .as_ref()
+                        .map(|_| wif_from_secret(&candidate.secret, &network, !compressed)),
+                })
+            })
+            .flatten();
+        VanityResult {
+            schema_version: RESULT_SCHEMA_VERSION,
+            private_key_hex: (split_base.is_none() && !args.bip38_only).then(|| secret_hex.clone()),
+            wif: export.wif.clone(),
+            bip38: export.bip38.clone(),
+            split_key_offset: split_base.is_some().then_some(secret_hex),
+            split_key_base: split_base,
+            address: m.address.clone(),
+            public_key_hex: if compressed {
+                hex::encode(candidate.public.serialize())
+            } else {
+                hex::encode(candidate.public.serialize_uncompressed())
+            },
+            format: format!("{:?}", m.format),
+            witness_version: m.format.is_bech32().then_some(args.witness_version),
+            witness_script: (m.format == AddressFormat::P2wsh)
+                .then(|| hex::encode(p2wsh_witness_script(&candidate.public))),
+            compressed,
+            other_compression,
+            network: network.name(),
+            network_params: network.preset().is_none().then(|| network.clone()),
+            attempts: m.attempts,
+            attempt_index: m.attempts - 1,
+            attempts_limit: if max_attempts == u64::MAX {
+                None
+            } else {
+                Some(max_attempts)
+            },
+            seed: stream.seed,
+            seed_root_id: stream.seed.is_none().then(|| stream.root_id()),
+            config_hash: Some(config_hash.clone()),
+            prefix: pattern.prefix.clone(),
+            prefix_offset: args.prefix_offset,
+            target: args.target.clone(),
+            suffix: pattern.suffix.clone(),
+            any_prefix: pattern.any_prefix.clone(),
+            any_suffix: pattern.any_suffix.clone(),
+            matched_prefix: matched_any_prefix(&target, pattern).map(str::to_string),
+            matched_suffix: matched_any_suffix(&target, pattern).map(str::to_string),
+            contains: pattern.contains.clone(),
+            exclude: pattern.exclude.clone(),
+            regex: pattern.regex.as_ref().map(|re| re.as_str().to_string()),
+            hash_prefix: pattern.hash_prefix.as_ref().map(|hash| hash.hex.clone()),
+            address_hash: pattern.hash_prefix.as_ref().and_then(|_| {
+                let mut payload = [0u8; 32];
+                let len = address_payload(
+                    &candidate.public,
+                    m.format,
+                    args.witness_version,
+                    compressed,
+                    &mut payload,
+                )
+                .ok()?;
+                Some(hex::encode(&payload[..len]))
+            }),
+            leading_char: pattern.leading.map(|run| run.char),
+            leading_run: pattern
+                .leading
+                .map(|run| leading_run(&target, pattern.fixed_len, run.char)),
+            matched_word: pattern.wordlist.as_ref().and_then(|list| {
+                list.find(target.get(pattern.fixed_len..).unwrap_or(""))
+                    .map(str::to_string)
+            }),
+            mnemonic: phrase.map(str::to_string),
+            hd_path: key_mode.candidate_path(candidate),
+            hd_index: candidate.child_index,
+            xprv: extended.as_ref().map(|(xprv, _)| xprv.clone()),
+            xpub: extended.map(|(_, xpub)| xpub),
+            descriptor: descriptor_key
+                .filter(|_| !args.bip38_only)
+                .and_then(|key| descriptor(m.format, args.witness_version, &key)),
+            passphrase_used: key_mode.passphrase_used(),
+            near_miss_score: None,
+            ignore_case: pattern.ignore_case,
+            match_wif: args.match_wif,
+            permuted: stream.permute,
+        }
+    };
+    // Each match is saved as the search finds it, before a checkpoint can move past it; the
+    // details printed afterwards reuse the saved report.
+    let saved_reports = Mutex::new(HashMap::new());
+    let save_match = |m: &SearchMatch| -> Result<()> {
+        let export = export_for(&m.candidate)?;
+        let report = report_for(m, &export);
+        // A resumed search finds again what was saved after its checkpoint.
+        let written = if prior.addresses.contains(&m.address) {
+            Ok(())
+        } else {
+            append_result_file(
+                &output_path,
+                &report,
+                args.output_format,
+                password.as_deref(),
+            )
+        };
+        lock_or_recover(&saved_reports).insert(m.address.clone(), (export, report));
+        written
+    };
+    let on_match: Option<&MatchSink> = (!args.output_stdout).then_some(&save_match);
+
+    let deadline = max_time.map(|limit| start + limit);
+    let outcome = search(
+        &config,
+        &progress,
+        checkpoint_writer.as_ref(),
+        on_match,
+        deadline,
+    );
+
+    progress.stop.store(true, Ordering::Release);
+    if let Some(handle) = stats_handle {
+        let _ = handle.join();
+    }
+    if let Some(handle) = metrics_handle {
+        let _ = handle.join();
+    }
+    let _ = progress_handle.join();
+    let outcome = outcome?;
+    let timed_out = deadline.is_some_and(|deadline| Instant::now() >= deadline);
+
+    // Reported totals span every resumed session, like `attempts`.
+    let prior_elapsed = resume_checkpoint
+        .as_ref()
+        .map_or(Duration::ZERO, |(_, checkpoint)| {
+            Duration::from_millis(checkpoint.elapsed_ms)
+        });
+    let elapsed = prior_elapsed + start.elapsed();
+    let attempts_made = outcome.attempts_made;
+    let outcome_next_attempt = outcome.next_attempt;
+    let matches = outcome.matches;
+    // A match that couldn't be saved stopped the search; it is printed but not counted.
+    let unsaved = matches.iter().filter(|m| m.save_error.is_some()).count() as u64;
+    let found_total = already_found + matches.len() as u64 - unsaved;
+    // --output-stdout keeps stdout for the results: status lines move to stderr and the
+    // printed key details give way to the JSON.
+    let status = |line: String| {
+        if args.output_stdout {
+            eprintln!("{line}");
+        } else {
+            println!("{line}");
+        }
+    };
+    if !matches.is_empty() {
+        if !args.quiet {
+            status(String::new());
+        }
+        if args.count > 1 {
+            status(format!(
+                "Found {} of {} vanity keys after {} attempts ({:.2?})",
+                found_total, args.count, attempts_made, elapsed
+            ));
+        }
+    }
+    let mut saved = false;
+    if args.output_stdout {
+        if let (1, Some(m)) = (args.count, matches.first()) {
+            status(format!(
+                "Found vanity key after {} attempts ({:.2?})",
+                m.attempts, elapsed
+            ));
+        }
+        let reports = matches
+            .iter()
+            .map(|m| Ok(report_for(m, &export_for(&m.candidate)?)))
+            .collect::<Result<Vec<_>>>()?;
+        if !reports.is_empty() {
+            write_results(&mut io::stdout().lock(), &reports, args.output_format)?;
+        }
+    }
+    for m in matches.iter().filter(|_| !args.output_stdout) {
+        let candidate = &m.candidate;
+        let saved_report = lock_or_recover(&saved_reports).remove(&m.address);
+        let (export, report) = match saved_report {
+            Some(saved) => saved,
+            None => {
+                let export = export_for(candidate)?;
+                let report = report_for(m, &export);
+                (export, report)
+            }
+        };
+        if args.count == 1 {
+            println!(
+                "Found vanity key after {} attempts ({:.2?})",
+                m.attempts, elapsed
+            );
+        } else {
+            println!();
+            println!("Match at attempt {}", m.attempts);
+        }
+        println!(
+            "Index     : {} (--derive-attempt {}{} with this seed re-derives it)",
+            m.attempts - 1,
+            m.attempts - 1,
+            if stream.permute { " --permute" } else { "" }
+        );
+        // An encrypted run keeps keys off stdout, unless this one has no other copy.
+        let show_secrets = !args.encrypt_output || m.save_error.is_some();
+        print_candidate(
+            candidate,
+            &m.address,
+            key_mode,
+            m.format,
+            args.witness_version,
+            &export,
+            show_secrets,
+        );
+        if let Some(mode) = args.qr {
+            let mnemonic = candidate.mnemonic.as_deref().filter(|_| args.qr_mnemonic);
+            if let Err(err) = emit_qr(
+                mode,
+                &output_path,
+                &m.address,
+                &export,
+                mnemonic,
+                args.encrypt_output,
+                args.ascii,
+            ) {
+                error!("Failed to render QR codes for {}: {err:?}", m.address);
+            }
+        }
+        if let Some(other) = &report.other_compression {
+            let encoding = if other.compressed {
+                "compressed"
+            } else {
+                "uncompressed"
+            };
+            println!("Other key : {} ({})", other.address, encoding);
+            if let Some(wif) = other.wif.as_ref().filter(|_| show_secrets) {
+                println!("Other WIF : {}", wif);
+            }
+        }
+        match &m.save_error {
+            None => {
+                saved = true;
+                println!("Result saved to {}", output_path.display());
+            }
+            Some(err) => error!(
+                "Failed to write result file {}: {err:?}; the key printed above is the only copy",
+                output_path.display()
+            ),
+        }
+    }
+    if unsaved > 0 {
+        status(String::new());
+        status(format!(
+            "Stopped after {} attempts ({:.2?}): {} of {} matches saved, {} could not be.",
+            attempts_made, elapsed, found_total, args.count, unsaved
+        ));
+    } else if found_total >= args.count {
+        // Every requested match was found and reported above.
+    } else if interrupted.load(Ordering::Acquire) {
+        status(String::new());
+        status(format!(
+            "Interrupted after {} attempts ({:.2?}) with {} of {} matches.",
+            attempts_made, elapsed, found_total, args.count
+        ));
+    } else if timed_out {
+        status(String::new());
+        status(format!(
+            "Time limit reached after {} attempts ({:.2?}) with {} of {} matches.",
+            attempts_made, elapsed, found_total, args.count
+        ));
+    } else if found_total == 0 {
+        status(String::new());
+        status(format!(
+            "No vanity key found after {} attempts ({:.2?}). Increase --attempts or relax the pattern.",
+            attempts_made, elapsed
+        ));
+    } else {
+        status(String::new());
+        status(format!(
+            "Only {} of {} vanity keys found after {} attempts ({:.2?}).",
+            found_total, args.count, attempts_made, elapsed
+        ));
+    }
+    // Should never happen for raw keys (a chance of about 2^-128 per attempt), so a
+    // nonzero count points at a broken key schedule.
+    if outcome.discarded > 0 {
+        status(format!(
+            "Discarded : {} attempts derived no valid key (counted in attempts, not in the odds)",
+            outcome.discarded
+        ));
+    }
+    for (start, end) in &outcome.untested {
+        status(format!(
+            "Untested  : attempts {}..{} (a search worker panicked); a resumed search retries them",
+            start, end
+        ));
+    }
+
+    if !outcome.best.is_empty() {
+        // Kept apart from the real matches so tools reading the result file never see them.
+        let best_path = output_path.with_extension(format!(
+            "best.{}",
+            output_path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .unwrap_or("json")
+        ));
+        let prefix_len = pattern.prefix.as_deref().map_or(0, str::len);
+        println!();
+        match &args.target {
+            Some(target) => println!("Closest to {} (leading characters shared):", target),
+            None => println!("Closest near misses (prefix characters matched):"),
+        }
+        for m in &outcome.best {
+            let target = target_of(m);
+            let score = prefix_score(&target, pattern);
+            println!(
+                "  {score}/{prefix_len}  {}  (attempt {})",
+                target, m.attempts
+            );
+            let mut report = report_for(m, &export_for(&m.candidate)?);
+            report.near_miss_score = Some(score);
+            if let Err(err) =
+                append_result_file(&best_path, &report, args.output_format, password.as_deref())
+            {
+                error!(
+                    "Failed to write near-miss file {}: {err:?}",
+                    best_path.display()
+                );
+                break;
+            }
+        }
+        println!("Near misses saved to {}", best_path.display());
+    }
+
+    if let Some(writer) = checkpoint_writer.as_ref() {
+        let saved_below = matches
+            .iter()
+            .filter(|m| m.attempts <= outcome_next_attempt && m.save_error.is_none())
+            .count();
+        writer.force_write(outcome_next_attempt, saved_below as u64)?;
+        if interrupted.load(Ordering::Acquire) {
+            println!(
+                "Interrupted, checkpoint saved at attempt {} ({})",
+                outcome_next_attempt,
+                writer.path().display()
+            );
+        } else if timed_out && found_total < args.count {
+            println!(
+                "Checkpoint saved at attempt {} ({}); --resume it to continue",
+                outcome_next_attempt,
+                writer.path().display()
+            );
+        }
+    }
+
+    let found = found_total >= args.count;
+    let stop_reason = if found {
+        "found"
+    } else if unsaved > 0 {
+        "save_failed"
+    } else if interrupted.load(Ordering::Acquire) {
+        "interrupted"
+    } else if timed_out {
+        "time_limit"
+    } else {
+        "exhausted"
+    };
+    if args.record_misses && !found {
+        let miss = SearchMiss {
+            schema_version: RESULT_SCHEMA_VERSION,
+            found,
+            stop_reason: stop_reason.to_string(),
+            matches: found_total,
+            matches_target: args.count,
+            attempts: attempts_made,
+            attempts_limit: (max_attempts != u64::MAX).then_some(max_attempts),
+            elapsed_ms: elapsed.as_millis() as u64,
+            next_attempt: outcome_next_attempt,
+            checkpoint: checkpoint_writer
+                .as_ref()
+                .map(|writer| writer.path().display().to_string()),
+            format: format!("{:?}", args.format),
+            witness_version: args.format.is_bech32().then_some(args.witness_version),
+            compressed,
+            network: network.name(),
+            seed: stream.seed,
+            seed_root_id: stream.seed.is_none().then(|| stream.root_id()),
+            config_hash: Some(config_hash.clone()),
+            prefix: pattern.prefix.clone(),
+            suffix: pattern.suffix.clone(),
+            contains: pattern.contains.clone(),
+            regex: pattern.regex.as_ref().map(|re| re.as_str().to_string()),
+            hd_path: key_mode.path_string().map(str::to_string),
+        };
+        match append_search_miss(&output_path, &miss, args.output_format, password.as_deref()) {
+            Ok(()) => status(format!("Miss recorded in {}", output_path.display())),
+            Err(err) => error!(
+                "Failed to record the miss in {}: {err:?}",
+                output_path.display()
+            ),
+        }
+    }
+    if args.summary_json {
+        let session_secs = start.elapsed().as_secs_f64().max(f64::EPSILON);
+        let summary = RunSummary {
+            found,
+            stop_reason,
+            matches: found_total,
+            matches_target: args.count,
+            attempts: attempts_made,
+            elapsed_ms: elapsed.as_millis(),
+            rate: attempts_made.saturating_sub(resume_done) as f64 / session_secs,
+            discarded: outcome.discarded,
+            result_path: saved.then(|| output_path.clone()),
+            checkpoint_path: checkpoint_writer
+                .as_ref()
+                .map(|writer| writer.path().to_path_buf()),
+        };
+        let line = serde_json::to_string(&summary)?;
+        match &args.summary_file {
+            Some(path) => write_atomic(path, format!("{}\n", line).as_bytes())
+                .with_context(|| format!("Failed to write summary {}", path.display()))?,
+            None => eprintln!("{}", line),
+        }
+    }
+    if unsaved > 0 {
+        return Err(anyhow!(
+            "Could not save {} match(es) to {}; the keys printed above are the only copies",
+            unsaved,
+            output_path.display()
+        ));
+    }
+    if args.fail_on_miss && !found {
+        // Distinct from the status 1 of an error, so scripts can tell a miss from a failure.
+        std::process::exit(2);
+    }
+
+    Ok(())
+}
+
+// What earlier sessions of this search saved to `path`.
+#[derive(Default)]
+struct PriorMatches {
+    // Matches below the checkpoint, which the resumed search won't test again.
+    count: u64,
+    // Addresses saved above it, which it will find again and mustn't save twice.
+    addresses: HashSet<String>,
+}
+
+// CSV rows carry no config hash, so the count falls back to the checkpoint's own and every
+// row's address is kept.
+fn prior_matches(
+    path: &Path,
+    format: OutputFormat,
+    password: Option<&str>,
+    config_hash: &str,
+    checkpoint: &CheckpointFile,
+) -> Result<PriorMatches> {
+    let context = || format!("Cannot count earlier matches in {}", path.display());
+    if format == OutputFormat::Csv {
+        let addresses = if path.exists() {
+            csv_addresses(path).with_context(context)?
+        } else {
+            Vec::new()
+        };
+        return Ok(PriorMatches {
+            count: checkpoint.matches_found,
+            addresses: addresses.into_iter().collect(),
+        });
+    }
+    if !path.exists() {
+        return Ok(PriorMatches::default());
+    }
+    let password = || {
+        password
+            .map(str::to_string)
+            .ok_or_else(|| anyhow!("{} is encrypted", path.display()))
+    };
+    let (below, above): (Vec<_>, Vec<_>) = config_matches(path, password, config_hash)
+        .with_context(context)?
+        .into_iter()
+        .partition(|(index, _)| *index < checkpoint.next_attempt);
+    Ok(PriorMatches {
+        count: below.len() as u64,
+        addresses: above.into_iter().map(|(_, address)| address).collect(),
+    })
+}
+
+struct LoadedConfig {
+    path: PathBuf,
+    // Keys taken from the file, sorted (CLI-overridden keys excluded).
+    applied: Vec<String>,
+}
+
+// Config values are turned back into `--flag value` arguments and placed before the real
+// command line, so clap validates both identically and explicit flags take precedence.
+fn parse_args() -> Result<(Args, Option<LoadedConfig>)> {
+    let cli: Vec<OsString> = std::env::args_os().collect();
+    let command = Args::command();
+    let matches = command.clone().get_matches_from(&cli);
+    let Some(path) = matches.get_one::<PathBuf>("config").cloned() else {
+        return Ok((Args::from_arg_matches(&matches)?, None));
+    };
+
+    let raw = fs::read_to_string(&path)
+        .with_context(|| format!("Unable to read config {}", path.display()))?;
+    let table: toml::Table = raw
+        .parse()
+        .with_context(|| format!("Invalid TOML in config {}", path.display()))?;
+
+    let mut injected: Vec<OsString> = Vec::new();
+    let mut applied = Vec::new();
+    for (key, value) in &table {
+        let id = key.replace('-', "_");
+        if id == "config" || !command.get_arguments().any(|a| a.get_id() == id.as_str()) {
+            return Err(anyhow!(
+                "Unknown key '{}' in config {}",
+                key,
+                path.display()
+            ));
+        }
+        if matches.value_source(&id) == Some(ValueSource::CommandLine) {
+            continue;
+        }
+        let flag = format!("--{}", id.replace('_', "-"));
+        let values = match value {
+            toml::Value::Array(items) => items.clone(),
+            other => vec![other.clone()],
+        };
+        for item in values {
+            match item {
+                toml::Value::Boolean(true) => injected.push(flag.clone().into()),
+                toml::Value::Boolean(false) => {}
+                toml::Value::String(text) => {
+                    injected.push(flag.clone().into());
+                    injected.push(text.into());
+                }
+                toml::Value::Integer(_) | toml::Value::Float(_) => {
+                    injected.push(flag.clone().into());
+                    injected.push(item.to_string().into());
+                }
+                _ => {
+                    return Err(anyhow!(
+                        "Unsupported value for '{}' in config {}",
+                        key,
+                        path.display()
+                    ))
+                }
+            }
+        }
+        applied.push(key.clone());
+    }
+
+    let mut argv = Vec::with_capacity(cli.len() + injected.len());
+    argv.push(cli[0].clone());
+    argv.extend(injected);
+    argv.extend(cli.into_iter().skip(1));
+    let args = Args::try_parse_from(argv)
+        .with_context(|| format!("Invalid settings in config {}", path.display()))?;
+    Ok((args, Some(LoadedConfig { path, applied })))
+}
+
+// Everything here comes from the tables the encoders and pattern checks use: the charsets
+// are whatever `ensure_charset` accepts.
+fn print_formats(custom: &NetworkParams, compressed: bool) -> Result<()> {
+    let networks = match custom.preset() {
+        Some(_) => [Network::Mainnet, Network::Testnet, Network::Regtest]
+            .map(Network::params)
+            .to_vec(),
+        None => vec![custom.clone()],
+    };
+    // One character of a set is shown as itself, several as `[mn]`.
+    let one_of = |chars: String| {
+        if chars.chars().count() == 1 {
+            chars
+        } else {
+            format!("[{}]", chars)
+        }
+    };
+    println!(
+        "{:<8} {:<12} {:<12} {:<8} {:<9} Charset",
+        "Network", "Format", "HRP/version", "Fixed", "Length"
+    );
+    for network in &networks {
+        let rows = [
+            (AddressFormat::P2pkh, 0),
+            (AddressFormat::P2shP2wpkh, 0),
+            (AddressFormat::Bech32, 0),
+            (AddressFormat::Bech32, 1),
+            (AddressFormat::P2wsh, 0),
+        ];
+        for (format, witness_version) in rows {
+            let name = format
+                .to_possible_value()
+                .map(|value| value.get_name().to_string())
+                .unwrap_or_default();
+            let length = max_encoded_len(format, witness_version, true, network, false);
+            let (name, version, fixed, length, charset) =
+                match address_leading_chars(format, network) {
+                    Some(leading) => {
+                        let version = match format {
+                            AddressFormat::P2pkh => network.p2pkh_version,
+                            _ => network.p2sh_version,
+                        };
+                        (
+                            name,
+                            format!("0x{:02x}", version),
+                            one_of(leading),
+                            format!("up to {}", length),
+                            "base58",
+                        )
+                    }
+                    None => (
+                        match format {
+                            AddressFormat::Bech32 => format!("{} v{}", name, witness_version),
+                            _ => name,
+                        },
+                        network.hrp.clone(),
+                        bech32_fixed_prefix(network, witness_version),
+                        length.to_string(),
+                        "bech32",
+                    ),
+                };
+            println!(
+                "{:<8} {:<12} {:<12} {:<8} {:<9} {}",
+                network.name(),
+                name,
+                version,
+                fixed,
+                length,
+                charset
+            );
+        }
+        println!(
+            "{:<8} {:<12} {:<12} {:<8} {:<9} base58",
+            network.name(),
+            if compressed { "wif" } else { "wif (uncomp)" },
+            format!("0x{:02x}", network.wif_version),
+            one_of(wif_leading_chars(network, compressed)),
+            format!(
+                "up to {}",
+                max_encoded_len(AddressFormat::P2pkh, 0, compressed, network, true)
+            )
+        );
+    }
+    let charset = |format| -> String {
+        ('!'..='~')
+            .filter(|c| ensure_charset(&c.to_string(), format).is_ok())
+            .collect()
+    };
+    println!();
+    println!(
+        "base58 : {} (case-sensitive unless --ignore-case)",
+        charset(AddressFormat::P2pkh)
+    );
+    println!(
+        "bech32 : {} (patterns are lowercased)",
+        charset(AddressFormat::Bech32)
+    );
+    println!("Patterns start with the fixed part (--prefix-offset skips it on Bech32).");
+    println!("Bech32 v2-16 share v1's length with their own version character.");
+    println!("wif is what --match-wif patterns are checked against.");
+    Ok(())
+}
+
+// `derive-material`: the raw-key schedule written out one step at a time, each from the
+// same functions a search uses.
+fn print_material(args: &Args, stream: &KeyStream, attempt: u64) -> Result<()> {
+    let network = network_params(args)?;
+    let compressed = !args.uncompressed;
+    match stream.seed {
+        Some(seed) => println!(
+            "Root      : {} (SHA-256 of \"vanity_bitcoin/seed-root\" || seed {} as 8 bytes LE)",
+            hex::encode(stream.root),
+            seed
+        ),
+        None => println!(
+            "Root      : {} (--seed-hex, as given)",
+            hex::encode(stream.root)
+        ),
+    }
+    let block = if stream.permute {
+        let block = permute_attempt(&stream.root, attempt);
+        println!(
+            "Permuted  : attempt {} reads block {} (4-round Feistel keyed by SHA-256 of \"vanity_bitcoin/permute\" || root)",
+            attempt, block
+        );
+        block
+    } else {
+        attempt
+    };
+    println!(
+        "Keystream : ChaCha20 (original 64-bit counter/nonce layout), key = root, nonce = 0, block counter = {}",
+        block
+    );
+    let mut material = [0u8; 32];
+    key_material_from_attempt(stream, attempt, &mut material);
+    println!(
+        "Material  : {} (first 32 of the block's 64 bytes)",
+        hex::encode(material)
+    );
+    let candidate = derive_candidate(stream, attempt, &KeyMode::Raw).ok_or_else(|| {
+        anyhow!(
+            "The material is zero or not below the curve order: attempt {} has no key and searches discard it",
+            attempt
+        )
+    })?;
+    if candidate.secret.secret_bytes() != material {
+        return Err(anyhow!(
+            "derive_candidate disagrees with key_material_from_attempt"
+        ));
+    }
+    println!(
+        "SecretHex : 0x{} (the material as a big-endian scalar, used unchanged)",
+        hex::encode(candidate.secret.secret_bytes())
+    );
+    let public = if compressed {
+        hex::encode(candidate.public.serialize())
+    } else {
+        hex::encode(candidate.public.serialize_uncompressed())
+    };
+    println!("Pubkey    : {} (secret x G)", public);
+    let mut address = String::with_capacity(64);
+    encode_public_address(
+        &candidate.public,
+        args.format,
+        args.witness_version,
+        compressed,
+        &network,
+        &mut address,
+    )?;
+    println!(
+        "Address   : {} ({:?}, {})",
+        address,
+        args.format,
+        network.name()
+    );
+    println!(
+        "WIF       : {}",
+        wif_from_secret(&candidate.secret, &network, compressed)
+    );
+    Ok(())
+}
+
+fn run_selftest(samples: u64, seed: u64) -> Result<()> {
+    println!("Self-test seed {} ({} samples per case)", seed, samples);
+    let reports = selftest::run(&KeyStream::from_seed(seed), samples);
+    let mut failed = 0;
+    for report in &reports {
+        match &report.result {
+            Ok(()) => println!("ok   {}", report.label),
+            Err(err) => {
+                failed += 1;
+                println!("FAIL {}: {:#}", report.label, err);
+            }
+        }
+    }
+    let candidates: u64 = reports.iter().map(|r| r.candidates).sum();
+    println!(
+        "Self-test: {} cases, {} candidates, {} failed",
+        reports.len(),
+        candidates,
+        failed
+    );
+    if failed > 0 {
+        return Err(anyhow!(
+            "{} of {} self-test cases failed (rerun with selftest --seed {})",
+            failed,
+            reports.len(),
+            seed
+        ));
+    }
+    Ok(())
+}
+
+// Entries are streamed, so multi-gigabyte files verify in constant memory.
+fn run_verify(path: &Path, passphrase: Option<&str>) -> Result<()> {
+    let (mut passed, mut failed, mut skipped) = (0, 0, 0);
+    let total = for_each_result_entry(
+        path,
+        || read_password(false),
+        |entry| {
+            let index = passed + failed + skipped + 1;
+            let address = entry["address"].as_str().unwrap_or("?");
+            match verify_entry(&entry, passphrase) {
+                Ok(Verdict::Pass) => {
+                    passed += 1;
+                    println!("ok   #{} {}", index, address);
+                }
+                Ok(Verdict::Skipped(reason)) => {
+                    skipped += 1;
+                    println!("skip #{} {} ({})", index, address, reason);
+                }
+                Err(err) => {
+                    failed += 1;
+                    println!("FAIL #{} {}: {:#}", index, address, err);
+                }
+            }
+            Ok(())
+        },
+    )
+    .with_context(|| {
+        format!(
+            "Unsupported result file {} (csv can't be verified)",
+            path.display()
+        )
+    })?;
+    println!(
+        "Verified {} entries: {} passed, {} failed, {} skipped",
+        total, passed, failed, skipped
+    );
+    if failed > 0 {
+        return Err(anyhow!(
+            "{} of {} entries failed verification",
+            failed,
+            total
+        ));
+    }
+    Ok(())
+}
 
-    let config_hash = hex::encode(config_fingerprint(
-        base_seed,
-        &prefix,
-        &suffix,
-        key_mode.as_ref(),
-        args.format,
-        args.witness_version,
-    ));
+const PASSWORD_ENV: &str = "VANITY_BITCOIN_PASSWORD";
 
-    if let Some((_, checkpoint)) = &resume_checkpoint {
-        if checkpoint.config_hash != config_hash {
-            return Err(anyhow!(
-                "Checkpoint was created for different search parameters."
-            ));
+// Non-interactive runs can set VANITY_BITCOIN_PASSWORD instead of answering the prompt.
+fn read_password(confirm: bool) -> Result<String> {
+    if let Ok(password) = std::env::var(PASSWORD_ENV) {
+        if password.is_empty() {
+            return Err(anyhow!("{PASSWORD_ENV} is set but empty"));
         }
+        return Ok(password);
     }
-
-    if resume_attempt >= max_attempts {
-        println!("Checkpoint already exhausted the requested attempt budget.");
-        return Ok(());
+    let password = rpassword::prompt_password("Password: ").context("Failed to read password")?;
+    if password.is_empty() {
+        return Err(anyhow!("Password must not be empty"));
     }
+    if confirm {
+        let again =
+            rpassword::prompt_password("Confirm password: ").context("Failed to read password")?;
+        if again != password {
+            return Err(anyhow!("Passwords do not match"));
+        }
+    }
+    Ok(password)
+}
+
+// --prompt: the prefix and suffix, blank for none. Piped input gives one per line.
+fn prompt_pattern(hide_input: bool) -> Result<(Option<String>, Option<String>)> {
+    let stdin = io::stdin();
+    let interactive = stdin.is_terminal();
+    let read = |label: &str| -> Result<Option<String>> {
+        let mut line = String::new();
+        if interactive && hide_input {
+            line = rpassword::prompt_password(format!("{label} (blank for none): "))
+                .with_context(|| format!("Failed to read the {}", label.to_lowercase()))?;
+        } else {
+            if interactive {
+                eprint!("{label} (blank for none): ");
+                io::stderr().flush()?;
+            }
+            stdin
+                .lock()
+                .read_line(&mut line)
+                .with_context(|| format!("Failed to read the {}", label.to_lowercase()))?;
+        }
+        let value = line.trim();
+        Ok((!value.is_empty()).then(|| value.to_string()))
+    };
+    Ok((read("Prefix")?, read("Suffix")?))
+}
 
-    println!("Searching for Bitcoin vanity key ({:?})...", args.format);
-    if let Some(p) = &prefix {
-        println!("Prefix    : {}", p);
+// The key stream picked by --seed, --seed-hex or --seed-file, if any.
+fn provided_stream(args: &Args) -> Result<Option<KeyStream>> {
+    if let Some(seed) = args.seed {
+        return Ok(Some(KeyStream::from_seed(seed)));
+    }
+    if let Some(hex) = &args.seed_hex {
+        return KeyStream::from_root_hex(hex)
+            .context("Invalid --seed-hex")
+            .map(Some);
     }
-    if let Some(s) = &suffix {
-        println!("Suffix    : {}", s);
+    let Some(path) = &args.seed_file else {
+        return Ok(None);
+    };
+    let bytes =
+        fs::read(path).with_context(|| format!("Unable to read --seed-file {}", path.display()))?;
+    let stream = match <[u8; 32]>::try_from(bytes.as_slice()) {
+        // 32 bytes of hex text would be only half a root, so this can't be ambiguous.
+        Ok(root) if !root.iter().all(u8::is_ascii_hexdigit) => KeyStream {
+            root,
+            seed: None,
+            permute: false,
+        },
+        _ => KeyStream::from_root_hex(&String::from_utf8_lossy(&bytes))
+            .with_context(|| format!("Invalid --seed-file {}", path.display()))?,
+    };
+    Ok(Some(stream))
+}
+
+// --network's parameters, or mainnet's with any --hrp/--*-version overrides applied.
+fn network_params(args: &Args) -> Result<NetworkParams> {
+    let mut params = args.network.params();
+    let byte = |flag: &str, value: &Option<String>, current: u8| -> Result<u8> {
+        let Some(value) = value else {
+            return Ok(current);
+        };
+        let parsed = match value
+            .strip_prefix("0x")
+            .or_else(|| value.strip_prefix("0X"))
+        {
+            Some(hex) => u8::from_str_radix(hex, 16),
+            None => value.parse(),
+        };
+        parsed.map_err(|_| {
+            anyhow!(
+                "{} must be a byte (0-255 or 0x00-0xff), got '{}'",
+                flag,
+                value
+            )
+        })
+    };
+    params.p2pkh_version = byte("--p2pkh-version", &args.p2pkh_version, params.p2pkh_version)?;
+    params.p2sh_version = byte("--p2sh-version", &args.p2sh_version, params.p2sh_version)?;
+    params.wif_version = byte("--wif-version", &args.wif_version, params.wif_version)?;
+    if let Some(hrp) = &args.hrp {
+        validate_hrp(hrp).context("Invalid --hrp")?;
+        params.hrp = hrp.clone();
     }
-    let max_display = if max_attempts == u64::MAX {
-        "∞".to_string()
+    Ok(params)
+}
+
+fn parse_secret(value: &str) -> Result<SecretKey> {
+    let bytes = hex::decode(value.trim_start_matches("0x"))?;
+    SecretKey::from_slice(&bytes).map_err(|_| anyhow!("not a valid 32-byte secp256k1 secret"))
+}
+
+// Chance that one attempt matches (None for --regex), plus the sample address (or WIF with
+// --match-wif) it was computed from.
+fn attempt_probability(config: &SearchConfig) -> Result<(Option<f64>, String)> {
+    let sample = derive_candidate(&config.stream, 0, &config.key_mode)
+        .ok_or_else(|| anyhow!("Failed to derive a sample key"))?;
+    let network = &config.network;
+    let (address, format, range) = if config.pattern.match_wif {
+        (
+            wif_from_secret(&sample.secret, network, config.compressed),
+            AddressFormat::P2pkh,
+            Some(wif_range(network, config.compressed)),
+        )
     } else {
-        max_attempts.to_string()
+        let mut address = String::with_capacity(64);
+        encode_public_address(
+            &sample.public,
+            config.format,
+            config.witness_version,
+            config.compressed,
+            network,
+            &mut address,
+        )?;
+        (
+            address,
+            config.format,
+            address_range(config.format, network),
+        )
     };
-    println!("Max tries : {}", max_display);
-    println!("Threads   : {}", threads);
-    match (&resume_checkpoint, args.seed) {
-        (Some(_), _) => println!("RNG seed  : {} (from checkpoint)", base_seed),
-        (None, Some(seed)) => println!("RNG seed  : {} (user supplied)", seed),
-        (None, None) => println!("RNG seed  : {} (randomized)", base_seed),
+    let Some(mut per_address) =
+        match_probability(&config.pattern, format, &address, range.as_ref())
+    else {
+        return Ok((None, address));
+    };
+    // Each extra format is one more chance per key, treated as independent.
+    for extra in &config.extra_formats {
+        let mut encoded = String::with_capacity(64);
+        encode_public_address(
+            &sample.public,
+            extra.format,
+            config.witness_version,
+            config.compressed,
+            network,
+            &mut encoded,
+        )?;
+        let range = address_range(extra.format, network);
+        let Some(p) = match_probability(&extra.pattern, extra.format, &encoded, range.as_ref())
+        else {
+            return Ok((None, address));
+        };
+        per_address = 1.0 - (1.0 - per_address) * (1.0 - p);
     }
-    println!("Output    : {}", output_path.display());
-    match key_mode.as_ref() {
-        KeyMode::Raw => println!("Mode      : raw private keys"),
-        KeyMode::Mnemonic { path_string, .. } => {
-            println!("Mode      : BIP-39 mnemonic (path {})", path_string)
+    // An attempt is one mnemonic, which covers every scanned index.
+    let addresses = match &config.key_mode {
+        KeyMode::Mnemonic { scan_indices, .. } => (*scan_indices).max(1),
+        _ => 1,
+    };
+    Ok((
+        Some(1.0 - (1.0 - per_address).powi(addresses as i32)),
+        address,
+    ))
+}
+
+fn print_estimate(config: &SearchConfig) -> Result<()> {
+    let (probability, address) = attempt_probability(config)?;
+    let Some(probability) = probability else {
+        return Err(anyhow!("--estimate does not support --regex patterns"));
+    };
+    println!(
+        "Estimate for {:?} ({})",
+        config.format,
+        config.network.name()
+    );
+    if probability <= 0.0 {
+        if config.format.is_bech32() && !config.pattern.match_wif {
+            println!(
+                "Probability: 0 (the pattern conflicts with the fixed prefix {})",
+                &address[..config.pattern.fixed_len]
+            );
+        } else {
+            println!("Probability: 0 (the prefix lies outside what the version byte allows)");
         }
+        return Ok(());
     }
-    if args.format == AddressFormat::Bech32 {
-        println!(
-            "Witness   : version {} ({})",
-            args.witness_version,
-            if args.witness_version == 0 {
-                "Bech32"
-            } else {
-                "Bech32m"
-            }
-        );
-    }
-    if resume_attempt > 0 {
-        println!("Start at  : attempt {}", resume_attempt);
+    let expected = config.count as f64 / probability;
+    println!("Probability: {} per attempt", format_odds(probability));
+    println!(
+        "Expected  : {:.0} attempts for {} match(es)",
+        expected, config.count
+    );
+
+    let (attempts, elapsed) = search::benchmark(config, ESTIMATE_BENCHMARK)?;
+    let rate = attempts as f64 / elapsed.as_secs_f64().max(f64::EPSILON);
+    println!(
+        "Rate      : {:.0} keys/s ({} threads, {:?} benchmark)",
+        rate, config.threads, ESTIMATE_BENCHMARK
+    );
+    if rate <= 0.0 {
+        return Ok(());
     }
-    if let Some((path, _)) = &resume_checkpoint {
-        println!("Resume    : {}", path.display());
+    let eta_secs = expected / rate;
+    println!("ETA       : {}", format_eta(eta_secs));
+    if eta_secs > IMPOSSIBLE_SECS {
+        println!("Warning   : this pattern is effectively impossible on this machine; shorten it");
     }
-    if let Some(path) = &args.checkpoint {
+    Ok(())
+}
+
+fn run_benchmark(config: &SearchConfig, duration: Duration, json: bool) -> Result<()> {
+    if !json {
         println!(
-            "Checkpoint : {} (every {} attempts)",
-            path.display(),
-            args.checkpoint_interval.max(1)
+            "Benchmarking {:?} ({}) on {} threads for {:?}...",
+            config.format,
+            config.network.name(),
+            config.threads,
+            duration
         );
     }
-    if args.stats_interval > 0 {
-        println!(
-            "Stats     : every {}s ({})",
-            args.stats_interval,
-            if args.stats_json { "json" } else { "text" }
-        );
+    let (attempts, elapsed) = search::benchmark(config, duration)?;
+    let report = BenchmarkReport {
+        format: format!("{:?}", config.format),
+        witness_version: config.format.is_bech32().then_some(config.witness_version),
+        mnemonic: matches!(
+            config.key_mode,
+            KeyMode::Mnemonic { .. } | KeyMode::FixedMnemonic { .. }
+        ),
+        threads: config.threads,
+        attempts,
+        elapsed_ms: elapsed.as_millis(),
+        attempts_per_sec: attempts as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+    };
+    if json {
+        println!("{}", serde_json::to_string(&report)?);
+    } else {
+        println!("Attempts  : {}", report.attempts);
+        println!("Elapsed   : {:.2?}", elapsed);
+        println!("Rate      : {:.2}/s", report.attempts_per_sec);
     }
+    Ok(())
+}
 
-    let checkpoint_writer = if let Some(path) = args.checkpoint.clone() {
-        if args.checkpoint_interval == 0 {
-            return Err(anyhow!("--checkpoint-interval must be greater than 0"));
+// `90`, `90s`, `30m`, `2h`, `1d` or a sum such as `1h30m`; a bare number is seconds.
+fn parse_duration(value: &str) -> Result<Duration> {
+    let mut total = 0u64;
+    let mut rest = value.trim();
+    if rest.is_empty() {
+        return Err(anyhow!("empty duration"));
+    }
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        if digits == 0 {
+            return Err(anyhow!("expected a number in '{}'", value));
         }
-        let writer = Arc::new(CheckpointWriter::new(
-            path,
-            config_hash.clone(),
-            base_seed,
-            args.checkpoint_interval,
-        ));
-        writer.force_write(resume_attempt)?;
-        Some(writer)
-    } else {
-        None
-    };
-
-    let start = Instant::now();
-    let scheduler = Arc::new(AtomicU64::new(resume_attempt));
-    let attempts_done = Arc::new(AtomicU64::new(resume_attempt));
-    let found = Arc::new(AtomicBool::new(false));
-    let result = Arc::new(Mutex::new(None));
-    let stats_stop = Arc::new(AtomicBool::new(false));
-    let stats_handle = spawn_stats_thread(
-        args.stats_interval,
-        args.stats_json,
-        Arc::clone(&attempts_done),
-        Arc::clone(&stats_stop),
-        start,
-    );
-
-    let pool = ThreadPoolBuilder::new()
-        .num_threads(threads)
-        .build()
-        .context("Failed to build rayon thread pool")?;
-
-    pool.install(|| {
-        rayon::scope(|s| {
-            for worker_idx in 0..threads {
-                let scheduler = Arc::clone(&scheduler);
-                let attempts_done = Arc::clone(&attempts_done);
-                let found = Arc::clone(&found);
-                let result = Arc::clone(&result);
-                let prefix = prefix.clone();
-                let suffix = suffix.clone();
-                let checkpoint = checkpoint_writer.clone();
-                let key_mode = Arc::clone(&key_mode);
-
-                s.spawn(move |_| {
-                    let mut stop = false;
-                    let mut address_buf = String::with_capacity(40);
-
-                    while !stop {
-                        if found.load(Ordering::Acquire) {
-                            break;
-                        }
-
-                        let start = scheduler.fetch_add(ATTEMPT_BATCH, Ordering::Relaxed);
-                        if start >= max_attempts {
-                            break;
-                        }
-
-                        let end = (start + ATTEMPT_BATCH).min(max_attempts);
-                        let mut processed = 0u64;
-
-                        for attempt in start..end {
-                            if found.load(Ordering::Acquire) {
-                                stop = true;
-                                break;
-                            }
-
-                            if worker_idx == 0 && attempt != 0 && attempt % PROGRESS_INTERVAL == 0 {
-                                println!("Checked {} keys...", attempt);
-                            }
-
-                            processed += 1;
-
-                            let attempt_number = attempt;
-                            let candidate = match derive_candidate(
-                                base_seed,
-                                attempt_number,
-                                key_mode.as_ref(),
-                            ) {
-                                Some(value) => value,
-                                None => continue,
-                            };
-                            if encode_address(
-                                &candidate.secret,
-                                args.format,
-                                args.witness_version,
-                                &mut address_buf,
-                            )
-                            .is_err()
-                            {
-                                continue;
-                            }
-
-                            if matches_pattern(&address_buf, prefix.as_deref(), suffix.as_deref()) {
-                                let mut guard = result.lock().expect("poisoned mutex");
-                                *guard = Some((candidate, address_buf.clone(), attempt_number + 1));
-                                found.store(true, Ordering::Release);
-                                stop = true;
-                                break;
-                            }
-                        }
-
-                        if processed != 0 {
-                            let total =
-                                attempts_done.fetch_add(processed, Ordering::Relaxed) + processed;
-                            if let Some(writer) = checkpoint.as_ref() {
-                                writer.maybe_write(total);
-                            }
-                        }
-
-                        if stop {
-                            break;
-                        }
-                    }
-                });
+        let amount: u64 = rest[..digits].parse()?;
+        rest = &rest[digits..];
+        let unit_len = rest
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let scale = match &rest[..unit_len] {
+            "" | "s" => 1,
+            "m" => 60,
+            "h" => 3_600,
+            "d" => 86_400,
+            unit => {
+                return Err(anyhow!(
+                    "unknown unit '{}' in '{}' (use s, m, h or d)",
+                    unit,
+                    value
+                ))
             }
-        });
-    });
-
-    stats_stop.store(true, Ordering::Release);
-    if let Some(handle) = stats_handle {
-        let _ = handle.join();
+        };
+        rest = &rest[unit_len..];
+        total = amount
+            .checked_mul(scale)
+            .and_then(|secs| total.checked_add(secs))
+            .ok_or_else(|| anyhow!("duration '{}' is too long", value))?;
     }
+    if total == 0 {
+        return Err(anyhow!("duration must be positive"));
+    }
+    Ok(Duration::from_secs(total))
+}
 
-    let elapsed = start.elapsed();
-    let attempts_made = attempts_done.load(Ordering::Relaxed).min(max_attempts);
-    if let Some((candidate, address, attempts_needed)) = result.lock().unwrap().take() {
-        println!();
-        println!(
-            "Found vanity key after {} attempts ({:.2?})",
-            attempts_needed, elapsed
-        );
-        print_candidate(
-            &candidate,
-            &address,
-            key_mode.as_ref(),
-            args.format,
-            args.witness_version,
-        );
-
-        let report = VanityResult {
-            private_key_hex: format!("0x{}", hex::encode(candidate.secret.secret_bytes())),
-            wif: wif_from_secret(&candidate.secret),
-            address,
-            format: format!("{:?}", args.format),
-            witness_version: (args.format == AddressFormat::Bech32).then_some(args.witness_version),
-            attempts: attempts_needed,
-            attempts_limit: if max_attempts == u64::MAX {
-                None
-            } else {
-                Some(max_attempts)
-            },
-            seed: base_seed,
-            prefix,
-            suffix,
-            mnemonic: candidate.mnemonic.clone(),
-            hd_path: key_mode.as_ref().path_string().map(|s| s.to_string()),
-        };
-        match append_result_file(&output_path, &report) {
-            Ok(_) => println!("Result saved to {}", output_path.display()),
-            Err(err) => eprintln!(
-                "Failed to write result file {}: {err:?}",
-                output_path.display()
-            ),
-        }
+// "1 in N", with a decimal while N is small enough for rounding to hide it (0.8 is not
+// "1 in 1").
+fn format_odds(probability: f64) -> String {
+    let n = 1.0 / probability;
+    if n < 10.0 {
+        format!("1 in {:.1}", n)
     } else {
-        println!();
-        println!(
-            "No vanity key found after {} attempts ({:.2?}). Increase --attempts or relax prefix/suffix.",
-            attempts_made, elapsed
-        );
+        format!("1 in {:.0}", n)
     }
+}
 
-    if let Some(writer) = checkpoint_writer.as_ref() {
-        writer.force_write(attempts_made)?;
+fn format_eta(secs: f64) -> String {
+    const MINUTE: f64 = 60.0;
+    const HOUR: f64 = 60.0 * MINUTE;
+    const DAY: f64 = 24.0 * HOUR;
+    const YEAR: f64 = 365.25 * DAY;
+    if secs < MINUTE {
+        format!("{:.1}s", secs)
+    } else if secs < HOUR {
+        format!("{:.1} minutes", secs / MINUTE)
+    } else if secs < DAY {
+        format!("{:.1} hours", secs / HOUR)
+    } else if secs < YEAR {
+        format!("{:.1} days", secs / DAY)
+    } else {
+        format!("{:.3e} years", secs / YEAR)
     }
+}
 
-    Ok(())
+#[derive(Default)]
+struct SecretExport {
+    // None with --bip38-only.
+    wif: Option<String>,
+    bip38: Option<String>,
+    compressed: bool,
+}
+
+fn export_secret(
+    secret: &SecretKey,
+    args: &Args,
+    network: &NetworkParams,
+    compressed: bool,
+) -> Result<SecretExport> {
+    let bip38 = args
+        .bip38_passphrase
+        .as_deref()
+        .map(|passphrase| encrypt_bip38(secret, passphrase, network, compressed))
+        .transpose()
+        .context("BIP-38 encryption failed")?;
+    Ok(SecretExport {
+        wif: (!args.bip38_only).then(|| wif_from_secret(secret, network, compressed)),
+        bip38,
+        compressed,
+    })
 }
 
+// Without `show_secrets` (--encrypt-output) the key lines stay out of stdout, which long
+// runs usually send to a log file.
 fn print_candidate(
     candidate: &CandidateKey,
     address: &str,
     mode: &KeyMode,
     format: AddressFormat,
     witness_version: u8,
+    export: &SecretExport,
+    show_secrets: bool,
 ) {
     let secret_hex = hex::encode(candidate.secret.secret_bytes());
-    let wif = wif_from_secret(&candidate.secret);
-    match format {
-        AddressFormat::Bech32 => {
-            println!(
-                "Address   : {} ({:?} v{})",
-                address, format, witness_version
-            );
+    if format.is_bech32() {
+        println!(
+            "Address   : {} ({:?} v{})",
+            address, format, witness_version
+        );
+    } else {
+        println!("Address   : {} ({:?})", address, format);
+    }
+    if format == AddressFormat::P2wsh {
+        println!(
+            "Script    : {} (<pubkey> OP_CHECKSIG)",
+            hex::encode(p2wsh_witness_script(&candidate.public))
+        );
+    }
+    if let KeyMode::SplitKey { .. } = mode {
+        println!("Offset    : 0x{}", secret_hex);
+        println!("Pubkey    : {}", hex::encode(candidate.public.serialize()));
+        println!(
+            "Recover with --combine 0x{} --base-secret <BASE_SECRET_HEX>",
+            secret_hex
+        );
+        return;
+    }
+    if !show_secrets {
+        println!("Key       : in the encrypted result file only");
+    } else if let Some(wif) = &export.wif {
+        println!("SecretHex : 0x{}", secret_hex);
+        if export.compressed {
+            println!("WIF       : {}", wif);
+        } else {
+            println!("WIF       : {} (uncompressed)", wif);
         }
-        _ => println!("Address   : {} ({:?})", address, format),
     }
-    println!("SecretHex : 0x{}", secret_hex);
-    println!("WIF       : {}", wif);
+    if let Some(encrypted) = &export.bip38 {
+        println!("BIP38     : {}", encrypted);
+    }
     if let Some(phrase) = candidate.mnemonic.as_ref() {
-        println!("Mnemonic  : {}", phrase);
-        if let KeyMode::Mnemonic { path_string, .. } = mode {
-            println!("HD path   : {}", path_string);
+        // The user supplied a fixed mnemonic, so it isn't echoed back.
+        if show_secrets && !matches!(mode, KeyMode::FixedMnemonic { .. }) {
+            println!("Mnemonic  : {}", phrase);
+        }
+        if let Some(path) = mode.candidate_path(candidate) {
+            println!("HD path   : {}", path);
         }
     }
 }
 
-fn prepare_pattern(pattern: Option<String>, format: AddressFormat) -> Result<Option<String>> {
-    pattern
-        .map(|value| {
-            let normalized = match format {
-                AddressFormat::P2pkh => value,
-                AddressFormat::Bech32 => value.to_lowercase(),
-            };
-            ensure_charset(&normalized, format)?;
-            Ok(normalized)
-        })
-        .transpose()
+// Address, then the WIF (BIP-38 key with --bip38-only), then optionally the mnemonic.
+// With `encrypted` (--encrypt-output) nothing that reveals the key is rendered, so only the
+// address and any BIP-38 key remain.
+fn emit_qr(
+    mode: QrMode,
+    output: &Path,
+    address: &str,
+    export: &SecretExport,
+    mnemonic: Option<&str>,
+    encrypted: bool,
+    ascii: bool,
+) -> Result<()> {
+    let mut codes = vec![("address", address)];
+    match (export.wif.as_deref(), export.bip38.as_deref()) {
+        (Some(key), _) if !encrypted => codes.push(("wif", key)),
+        (_, Some(key)) => codes.push(("bip38", key)),
+        _ => {}
+    }
+    if let Some(phrase) = mnemonic.filter(|_| !encrypted) {
+        codes.push(("mnemonic", phrase));
+    }
+    for (label, data) in codes {
+        if mode.terminal() {
+            println!("QR {}:", label);
+            print!("{}", qr::render_terminal(data, ascii)?);
+            println!();
+        }
+        if mode.png() {
+            let path = qr::png_path(output, address, label);
+            qr::write_png(data, &path)?;
+            println!("QR {:<8}: {}", label, path.display());
+        }
+    }
+    Ok(())
 }
 
-fn ensure_charset(value: &str, format: AddressFormat) -> Result<()> {
-    let valid = match format {
-        AddressFormat::P2pkh => value.chars().all(
-            |c| matches!(c, '1'..='9' | 'A'..='H' | 'J'..='N' | 'P'..='Z' | 'a'..='k' | 'm'..='z'),
-        ),
-        AddressFormat::Bech32 => value.chars().all(|c| matches!(c, '0'..='9' | 'a'..='z')),
-    };
-    if valid {
-        return Ok(());
+fn unlimited(ascii: bool) -> &'static str {
+    if ascii {
+        "unlimited"
+    } else {
+        "\u{221E}"
     }
+}
 
-    let note = match format {
-        AddressFormat::P2pkh => "Base58 characters (no 0 O I l)",
-        AddressFormat::Bech32 => "lowercase Bech32 characters",
+// A percentage/ETA bar when the attempt budget is bounded, otherwise a spinner.
+fn progress_bar(resume_attempt: u64, max_attempts: u64, ascii: bool) -> ProgressBar {
+    let (bar, template) = if max_attempts == u64::MAX {
+        (
+            ProgressBar::new_spinner(),
+            "{spinner} {human_pos} attempts | {per_sec} | {elapsed_precise}",
+        )
+    } else {
+        (
+            ProgressBar::new(max_attempts),
+            "[{bar:40}] {percent:>3}% {human_pos}/{human_len} | {per_sec} | ETA {eta}",
+        )
     };
-    Err(anyhow!(
-        "Pattern '{}' contains invalid characters for {:?} ({})",
-        value,
-        format,
-        note
-    ))
+    bar.set_draw_target(ProgressDrawTarget::stdout());
+    let mut style = ProgressStyle::with_template(template)
+        .expect("valid progress template")
+        .progress_chars("=> ");
+    if ascii {
+        // The default spinner frames are Braille dots.
+        style = style.tick_chars("-\\|/ ");
+    }
+    bar.set_style(style);
+    bar.set_position(resume_attempt);
+    // Resumed work shouldn't inflate the rate or shrink the ETA.
+    bar.reset_eta();
+    bar
 }
 
-fn matches_pattern(address: &str, prefix: Option<&str>, suffix: Option<&str>) -> bool {
-    if let Some(p) = prefix {
-        if !address.starts_with(p) {
-            return false;
+// Reports total work across all workers from the shared counter: either by driving `bar`,
+// or as one line per `interval` attempts crossed (coalesced if several fall in one poll).
+// With `quiet` the lines go to the log at info level instead of stdout.
+fn spawn_progress_thread(
+    progress: Arc<SearchProgress>,
+    resume_attempt: u64,
+    interval: u64,
+    bar: Option<ProgressBar>,
+    quiet: bool,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let mut last_mark = resume_attempt / interval;
+        while !progress.stop.load(Ordering::Acquire) {
+            thread::sleep(STATS_POLL);
+            let done = progress.attempts_done.load(Ordering::Relaxed);
+            if let Some(bar) = &bar {
+                bar.set_position(done);
+                continue;
+            }
+            let mark = done / interval;
+            if mark > last_mark {
+                last_mark = mark;
+                if quiet {
+                    info!("Checked {} keys...", mark * interval);
+                } else {
+                    println!("Checked {} keys...", mark * interval);
+                }
+            }
         }
-    }
-    if let Some(s) = suffix {
-        if !address.ends_with(s) {
-            return false;
+        if let Some(bar) = bar {
+            bar.finish_and_clear();
         }
-    }
-    true
+    })
 }
 
-fn derive_candidate(base_seed: u64, attempt: u64, mode: &KeyMode) -> Option<CandidateKey> {
-    match mode {
-        KeyMode::Raw => {
-            let material = key_material_from_attempt(base_seed, attempt);
-            let secret = SecretKey::from_slice(&material).ok()?;
-            Some(CandidateKey {
-                secret,
-                mnemonic: None,
-            })
-        }
-        KeyMode::Mnemonic { path, .. } => {
-            let entropy = key_material_from_attempt(base_seed, attempt);
-            let mnemonic = Mnemonic::from_entropy_in(Language::English, &entropy).ok()?;
-            let phrase = mnemonic.to_string();
-            let seed = mnemonic.to_seed("");
-            let child = XPrv::derive_from_path(&seed, path).ok()?;
-            let signing_key = child.private_key();
-            let secret = SecretKey::from_slice(&signing_key.to_bytes()).ok()?;
-            Some(CandidateKey {
-                secret,
-                mnemonic: Some(phrase),
-            })
-        }
-    }
+// Everything needed to print one stats line; shared by the interval thread and SIGUSR1.
+#[derive(Clone)]
+struct StatsReporter {
+    json_mode: bool,
+    progress: Arc<SearchProgress>,
+    resume_attempt: u64,
+    matches_target: u64,
+    // Per-attempt match chance, when the pattern can be estimated and isn't impossible.
+    probability: Option<f64>,
+    output: Option<Arc<StatsOutput>>,
+    start: Instant,
 }
 
-fn key_material_from_attempt(base_seed: u64, attempt: u64) -> [u8; 32] {
-    let mut state = base_seed ^ attempt;
-    let mut out = [0u8; 32];
-    for chunk in out.chunks_mut(8) {
-        state = splitmix64(state);
-        chunk.copy_from_slice(&state.to_le_bytes());
+impl StatsReporter {
+    fn stats(&self) -> (ProgressStats, Duration) {
+        let elapsed = self.start.elapsed();
+        let elapsed_ms = elapsed.as_millis();
+        let attempts = self.progress.attempts_done.load(Ordering::Relaxed);
+        let discarded = self.progress.discarded.load(Ordering::Relaxed);
+        // Discarded attempts tested no key, so they don't count toward the odds.
+        let tested = attempts.saturating_sub(discarded);
+        // `elapsed` only covers this session, so the rate must exclude resumed work.
+        let session_attempts = attempts.saturating_sub(self.resume_attempt);
+        let elapsed_secs = elapsed.as_secs_f64().max(f64::EPSILON);
+        let stats = ProgressStats {
+            attempts,
+            session_attempts,
+            attempts_per_sec: session_attempts as f64 / elapsed_secs,
+            elapsed_ms,
+            matches_found: self.progress.matches_found.load(Ordering::Relaxed),
+            matches_target: self.matches_target,
+            discarded,
+            match_probability: self.probability,
+            expected_attempts: self.probability.map(|p| 1.0 / p),
+            // 1 - (1-p)^n without losing tiny p to rounding.
+            found_probability: self
+                .probability
+                .map(|p| -((tested as f64) * (-p).ln_1p()).exp_m1()),
+        };
+        (stats, elapsed)
     }
-    out
-}
 
-fn encode_address(
-    secret: &SecretKey,
-    format: AddressFormat,
-    witness_version: u8,
-    out: &mut String,
-) -> Result<()> {
-    match format {
-        AddressFormat::P2pkh => {
-            let public = SecpPublicKey::from_secret_key(&SECP256K1, secret);
-            let pub_bytes = public.serialize();
-            let sha = Sha256::digest(pub_bytes);
-            let rip = Ripemd160::digest(&sha);
-            let mut payload = Vec::with_capacity(25);
-            payload.push(0x00);
-            payload.extend_from_slice(&rip);
-            let checksum = double_sha256(&payload);
-            payload.extend_from_slice(&checksum[..4]);
-            encode_base58(&payload, out);
-            Ok(())
-        }
-        AddressFormat::Bech32 => {
-            let public = SecpPublicKey::from_secret_key(&SECP256K1, secret);
-            let program = match witness_version {
-                0 => {
-                    let pub_bytes = public.serialize();
-                    let sha = Sha256::digest(pub_bytes);
-                    Ripemd160::digest(&sha).to_vec()
-                }
-                1 => {
-                    let pub_bytes = public.serialize();
-                    pub_bytes[1..].to_vec()
-                }
-                other => {
-                    return Err(anyhow!(
-                        "Witness version {} not supported (only v0 or v1)",
-                        other
-                    ));
+    fn report(&self) {
+        let (stats, elapsed) = self.stats();
+        let line = if self.json_mode {
+            match serde_json::to_string(&stats) {
+                Ok(line) => line,
+                Err(err) => {
+                    error!("Failed to serialize stats: {err:?}");
+                    return;
                 }
-            };
-            let variant = if witness_version == 0 {
-                Variant::Bech32
+            }
+        } else {
+            let found = if self.matches_target > 1 {
+                format!(" | found={}/{}", stats.matches_found, self.matches_target)
             } else {
-                Variant::Bech32m
+                String::new()
             };
-            let mut data = Vec::with_capacity(1 + program.len());
-            let version_u5 = bech32::u5::try_from_u8(witness_version)
-                .map_err(|e| anyhow!("Invalid witness version: {e}"))?;
-            data.push(version_u5);
-            data.extend(program.to_base32());
-            let addr = bech32::encode("bc", data, variant)
-                .map_err(|e| anyhow!("bech32 encode failed: {e}"))?;
-            out.clear();
-            out.push_str(&addr);
-            Ok(())
+            let discarded = match stats.discarded {
+                0 => String::new(),
+                n => format!(" | discarded={}", n),
+            };
+            let odds = match (stats.expected_attempts, stats.found_probability) {
+                (Some(expected), Some(found)) => format!(
+                    " | 1 in {:.0} per attempt | {:.0}% likely found by now",
+                    expected,
+                    found * 100.0
+                ),
+                _ => String::new(),
+            };
+            format!(
+                "Stats | attempts={} | rate={:.2}/s | elapsed={:.2?}{}{}{}",
+                stats.attempts, stats.attempts_per_sec, elapsed, found, discarded, odds
+            )
+        };
+        match &self.output {
+            Some(output) => {
+                if let Err(err) = output.write(&line) {
+                    warn!(
+                        "Failed to write stats to {}: {err:?}",
+                        output.path.display()
+                    );
+                }
+            }
+            // The prefix lets stdout consumers pick stats out of the other output.
+            None if self.json_mode => println!("STATS {line}"),
+            None => println!("{line}"),
         }
     }
 }
 
-fn wif_from_secret(secret: &SecretKey) -> String {
-    let mut payload = Vec::with_capacity(34);
-    payload.push(0x80);
-    payload.extend_from_slice(&secret.secret_bytes());
-    payload.push(0x01); // compressed
-    let checksum = double_sha256(&payload);
-    let mut buf = payload;
-    buf.extend_from_slice(&checksum[..4]);
-    let mut out = String::with_capacity(52);
-    encode_base58(&buf, &mut out);
-    out
-}
-
-fn double_sha256(data: &[u8]) -> [u8; 32] {
-    let first = Sha256::digest(data);
-    let second = Sha256::digest(&first);
-    let mut out = [0u8; 32];
-    out.copy_from_slice(&second);
-    out
+// --stats-output target. Records are written bare (no `STATS ` prefix), one per line.
+struct StatsOutput {
+    path: PathBuf,
+    append: bool,
+    // Opened once for appends, so a pipe reader sees one stream rather than an EOF per record.
+    file: Mutex<Option<fs::File>>,
 }
 
-fn encode_base58(input: &[u8], out: &mut String) {
-    // 138/100 is enough slack to Base58-encode arbitrary data (Bitcoin reference logic).
-    let capacity = (input.len() * 138 / 100) + 1;
-    let mut digits = vec![0u8; capacity.max(1)];
-    let mut digit_len = 1;
-
-    for &byte in input {
-        let mut carry = byte as u32;
-        for digit in digits[..digit_len].iter_mut() {
-            let val = (*digit as u32) * 256 + carry;
-            *digit = (val % 58) as u8;
-            carry = val / 58;
-        }
-        while carry > 0 {
-            if digit_len >= digits.len() {
-                digits.push(0);
-            }
-            digits[digit_len] = (carry % 58) as u8;
-            carry /= 58;
-            digit_len += 1;
+impl StatsOutput {
+    fn new(path: PathBuf, append: bool) -> Self {
+        Self {
+            path,
+            append,
+            file: Mutex::new(None),
         }
     }
 
-    let mut zeros = 0;
-    for b in input {
-        if *b == 0 {
-            zeros += 1;
-        } else {
-            break;
+    fn write(&self, line: &str) -> Result<()> {
+        let record = format!("{line}\n");
+        // A FIFO or device can't be replaced by rename, so it is always written in place.
+        let regular = fs::metadata(&self.path).map_or(true, |meta| meta.is_file());
+        if !self.append && regular {
+            return write_atomic(&self.path, record.as_bytes());
         }
-    }
-
-    out.clear();
-    out.reserve(zeros + digit_len);
-    for _ in 0..zeros {
-        out.push('1');
-    }
-    for digit in digits[..digit_len].iter().rev() {
-        out.push(BASE58_ALPHABET[*digit as usize] as char);
-    }
-    if out.is_empty() {
-        out.push('1');
-    }
-}
-
-fn config_fingerprint(
-    base_seed: u64,
-    prefix: &Option<String>,
-    suffix: &Option<String>,
-    mode: &KeyMode,
-    format: AddressFormat,
-    witness_version: u8,
-) -> [u8; 32] {
-    let mut data = Vec::new();
-    data.extend_from_slice(&base_seed.to_le_bytes());
-    if let Some(p) = prefix {
-        data.extend_from_slice(p.as_bytes());
-        data.push(0xff);
-    }
-    if let Some(s) = suffix {
-        data.extend_from_slice(s.as_bytes());
-        data.push(0x01);
-    }
-    match mode {
-        KeyMode::Raw => data.push(0x10),
-        KeyMode::Mnemonic { path_string, .. } => {
-            data.push(0x22);
-            data.extend_from_slice(path_string.as_bytes());
+        let mut file = lock_or_recover(&self.file);
+        if file.is_none() {
+            *file = Some(
+                fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.path)?,
+            );
         }
-    }
-    match format {
-        AddressFormat::P2pkh => data.push(0x01),
-        AddressFormat::Bech32 => {
-            data.push(0x02);
-            data.push(witness_version);
+        if let Some(handle) = file.as_mut() {
+            if let Err(err) = handle.write_all(record.as_bytes()) {
+                // Reopen next time (e.g. the pipe reader restarted).
+                *file = None;
+                return Err(err.into());
+            }
         }
+        Ok(())
     }
-    let mut hasher = Sha256::new();
-    hasher.update(&data);
-    let first = hasher.finalize();
-    let second = Sha256::digest(&first);
-    let mut out = [0u8; 32];
-    out.copy_from_slice(&second);
-    out
 }
 
 fn spawn_stats_thread(
     interval_secs: u64,
-    json_mode: bool,
-    attempts_done: Arc<AtomicU64>,
-    stop: Arc<AtomicBool>,
-    start: Instant,
+    reporter: StatsReporter,
 ) -> Option<thread::JoinHandle<()>> {
     if interval_secs == 0 {
         return None;
     }
     let interval = Duration::from_secs(interval_secs.max(1));
     Some(thread::spawn(move || loop {
-        if stop.load(Ordering::Acquire) {
-            break;
+        // Sleep in short slices so a stop (match found or Ctrl-C) never waits a full interval.
+        let wake = Instant::now() + interval;
+        while Instant::now() < wake {
+            if reporter.progress.stop.load(Ordering::Acquire) {
+                return;
+            }
+            thread::sleep(STATS_POLL.min(wake.saturating_duration_since(Instant::now())));
         }
-        thread::sleep(interval);
-        if stop.load(Ordering::Acquire) {
+        if reporter.progress.stop.load(Ordering::Acquire) {
             break;
         }
-        let elapsed = start.elapsed();
-        let elapsed_ms = elapsed.as_millis();
-        if elapsed_ms == 0 {
+        if reporter.start.elapsed().as_millis() == 0 {
             continue;
         }
-        let attempts = attempts_done.load(Ordering::Relaxed);
-        let elapsed_secs = elapsed.as_secs_f64().max(f64::EPSILON);
-        let stats = ProgressStats {
-            attempts,
-            attempts_per_sec: attempts as f64 / elapsed_secs,
-            elapsed_ms,
-        };
-        if json_mode {
-            match serde_json::to_string(&stats) {
-                Ok(line) => println!("STATS {line}"),
-                Err(err) => eprintln!("Failed to serialize stats: {err:?}"),
-            }
-        } else {
-            println!(
-                "Stats | attempts={} | rate={:.2}/s | elapsed={:.2?}",
-                stats.attempts, stats.attempts_per_sec, elapsed
-            );
-        }
+        reporter.report();
     }))
 }
 
-fn load_checkpoint_file(path: &Path) -> Result<CheckpointFile> {
-    let raw = fs::read_to_string(path)
-        .with_context(|| format!("Unable to read checkpoint {}", path.display()))?;
-    let checkpoint: CheckpointFile = serde_json::from_str(&raw)
-        .with_context(|| format!("Invalid checkpoint JSON {}", path.display()))?;
-    if checkpoint.version != 1 {
-        return Err(anyhow!(
-            "Unsupported checkpoint version {}",
-            checkpoint.version
-        ));
-    }
-    Ok(checkpoint)
+// --metrics-addr: answers `GET /metrics` with the stats reporter's numbers until the search
+// stops. One request at a time is plenty for a scraper, so this needs no HTTP crate.
+fn spawn_metrics_server(
+    listener: TcpListener,
+    reporter: StatsReporter,
+) -> Result<thread::JoinHandle<()>> {
+    // Non-blocking accepts let the loop notice the stop flag.
+    listener
+        .set_nonblocking(true)
+        .context("Failed to configure the metrics listener")?;
+    Ok(thread::spawn(move || {
+        while !reporter.progress.stop.load(Ordering::Acquire) {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    if let Err(err) = serve_metrics(stream, || reporter.stats().0) {
+                        warn!("Metrics request failed: {err:?}");
+                    }
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => thread::sleep(STATS_POLL),
+                Err(err) => {
+                    warn!("Metrics listener failed: {err:?}");
+                    thread::sleep(STATS_POLL);
+                }
+            }
+        }
+    }))
 }
 
-fn save_checkpoint_file(path: &Path, payload: &CheckpointFile) -> Result<()> {
-    if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent)
-            .with_context(|| format!("Failed to create checkpoint dir {}", parent.display()))?;
-    }
-    let data = serde_json::to_vec_pretty(payload)?;
-    fs::write(path, data)
-        .with_context(|| format!("Failed to write checkpoint {}", path.display()))?;
-    Ok(())
-}
+// `kill -USR1 <pid>` prints one stats line on demand, independent of --stats-interval.
+// The thread is left blocked on the signal iterator and dies with the process.
+#[cfg(unix)]
+fn install_stats_signal(reporter: StatsReporter) -> Result<()> {
+    use signal_hook::{consts::SIGUSR1, iterator::Signals};
 
-fn append_result_file(path: &Path, report: &VanityResult) -> Result<()> {
-    let mut entries: Vec<Value> = Vec::new();
-    if path.exists() {
-        let raw = fs::read_to_string(path)
-            .with_context(|| format!("Failed to read existing result file {}", path.display()))?;
-        if !raw.trim().is_empty() {
-            let existing: Value = serde_json::from_str(&raw).with_context(|| {
-                format!("Failed to parse existing result file {}", path.display())
-            })?;
-            match existing {
-                Value::Array(arr) => entries = arr,
-                other => entries.push(other),
-            }
+    let mut signals = Signals::new([SIGUSR1]).context("Failed to install SIGUSR1 handler")?;
+    thread::spawn(move || {
+        for _ in signals.forever() {
+            reporter.report();
         }
-    }
-    entries.push(serde_json::to_value(report)?);
-    let data = serde_json::to_vec_pretty(&entries)?;
-    if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent)
-            .with_context(|| format!("Failed to create result dir {}", parent.display()))?;
-    }
-    fs::write(path, data)
-        .with_context(|| format!("Failed to write result file {}", path.display()))?;
+    });
     Ok(())
 }
 
-fn splitmix64(mut x: u64) -> u64 {
-    x = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
-    let mut z = x;
-    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
-    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
-    z ^ (z >> 31)
+#[cfg(not(unix))]
+fn install_stats_signal(_reporter: StatsReporter) -> Result<()> {
+    Ok(())
 }
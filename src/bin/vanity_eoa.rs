@@ -88,6 +88,10 @@ struct Args {
     /// Emit JSON progress stats instead of plain text.
     #[arg(long)]
     stats_json: bool,
+
+    /// Print "unlimited" instead of the infinity sign for terminals without UTF-8.
+    #[arg(long)]
+    ascii: bool,
 }
 
 #[derive(Serialize)]
@@ -332,7 +336,11 @@ fn main() -> Result<()> {
         println!("Matching  : lowercase hex");
     }
     let max_display = if max_attempts == u64::MAX {
-        "∞".to_string()
+        if args.ascii {
+            "unlimited".to_string()
+        } else {
+            "\u{221E}".to_string()
+        }
     } else {
         max_attempts.to_string()
     };
@@ -415,7 +423,6 @@ fn main() -> Result<()> {
                 let result = Arc::clone(&result);
                 let prefix = prefix.clone();
                 let suffix = suffix.clone();
-                let checksum_mode = checksum_mode;
                 let checkpoint = checkpoint_writer.clone();
                 let key_mode = Arc::clone(&key_mode);
 
@@ -638,7 +645,7 @@ fn derive_candidate(base_seed: u64, attempt: u64, mode: &KeyMode) -> Option<Cand
             let mnemonic = Mnemonic::from_entropy_in(Language::English, &entropy).ok()?;
             let phrase = mnemonic.to_string();
             let seed = mnemonic.to_seed("");
-            let child = XPrv::derive_from_path(&seed, path).ok()?;
+            let child = XPrv::derive_from_path(seed, path).ok()?;
             let signing_key = child.private_key();
             let secret = SecretKey::from_slice(&signing_key.to_bytes()).ok()?;
             Some(CandidateKey {
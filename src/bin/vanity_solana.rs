@@ -12,7 +12,6 @@ use std::{
 
 use anyhow::{anyhow, Context, Result};
 use bip39::{Language, Mnemonic};
-use bs58;
 use clap::Parser;
 use ed25519_dalek::{PublicKey, SecretKey};
 use ed25519_dalek_bip32::{DerivationPath, ExtendedSigningKey};
@@ -85,6 +84,10 @@ struct Args {
     /// Emit JSON progress stats instead of plain text
     #[arg(long)]
     stats_json: bool,
+
+    /// Print "unlimited" instead of the infinity sign for terminals without UTF-8
+    #[arg(long)]
+    ascii: bool,
 }
 
 #[derive(Serialize)]
@@ -320,7 +323,11 @@ fn main() -> Result<()> {
         println!("Suffix    : {}", s);
     }
     let max_display = if max_attempts == u64::MAX {
-        "∞".to_string()
+        if args.ascii {
+            "unlimited".to_string()
+        } else {
+            "\u{221E}".to_string()
+        }
     } else {
         max_attempts.to_string()
     };
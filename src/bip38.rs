@@ -0,0 +1,90 @@
+//! BIP-38 passphrase-protected private keys (non-EC-multiply mode, `6P...`).
+
+use aes::{
+    cipher::{BlockEncrypt, KeyInit},
+    Aes256,
+};
+use anyhow::{anyhow, Result};
+use scrypt::{scrypt, Params};
+use secp256k1::SecretKey;
+
+use crate::address::{double_sha256, encode_address, encode_base58, AddressFormat, NetworkParams};
+
+// scrypt N = 2^14, r = 8, p = 8, 64-byte output.
+const SCRYPT_LOG_N: u8 = 14;
+const SCRYPT_R: u32 = 8;
+const SCRYPT_P: u32 = 8;
+
+/// Encrypt `secret` under `passphrase`. The salt is the first 4 bytes of the double SHA-256
+/// of the key's P2PKH address on `network`, so decoders must use the same network and
+/// compression. The passphrase is used as given (no NFC normalisation).
+pub fn encrypt_bip38(
+    secret: &SecretKey,
+    passphrase: &str,
+    network: &NetworkParams,
+    compressed: bool,
+) -> Result<String> {
+    let mut address = String::with_capacity(36);
+    encode_address(
+        secret,
+        AddressFormat::P2pkh,
+        0,
+        compressed,
+        network,
+        &mut address,
+    )?;
+    let address_hash = double_sha256(address.as_bytes());
+    let salt = &address_hash[..4];
+
+    let params = Params::new(SCRYPT_LOG_N, SCRYPT_R, SCRYPT_P, 64)
+        .map_err(|e| anyhow!("Invalid scrypt parameters: {e}"))?;
+    let mut derived = [0u8; 64];
+    scrypt(passphrase.as_bytes(), salt, &params, &mut derived)
+        .map_err(|e| anyhow!("scrypt failed: {e}"))?;
+    let (half1, half2) = derived.split_at(32);
+
+    let cipher = Aes256::new(half2.into());
+    let mut block = secret.secret_bytes();
+    for (byte, mask) in block.iter_mut().zip(half1) {
+        *byte ^= mask;
+    }
+    for chunk in block.chunks_exact_mut(16) {
+        cipher.encrypt_block(chunk.into());
+    }
+
+    let mut payload = Vec::with_capacity(43);
+    payload.extend_from_slice(&[0x01, 0x42, if compressed { 0xe0 } else { 0xc0 }]);
+    payload.extend_from_slice(salt);
+    payload.extend_from_slice(&block);
+    let checksum = double_sha256(&payload);
+    payload.extend_from_slice(&checksum[..4]);
+    let mut out = String::with_capacity(58);
+    encode_base58(&payload, &mut out);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::Network;
+
+    // BIP-38 test vectors: "No compression, no EC multiply" #1 and "Compression, no EC
+    // multiply" #1.
+    #[test]
+    fn matches_bip38_vectors() {
+        let secret = SecretKey::from_slice(
+            &hex::decode("cbf4b9f70470856bb4f40f80b87edb90865997ffee6df315ab166d713af433a5")
+                .unwrap(),
+        )
+        .unwrap();
+        let network = Network::Mainnet.params();
+        assert_eq!(
+            encrypt_bip38(&secret, "TestingOneTwoThree", &network, false).unwrap(),
+            "6PRVWUbkzzsbcVac2qwfssoUJAN1Xhrg6bNk8J7Nzm5H7kxEbn2Nh2ZoGg"
+        );
+        assert_eq!(
+            encrypt_bip38(&secret, "TestingOneTwoThree", &network, true).unwrap(),
+            "6PYNKZ1EAgYgmQfmNVamxyXVWHzK5s6DGhwP4J5o44cvXdoY7sRzhtpUeo"
+        );
+    }
+}
@@ -0,0 +1,496 @@
+//! Resumable checkpoint files (`{version,next_attempt,seed,config_hash,shard?}` plus, from
+//! version 2, cumulative `elapsed_ms`/`matches_found` and `created_at`).
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, TryLockError,
+    },
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::VanityError,
+    keys::KeyStream,
+    output::{compress_for_path, read_text, write_atomic},
+    search::{lock_or_recover, Shard},
+};
+
+pub const CHECKPOINT_VERSION: u32 = 2;
+// Checkpoints are a few hundred bytes; `latest_checkpoint` skips anything far larger (such
+// as a result file) without reading it.
+const MAX_CHECKPOINT_BYTES: u64 = 64 * 1024;
+
+#[derive(Serialize, Deserialize)]
+pub struct CheckpointFile {
+    pub version: u32,
+    pub next_attempt: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_seed: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed_root: Option<String>,
+    pub config_hash: String,
+    // Written only by sharded searches; `next_attempt` is then this shard's next lane attempt.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shard: Option<Shard>,
+    // Totals across every session that wrote this file; zero when loaded from version 1.
+    #[serde(default)]
+    pub elapsed_ms: u64,
+    // Saved matches below `next_attempt`; a resumed search finds any above it again.
+    #[serde(default)]
+    pub matches_found: u64,
+    // UTC time the search first wrote a checkpoint, kept across resumes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<String>,
+}
+
+impl CheckpointFile {
+    pub fn key_stream(&self) -> Result<KeyStream> {
+        match (&self.seed_root, self.base_seed) {
+            (Some(root), seed) => {
+                let bytes = hex::decode(root).context("Invalid checkpoint seed_root hex")?;
+                let root: [u8; 32] = bytes
+                    .try_into()
+                    .map_err(|_| anyhow!("Checkpoint seed_root must be 32 bytes"))?;
+                Ok(KeyStream {
+                    root,
+                    seed,
+                    permute: false,
+                })
+            }
+            (None, Some(seed)) => Ok(KeyStream::from_seed(seed)),
+            (None, None) => Err(anyhow!("Checkpoint has neither base_seed nor seed_root")),
+        }
+    }
+}
+
+pub struct CheckpointWriter {
+    path: PathBuf,
+    config_hash: String,
+    stream: KeyStream,
+    shard: Shard,
+    interval: u64,
+    next_flush: AtomicU64,
+    lock: Mutex<()>,
+    started: Instant,
+    prior_elapsed_ms: u64,
+    prior_matches: u64,
+    created_at: String,
+}
+
+impl CheckpointWriter {
+    pub fn new(
+        path: PathBuf,
+        config_hash: String,
+        stream: KeyStream,
+        shard: Shard,
+        interval: u64,
+    ) -> Self {
+        Self {
+            path,
+            config_hash,
+            stream,
+            shard,
+            interval: interval.max(1),
+            next_flush: AtomicU64::new(0),
+            lock: Mutex::new(()),
+            started: Instant::now(),
+            prior_elapsed_ms: 0,
+            prior_matches: 0,
+            created_at: utc_timestamp(SystemTime::now()),
+        }
+    }
+
+    /// Carry the totals and creation time of a resumed checkpoint into every later write.
+    pub fn continuing(mut self, previous: &CheckpointFile) -> Self {
+        self.prior_elapsed_ms = previous.elapsed_ms;
+        self.prior_matches = previous.matches_found;
+        if let Some(created_at) = &previous.created_at {
+            self.created_at = created_at.clone();
+        }
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// `matches` counts this session's saved matches below `attempts`; earlier sessions are
+    /// added from the resumed checkpoint. It only runs once a write is due, so callers can
+    /// count under a shared lock without taking it on every batch.
+    pub fn maybe_write(&self, attempts: u64, matches: impl FnOnce() -> u64) {
+        let target = self.next_flush.load(Ordering::Relaxed);
+        if attempts < target {
+            return;
+        }
+        let _guard = match self.lock.try_lock() {
+            Ok(guard) => guard,
+            // A writer panicked mid-write; the next write replaces its file anyway.
+            Err(TryLockError::Poisoned(poisoned)) => {
+                log::warn!("Recovered a checkpoint lock poisoned by a panicked thread");
+                self.lock.clear_poison();
+                poisoned.into_inner()
+            }
+            Err(TryLockError::WouldBlock) => return,
+        };
+        let target = self.next_flush.load(Ordering::Relaxed);
+        if attempts < target {
+            return;
+        }
+        if let Err(err) = self.write_file(attempts, matches()) {
+            log::warn!(
+                "Failed to write checkpoint {}: {err:?}",
+                self.path.display()
+            );
+        } else {
+            let next = attempts.saturating_add(self.interval);
+            self.next_flush.store(next, Ordering::Relaxed);
+        }
+    }
+
+    pub fn force_write(&self, attempts: u64, matches: u64) -> Result<()> {
+        let _guard = lock_or_recover(&self.lock);
+        self.write_file(attempts, matches)?;
+        let next = attempts.saturating_add(self.interval);
+        self.next_flush.store(next, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn write_file(&self, attempts: u64, matches: u64) -> Result<()> {
+        let payload = CheckpointFile {
+            version: CHECKPOINT_VERSION,
+            next_attempt: attempts,
+            base_seed: self.stream.seed,
+            seed_root: Some(hex::encode(self.stream.root)),
+            config_hash: self.config_hash.clone(),
+            shard: self.shard.is_sharded().then_some(self.shard),
+            elapsed_ms: self
+                .prior_elapsed_ms
+                .saturating_add(self.started.elapsed().as_millis() as u64),
+            matches_found: self.prior_matches.saturating_add(matches),
+            created_at: Some(self.created_at.clone()),
+        };
+        save_checkpoint_file(&self.path, &payload)?;
+        log::debug!("Checkpoint {} at attempt {}", self.path.display(), attempts);
+        Ok(())
+    }
+}
+
+pub fn load_checkpoint_file(path: &Path) -> Result<CheckpointFile, VanityError> {
+    let unreadable = |source| VanityError::CheckpointUnreadable {
+        path: path.to_path_buf(),
+        source,
+    };
+    let raw = read_text(path).map_err(|e| unreadable(e.into()))?;
+    let checkpoint: CheckpointFile =
+        serde_json::from_str(&raw).map_err(|e| unreadable(Box::new(e)))?;
+    // Version 1 files lack the run totals, which default to zero.
+    if !(1..=CHECKPOINT_VERSION).contains(&checkpoint.version) {
+        return Err(VanityError::CheckpointVersion(checkpoint.version));
+    }
+    Ok(checkpoint)
+}
+
+impl CheckpointFile {
+    /// Errors unless this checkpoint was written by a search with `config_hash`.
+    pub fn ensure_config_hash(&self, config_hash: &str) -> Result<(), VanityError> {
+        if self.config_hash != config_hash {
+            return Err(VanityError::CheckpointMismatch {
+                expected: config_hash.to_string(),
+                found: self.config_hash.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    /// The key stream, which must agree with a --seed/--seed-hex/--seed-file (`provided`).
+    pub fn matching_stream(&self, provided: Option<&KeyStream>) -> Result<KeyStream> {
+        let stream = self.key_stream()?;
+        if let Some(provided) = provided {
+            if stream.root != provided.root {
+                return Err(match provided.seed {
+                    Some(seed) => {
+                        anyhow!("Checkpoint seed root does not match --seed ({})", seed)
+                    }
+                    None => anyhow!(
+                        "Checkpoint seed root does not match the given root {}",
+                        provided.root_id()
+                    ),
+                });
+            }
+        }
+        Ok(stream)
+    }
+}
+
+/// --resume-auto: the checkpoint in `dir` with the highest `next_attempt` that this search
+/// can resume (same seed if one is given, same shard, and a config hash equal to
+/// `fingerprint` of its stream). Other files, unreadable or oversized ones included, are
+/// skipped; a missing directory just means a fresh start.
+pub fn latest_checkpoint(
+    dir: &Path,
+    provided: Option<&KeyStream>,
+    permute: bool,
+    shard: Shard,
+    fingerprint: impl Fn(&KeyStream) -> String,
+) -> Result<Option<(PathBuf, CheckpointFile)>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => {
+            return Err(err)
+                .with_context(|| format!("Unable to read --resume-auto dir {}", dir.display()))
+        }
+    };
+    let mut found: Vec<(PathBuf, CheckpointFile)> = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if !fs::metadata(&path)
+            .is_ok_and(|meta| meta.is_file() && meta.len() <= MAX_CHECKPOINT_BYTES)
+        {
+            continue;
+        }
+        let Ok(checkpoint) = load_checkpoint_file(&path) else {
+            continue;
+        };
+        let Ok(stream) = checkpoint.matching_stream(provided) else {
+            continue;
+        };
+        let hash = fingerprint(&stream.permuted(permute));
+        if checkpoint.shard.unwrap_or_default() == shard
+            && checkpoint.ensure_config_hash(&hash).is_ok()
+        {
+            found.push((path, checkpoint));
+        }
+    }
+    // Ties go to the first path by name, so the pick doesn't depend on directory order.
+    Ok(found.into_iter().max_by(|(a_path, a), (b_path, b)| {
+        a.next_attempt
+            .cmp(&b.next_attempt)
+            .then_with(|| b_path.cmp(a_path))
+    }))
+}
+
+pub fn save_checkpoint_file(path: &Path, payload: &CheckpointFile) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create checkpoint dir {}", parent.display()))?;
+    }
+    let data = serde_json::to_vec_pretty(payload)?;
+    write_atomic(path, &compress_for_path(path, &data)?)
+        .with_context(|| format!("Failed to write checkpoint {}", path.display()))?;
+    Ok(())
+}
+
+// RFC 3339 in UTC (`2024-05-01T12:00:00Z`), without pulling in a date crate.
+fn utc_timestamp(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+    // Civil-from-days (Howard Hinnant), shifted so years start in March.
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3_600,
+        rem % 3_600 / 60,
+        rem % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn version_one_checkpoints_still_load() {
+        let dir = std::env::temp_dir().join(format!("vanity-ckpt-{}", std::process::id()));
+        let path = dir.join("v1.json");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            &path,
+            r#"{"version":1,"next_attempt":42,"base_seed":7,"config_hash":"ab"}"#,
+        )
+        .unwrap();
+        let loaded = load_checkpoint_file(&path).unwrap();
+        assert_eq!(loaded.next_attempt, 42);
+        assert_eq!((loaded.elapsed_ms, loaded.matches_found), (0, 0));
+        assert!(loaded.created_at.is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn load_failures_are_typed() {
+        let dir = std::env::temp_dir().join(format!("vanity-ckpt-err-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("run.json");
+        assert!(matches!(
+            load_checkpoint_file(&path),
+            Err(VanityError::CheckpointUnreadable { .. })
+        ));
+        fs::write(
+            &path,
+            r#"{"version":9,"next_attempt":0,"config_hash":"ab"}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            load_checkpoint_file(&path),
+            Err(VanityError::CheckpointVersion(9))
+        ));
+        fs::write(
+            &path,
+            r#"{"version":2,"next_attempt":0,"config_hash":"ab"}"#,
+        )
+        .unwrap();
+        let loaded = load_checkpoint_file(&path).unwrap();
+        assert!(loaded.ensure_config_hash("ab").is_ok());
+        match loaded.ensure_config_hash("cd") {
+            Err(VanityError::CheckpointMismatch { expected, found }) => {
+                assert_eq!((expected.as_str(), found.as_str()), ("cd", "ab"));
+            }
+            other => panic!("expected a mismatch, got {other:?}"),
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn gzip_checkpoints_round_trip() {
+        let dir = std::env::temp_dir().join(format!("vanity-ckpt-gz-{}", std::process::id()));
+        let path = dir.join("run.json.gz");
+        let writer = CheckpointWriter::new(
+            path.clone(),
+            "cd".into(),
+            KeyStream::from_seed(1),
+            Shard::default(),
+            10,
+        );
+        writer.force_write(99, 2).unwrap();
+        assert_eq!(&fs::read(&path).unwrap()[..2], &[0x1f, 0x8b]);
+        let loaded = load_checkpoint_file(&path).unwrap();
+        assert_eq!((loaded.next_attempt, loaded.matches_found), (99, 2));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn matches_are_only_counted_when_a_write_is_due() {
+        let dir = std::env::temp_dir().join(format!("vanity-ckpt-due-{}", std::process::id()));
+        let path = dir.join("run.json");
+        let writer = CheckpointWriter::new(
+            path.clone(),
+            "ab".into(),
+            KeyStream::from_seed(1),
+            Shard::default(),
+            100,
+        );
+        let counted = std::cell::Cell::new(0);
+        let count = || {
+            counted.set(counted.get() + 1);
+            3
+        };
+        writer.maybe_write(10, count);
+        assert_eq!(counted.get(), 1);
+        // The next write is due at 110.
+        writer.maybe_write(50, count);
+        assert_eq!(counted.get(), 1);
+        writer.maybe_write(110, count);
+        assert_eq!(counted.get(), 2);
+        let loaded = load_checkpoint_file(&path).unwrap();
+        assert_eq!((loaded.next_attempt, loaded.matches_found), (110, 3));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn poisoned_writer_still_writes() {
+        let dir = std::env::temp_dir().join(format!("vanity-ckpt-poison-{}", std::process::id()));
+        let path = dir.join("run.json");
+        let writer = CheckpointWriter::new(
+            path.clone(),
+            "ef".into(),
+            KeyStream::from_seed(1),
+            Shard::default(),
+            1,
+        );
+        let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _guard = writer.lock.lock().unwrap();
+            panic!("worker died holding the checkpoint lock");
+        }));
+        assert!(writer.lock.is_poisoned());
+        writer.maybe_write(5, || 0);
+        assert_eq!(load_checkpoint_file(&path).unwrap().next_attempt, 5);
+        writer.force_write(7, 1).unwrap();
+        assert_eq!(load_checkpoint_file(&path).unwrap().next_attempt, 7);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn the_furthest_resumable_checkpoint_is_picked() {
+        let dir = std::env::temp_dir().join(format!("vanity-latest-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("subdir.json")).unwrap();
+        let shard = Shard::default();
+        let write = |name: &str, hash: &str, seed: u64, shard: Shard, next: u64| {
+            let stream = KeyStream::from_seed(seed);
+            CheckpointWriter::new(dir.join(name), hash.into(), stream, shard, 1)
+                .force_write(next, 0)
+                .unwrap();
+        };
+        write("early.json", "x", 1, shard, 100);
+        write("b-tie.json", "x", 1, shard, 300);
+        write("a-tie.json", "x", 1, shard, 300);
+        write("other-search.json", "y", 1, shard, 900);
+        write("other-seed.json", "x", 2, shard, 900);
+        write("other-shard.json", "x", 1, Shard::new(1, 2).unwrap(), 900);
+        // A resumable checkpoint, but padded to the size of a result file.
+        write("padded.json", "x", 1, shard, 900);
+        let padded = dir.join("padded.json");
+        let mut raw = fs::read(&padded).unwrap();
+        raw.resize(MAX_CHECKPOINT_BYTES as usize + 1, b' ');
+        fs::write(&padded, raw).unwrap();
+
+        // Stands in for `config_fingerprint`: hash "x" belongs to seed 1's root only.
+        let fingerprint = |stream: &KeyStream| {
+            if stream.root == KeyStream::from_seed(1).root {
+                "x".to_string()
+            } else {
+                "z".to_string()
+            }
+        };
+        let (path, checkpoint) = latest_checkpoint(&dir, None, false, shard, fingerprint)
+            .unwrap()
+            .unwrap();
+        assert_eq!(path, dir.join("a-tie.json"));
+        assert_eq!(checkpoint.next_attempt, 300);
+        // A given seed rules out checkpoints of other roots.
+        let seed_two = KeyStream::from_seed(2);
+        let picked = latest_checkpoint(&dir, Some(&seed_two), false, shard, |_| "x".into());
+        assert_eq!(picked.unwrap().unwrap().0, dir.join("other-seed.json"));
+        assert!(
+            latest_checkpoint(&dir.join("missing"), None, false, shard, fingerprint)
+                .unwrap()
+                .is_none()
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn utc_timestamps_are_rfc3339() {
+        assert_eq!(utc_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        let leap_day = UNIX_EPOCH + Duration::from_secs(951_827_696);
+        assert_eq!(utc_timestamp(leap_day), "2000-02-29T12:34:56Z");
+    }
+}
@@ -0,0 +1,149 @@
+//! BIP-380 output descriptors (`wpkh(<wif>)#checksum`), so results import directly with
+//! Bitcoin Core's `importdescriptors`.
+
+use bip32::DerivationPath;
+
+use crate::address::AddressFormat;
+
+// Character positions feed the checksum: the low 5 bits per character, the high bits per
+// group of three.
+const INPUT_CHARSET: &[u8] = b"0123456789()[],'/*abcdefgh@:$%{}IJKLMNOPQRSTUVWXYZ&+-.;<=>?!^_|~ijklmnopqrstuvwxyzABCDEFGH`#\"\\ ";
+const CHECKSUM_CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const GENERATOR: [u64; 5] = [
+    0xf5dee51989,
+    0xa9fdca3312,
+    0x1bab10e32d,
+    0x3706b1677a,
+    0x644d626ffd,
+];
+
+/// `key` (a WIF or key expression) in the script of `format`, with its checksum appended.
+/// `None` for witness versions 2–16, which no descriptor covers.
+pub fn descriptor(format: AddressFormat, witness_version: u8, key: &str) -> Option<String> {
+    let body = match (format, witness_version) {
+        (AddressFormat::P2pkh, _) => format!("pkh({key})"),
+        (AddressFormat::P2shP2wpkh, _) => format!("sh(wpkh({key}))"),
+        (AddressFormat::Bech32, 0) => format!("wpkh({key})"),
+        // `tr()` takes the untweaked internal key and applies the BIP-341 tweak itself.
+        (AddressFormat::Bech32, 1) => format!("tr({key})"),
+        (AddressFormat::Bech32, _) => return None,
+        (AddressFormat::P2wsh, _) => format!("wsh(pk({key}))"),
+    };
+    let checksum = checksum(&body)?;
+    Some(format!("{body}#{checksum}"))
+}
+
+/// `master` followed by `path`, with a non-hardened last step replaced by `*` so the
+/// descriptor ranges over the whole branch (`xprv…/84h/0h/0h/0/*`).
+pub fn ranged_key(master: &str, path: &DerivationPath) -> String {
+    let steps: Vec<_> = path.iter().collect();
+    let mut key = master.to_string();
+    for (at, child) in steps.iter().enumerate() {
+        if at + 1 == steps.len() && !child.is_hardened() {
+            key.push_str("/*");
+        } else if child.is_hardened() {
+            key.push_str(&format!("/{}h", child.index()));
+        } else {
+            key.push_str(&format!("/{}", child.index()));
+        }
+    }
+    key
+}
+
+/// Whether `descriptor` ends in the `#checksum` of everything before it.
+pub fn has_valid_checksum(descriptor: &str) -> bool {
+    descriptor
+        .rsplit_once('#')
+        .is_some_and(|(body, sum)| checksum(body).as_deref() == Some(sum))
+}
+
+/// The 8-character checksum of `descriptor`, or `None` if it holds a character outside
+/// the descriptor charset.
+pub fn checksum(descriptor: &str) -> Option<String> {
+    let mut symbols = Vec::with_capacity(descriptor.len() * 2 + 8);
+    let mut groups = Vec::with_capacity(3);
+    for c in descriptor.bytes() {
+        let value = INPUT_CHARSET.iter().position(|&p| p == c)? as u64;
+        symbols.push(value & 31);
+        groups.push(value >> 5);
+        if groups.len() == 3 {
+            symbols.push(groups[0] * 9 + groups[1] * 3 + groups[2]);
+            groups.clear();
+        }
+    }
+    match groups[..] {
+        [a] => symbols.push(a),
+        [a, b] => symbols.push(a * 3 + b),
+        _ => {}
+    }
+    symbols.extend([0; 8]);
+    let sum = polymod(&symbols) ^ 1;
+    Some(
+        (0..8)
+            .map(|i| CHECKSUM_CHARSET[((sum >> (5 * (7 - i))) & 31) as usize] as char)
+            .collect(),
+    )
+}
+
+fn polymod(symbols: &[u64]) -> u64 {
+    let mut chk = 1u64;
+    for &value in symbols {
+        let top = chk >> 35;
+        chk = ((chk & 0x7_ffff_ffff) << 5) ^ value;
+        for (i, generator) in GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                chk ^= generator;
+            }
+        }
+    }
+    chk
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn checksums_match_bip380_vectors() {
+        assert_eq!(checksum("raw(deadbeef)").unwrap(), "89f8spxm");
+        assert_eq!(
+            checksum("addr(mkmZxiEcEd8ZqjQWVZuC6so5dFMKEFpN2j)").unwrap(),
+            "02wpgw69"
+        );
+        assert!(has_valid_checksum("raw(deadbeef)#89f8spxm"));
+        assert!(!has_valid_checksum("raw(deadbeef)#89f8spxn"));
+        assert!(checksum("raw(dead\u{e9})").is_none());
+    }
+
+    #[test]
+    fn key_one_descriptors_per_format() {
+        let wif = "KwDiBf89QgGbjEhKnhXJuH7LrciVrZi3qYjgd9M7rFU73sVHnoWn";
+        let of = |format, version| descriptor(format, version, wif);
+        assert_eq!(
+            of(AddressFormat::Bech32, 0).unwrap(),
+            format!("wpkh({wif})#gul0776m")
+        );
+        assert_eq!(
+            of(AddressFormat::P2pkh, 0).unwrap(),
+            format!("pkh({wif})#yj0ctua6")
+        );
+        assert_eq!(
+            of(AddressFormat::P2wsh, 0).unwrap(),
+            format!("wsh(pk({wif}))#p25k6dzq")
+        );
+        assert!(of(AddressFormat::P2shP2wpkh, 0)
+            .unwrap()
+            .starts_with("sh(wpkh("));
+        assert!(of(AddressFormat::Bech32, 1).unwrap().starts_with("tr("));
+        assert!(of(AddressFormat::Bech32, 2).is_none());
+    }
+
+    #[test]
+    fn ranged_keys_end_in_a_wildcard() {
+        let path = DerivationPath::from_str("m/84'/0'/0'/0/7").unwrap();
+        assert_eq!(ranged_key("xprv", &path), "xprv/84h/0h/0h/0/*");
+        let hardened = DerivationPath::from_str("m/0'/1'").unwrap();
+        assert_eq!(ranged_key("xprv", &hardened), "xprv/0h/1h");
+    }
+}
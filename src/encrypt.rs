@@ -0,0 +1,107 @@
+//! Password-encrypted result container: Argon2id key derivation + XChaCha20-Poly1305.
+
+use anyhow::{anyhow, Context, Result};
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit},
+    XChaCha20Poly1305, XNonce,
+};
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+
+const CONTAINER_VERSION: u32 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+
+// Everything needed to decrypt except the password; the header fields are not secret.
+#[derive(Serialize, Deserialize)]
+pub struct EncryptedContainer {
+    pub version: u32,
+    pub kdf: String,
+    pub m_cost_kib: u32,
+    pub t_cost: u32,
+    pub p_cost: u32,
+    pub salt: String,
+    pub cipher: String,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+pub fn seal(plaintext: &[u8], password: &str) -> Result<EncryptedContainer> {
+    let params = Params::default();
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let mut nonce = [0u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut nonce);
+    let cipher = cipher_for(password, &salt, &params)?;
+    let ciphertext = cipher
+        .encrypt(&XNonce::from(nonce), plaintext)
+        .map_err(|_| anyhow!("Encryption failed"))?;
+    Ok(EncryptedContainer {
+        version: CONTAINER_VERSION,
+        kdf: "argon2id".to_string(),
+        m_cost_kib: params.m_cost(),
+        t_cost: params.t_cost(),
+        p_cost: params.p_cost(),
+        salt: hex::encode(salt),
+        cipher: "xchacha20poly1305".to_string(),
+        nonce: hex::encode(nonce),
+        ciphertext: hex::encode(ciphertext),
+    })
+}
+
+pub fn open(container: &EncryptedContainer, password: &str) -> Result<Vec<u8>> {
+    if container.version != CONTAINER_VERSION {
+        return Err(anyhow!(
+            "Unsupported encrypted container version {}",
+            container.version
+        ));
+    }
+    if container.kdf != "argon2id" || container.cipher != "xchacha20poly1305" {
+        return Err(anyhow!(
+            "Unsupported container scheme {}/{}",
+            container.kdf,
+            container.cipher
+        ));
+    }
+    let params = Params::new(
+        container.m_cost_kib,
+        container.t_cost,
+        container.p_cost,
+        None,
+    )
+    .map_err(|e| anyhow!("Invalid Argon2 parameters: {e}"))?;
+    let salt = hex::decode(&container.salt).context("Invalid container salt")?;
+    let nonce: [u8; NONCE_LEN] = hex::decode(&container.nonce)
+        .context("Invalid container nonce")?
+        .try_into()
+        .map_err(|_| anyhow!("Container nonce must be {} bytes", NONCE_LEN))?;
+    let ciphertext = hex::decode(&container.ciphertext).context("Invalid container ciphertext")?;
+    let cipher = cipher_for(password, &salt, &params)?;
+    cipher
+        .decrypt(&XNonce::from(nonce), ciphertext.as_slice())
+        .map_err(|_| anyhow!("Wrong password or corrupted file"))
+}
+
+fn cipher_for(password: &str, salt: &[u8], params: &Params) -> Result<XChaCha20Poly1305> {
+    let mut key = [0u8; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params.clone())
+        .hash_password_into(password.as_bytes(), salt, &mut key)
+        .map_err(|e| anyhow!("Argon2 key derivation failed: {e}"))?;
+    Ok(XChaCha20Poly1305::new(&key.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_and_rejects_wrong_password() {
+        let container = seal(b"[{\"address\":\"1Bad\"}]", "hunter2").unwrap();
+        assert_eq!(
+            open(&container, "hunter2").unwrap(),
+            b"[{\"address\":\"1Bad\"}]"
+        );
+        assert!(open(&container, "hunter3").is_err());
+    }
+}
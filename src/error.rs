@@ -0,0 +1,44 @@
+//! Typed failures of the library's entry points (`prepare_pattern`, `encode_address`,
+//! `load_checkpoint_file` and their neighbours), so embedders can match on a variant rather
+//! than an error string. The binaries wrap them in `anyhow` for display.
+
+use std::{error::Error as StdError, path::PathBuf};
+
+use thiserror::Error;
+
+use crate::address::AddressFormat;
+
+#[derive(Debug, Error)]
+pub enum VanityError {
+    /// `pattern` has characters outside the format's alphabet, or misspells a fixed part
+    /// such as the Bech32 HRP; `message` says which.
+    #[error("{message}")]
+    InvalidPattern { pattern: String, message: String },
+    /// A well-formed pattern that no address of the format can match: too long, or
+    /// contradicting the characters its version byte fixes.
+    #[error("{0}")]
+    InfeasiblePattern(String),
+    /// P2WSH beyond version 0, or Bech32 beyond 16.
+    #[error("{format:?} does not support witness version {version}")]
+    UnsupportedWitnessVersion { format: AddressFormat, version: u8 },
+    #[error("{0:?} requires compressed public keys")]
+    UncompressedKey(AddressFormat),
+    /// The encoder rejected its input, e.g. an HRP Bech32 cannot carry.
+    #[error("{0}")]
+    Encoding(String),
+    /// A key or tweak fell outside the curve order, or an HD step failed.
+    #[error("{0}")]
+    KeyDerivation(String),
+    /// The checkpoint file could not be read, decompressed or parsed.
+    #[error("Unable to read checkpoint {}", .path.display())]
+    CheckpointUnreadable {
+        path: PathBuf,
+        #[source]
+        source: Box<dyn StdError + Send + Sync>,
+    },
+    #[error("Unsupported checkpoint version {0}")]
+    CheckpointVersion(u32),
+    /// The checkpoint's `config_hash` (`found`) is not the one this search computes.
+    #[error("Checkpoint was created for different search parameters.")]
+    CheckpointMismatch { expected: String, found: String },
+}
@@ -0,0 +1,908 @@
+//! Deterministic `(root, attempt)` key schedule for raw keys and BIP-39 mnemonics.
+
+use anyhow::{anyhow, Result};
+use bip32::{ChildNumber, DerivationPath, Prefix, XPrv};
+use bip39::{Language, Mnemonic};
+use hmac::{Hmac, Mac};
+use k256::{
+    elliptic_curve::{
+        sec1::{FromEncodedPoint, ToEncodedPoint},
+        BatchNormalize,
+    },
+    AffinePoint, EncodedPoint, ProjectivePoint,
+};
+use rand::{rngs::OsRng, RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use secp256k1::{PublicKey, Scalar, SecretKey};
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::address::{AddressFormat, Network, SECP256K1};
+
+#[derive(Clone)]
+pub enum KeyMode {
+    Raw,
+    Mnemonic {
+        path: DerivationPath,
+        path_string: String,
+        words: usize,
+        passphrase: String,
+        // > 1 replaces the last (non-hardened) component of `path` with 0..scan_indices, so
+        // one mnemonic->seed step covers that many receive addresses.
+        scan_indices: u32,
+    },
+    // The searcher only knows `base`; each attempt yields an offset `o` and the address of
+    // `base + o*G`. The owner of the base secret recovers the key as `base_secret + o`.
+    SplitKey {
+        base: PublicKey,
+    },
+    // Secrets are `base + attempt` for a base fixed by the stream root, so neighbouring
+    // public keys differ by G and a worker can build them by point addition with one field
+    // inversion per chunk (see `SequentialBatch`). Faster, but keys are no longer
+    // independent: anyone who learns one key and its attempt index learns them all.
+    Sequential,
+    // One user-supplied mnemonic: attempt `i` is child `i` under the parent of `path`, so
+    // the search looks for a vanity receive address in an existing wallet. Keys don't
+    // depend on the stream, and the index space ends at `HD_INDICES`.
+    FixedMnemonic {
+        phrase: String,
+        path: DerivationPath,
+        path_string: String,
+        passphrase: String,
+        parent: HdParent,
+    },
+}
+
+/// Non-hardened child indices, and so the attempts a `KeyMode::FixedMnemonic` search has.
+pub const HD_INDICES: u64 = 1 << 31;
+
+/// The parent node a fixed-mnemonic search derives every child from, derived once.
+#[derive(Clone)]
+pub struct HdParent {
+    node: HdNode,
+    public: PublicKey,
+}
+
+impl KeyMode {
+    /// Fixed-mnemonic mode for `phrase`, whose last `path` component (non-hardened) is the
+    /// one the attempt replaces. The phrase is stored normalised, as wallets print it.
+    pub fn fixed_mnemonic(
+        phrase: &str,
+        path: DerivationPath,
+        path_string: String,
+        passphrase: String,
+    ) -> Result<Self> {
+        let mnemonic = Mnemonic::parse_in(Language::English, phrase)
+            .map_err(|err| anyhow!("Invalid --fixed-mnemonic: {}", err))?;
+        if path.iter().last().is_none_or(|last| last.is_hardened()) {
+            return Err(anyhow!(
+                "--fixed-mnemonic needs an --hd-path ending in a non-hardened index, got {}",
+                path_string
+            ));
+        }
+        let node = path
+            .parent()
+            .and_then(|parent| {
+                HdNode::from_seed(&mnemonic.to_seed(&passphrase))?.derive(parent.iter())
+            })
+            .ok_or_else(|| {
+                anyhow!(
+                    "{} has an invalid parent key for this mnemonic",
+                    path_string
+                )
+            })?;
+        let public = PublicKey::from_secret_key(&SECP256K1, &node.secret);
+        Ok(KeyMode::FixedMnemonic {
+            phrase: mnemonic.to_string(),
+            path,
+            path_string,
+            passphrase,
+            parent: HdParent { node, public },
+        })
+    }
+
+    pub fn path_string(&self) -> Option<&str> {
+        match self {
+            KeyMode::Raw | KeyMode::SplitKey { .. } | KeyMode::Sequential => None,
+            KeyMode::Mnemonic { path_string, .. } | KeyMode::FixedMnemonic { path_string, .. } => {
+                Some(path_string.as_str())
+            }
+        }
+    }
+
+    pub fn passphrase_used(&self) -> bool {
+        match self {
+            KeyMode::Mnemonic { passphrase, .. } | KeyMode::FixedMnemonic { passphrase, .. } => {
+                !passphrase.is_empty()
+            }
+            _ => false,
+        }
+    }
+
+    // The path and passphrase a matched phrase is re-derived under.
+    fn hd_parts(&self) -> Option<(&DerivationPath, &str)> {
+        match self {
+            KeyMode::Mnemonic {
+                path, passphrase, ..
+            }
+            | KeyMode::FixedMnemonic {
+                path, passphrase, ..
+            } => Some((path, passphrase)),
+            _ => None,
+        }
+    }
+
+    /// The full HD path a candidate came from (its scanned index substituted in).
+    pub fn candidate_path(&self, candidate: &CandidateKey) -> Option<String> {
+        let path_string = self.path_string()?;
+        Some(match candidate.child_index {
+            Some(index) => {
+                let parent = path_string
+                    .rsplit_once('/')
+                    .map_or("m", |(parent, _)| parent);
+                format!("{}/{}", parent, index)
+            }
+            None => path_string.to_string(),
+        })
+    }
+}
+
+// Key material is the ChaCha20 keystream block `attempt` under a 256-bit root. The root is
+// drawn from the OS RNG, given directly (--seed-hex/--seed-file), or expanded from --seed
+// so deterministic runs stay reproducible. With `permute`, attempt `i` reads block
+// `permute_attempt(root, i)` instead, so a partial run covers blocks spread over the whole
+// index space rather than `0..i`.
+#[derive(Clone, Copy)]
+pub struct KeyStream {
+    pub root: [u8; 32],
+    pub seed: Option<u64>,
+    pub permute: bool,
+}
+
+impl KeyStream {
+    pub fn from_seed(seed: u64) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(b"vanity_bitcoin/seed-root");
+        hasher.update(seed.to_le_bytes());
+        let mut root = [0u8; 32];
+        root.copy_from_slice(&hasher.finalize());
+        Self {
+            root,
+            seed: Some(seed),
+            permute: false,
+        }
+    }
+
+    /// A caller-supplied root: 64 hex characters (optional `0x`), used as-is.
+    pub fn from_root_hex(hex: &str) -> Result<Self> {
+        let bytes = hex::decode(hex.trim().trim_start_matches("0x"))
+            .map_err(|err| anyhow!("root is not hex: {}", err))?;
+        let root: [u8; 32] = bytes
+            .try_into()
+            .map_err(|bytes: Vec<u8>| anyhow!("root must be 32 bytes, got {}", bytes.len()))?;
+        Ok(Self {
+            root,
+            seed: None,
+            permute: false,
+        })
+    }
+
+    /// First 8 bytes of the root's SHA-256, hex: names the root in results without
+    /// revealing it.
+    pub fn root_id(&self) -> String {
+        hex::encode(&Sha256::digest(self.root)[..8])
+    }
+
+    pub fn random() -> Self {
+        let mut root = [0u8; 32];
+        OsRng.fill_bytes(&mut root);
+        Self {
+            root,
+            seed: None,
+            permute: false,
+        }
+    }
+
+    /// This stream with `--permute`'s attempt order switched on or off.
+    pub fn permuted(self, permute: bool) -> Self {
+        Self { permute, ..self }
+    }
+}
+
+#[derive(Clone)]
+pub struct CandidateKey {
+    // In split-key mode this is the offset, not a spendable key.
+    pub secret: SecretKey,
+    pub public: PublicKey,
+    pub mnemonic: Option<String>,
+    // Set when the mnemonic modes scan indices: the last path component used.
+    pub child_index: Option<u32>,
+}
+
+/// Every candidate for `attempt`: one, or `scan_indices` children sharing a mnemonic.
+/// `out` is cleared first so callers can reuse it across attempts.
+pub fn derive_candidates(
+    stream: &KeyStream,
+    attempt: u64,
+    mode: &KeyMode,
+    out: &mut Vec<CandidateKey>,
+) {
+    out.clear();
+    let KeyMode::Mnemonic {
+        path,
+        words,
+        passphrase,
+        scan_indices,
+        ..
+    } = mode
+    else {
+        out.extend(derive_candidate(stream, attempt, mode));
+        return;
+    };
+    if *scan_indices <= 1 {
+        out.extend(derive_candidate(stream, attempt, mode));
+        return;
+    }
+    let Some((phrase, seed)) = mnemonic_seed(stream, attempt, *words, passphrase) else {
+        return;
+    };
+    let Some(parent) = path
+        .parent()
+        .and_then(|parent| HdNode::from_seed(&seed)?.derive(parent.iter()))
+    else {
+        return;
+    };
+    // One multiplication for the parent's public key serves every sibling.
+    let parent_public = PublicKey::from_secret_key(&SECP256K1, &parent.secret);
+    for index in 0..*scan_indices {
+        let Some(child) = ChildNumber::new(index, false)
+            .ok()
+            .and_then(|number| parent.child(number, Some(&parent_public)))
+        else {
+            continue;
+        };
+        out.push(CandidateKey {
+            secret: child.secret,
+            public: PublicKey::from_secret_key(&SECP256K1, &child.secret),
+            mnemonic: Some(phrase.clone()),
+            child_index: Some(index),
+        });
+    }
+}
+
+fn mnemonic_seed(
+    stream: &KeyStream,
+    attempt: u64,
+    words: usize,
+    passphrase: &str,
+) -> Option<(String, [u8; 64])> {
+    // 4 bytes of entropy per 3 words (128..=256 bits).
+    let mut entropy = [0u8; 32];
+    let entropy = &mut entropy[..words * 4 / 3];
+    key_material_from_attempt(stream, attempt, entropy);
+    let mnemonic = Mnemonic::from_entropy_in(Language::English, entropy).ok()?;
+    let seed = mnemonic.to_seed(passphrase);
+    Some((mnemonic.to_string(), seed))
+}
+
+// BIP-32 private derivation on the global libsecp256k1 context. `XPrv` (k256) computes
+// the parent's public key at every step for its fingerprint, which the hot loop never
+// needs; it stays for the extended keys reported with a match.
+#[derive(Clone)]
+struct HdNode {
+    secret: SecretKey,
+    chain_code: [u8; 32],
+}
+
+impl HdNode {
+    fn from_seed(seed: &[u8]) -> Option<Self> {
+        let mut mac = Hmac::<Sha512>::new_from_slice(b"Bitcoin seed").ok()?;
+        mac.update(seed);
+        Self::from_hmac(None, &mac.finalize().into_bytes())
+    }
+
+    fn derive(self, path: impl IntoIterator<Item = ChildNumber>) -> Option<Self> {
+        path.into_iter()
+            .try_fold(self, |node, number| node.child(number, None))
+    }
+
+    // `public` may pass this node's public key in, to derive many non-hardened siblings
+    // with one multiplication.
+    fn child(&self, number: ChildNumber, public: Option<&PublicKey>) -> Option<Self> {
+        let mut mac = Hmac::<Sha512>::new_from_slice(&self.chain_code).ok()?;
+        if number.is_hardened() {
+            mac.update(&[0]);
+            mac.update(&self.secret.secret_bytes());
+        } else {
+            let public = match public {
+                Some(public) => *public,
+                None => PublicKey::from_secret_key(&SECP256K1, &self.secret),
+            };
+            mac.update(&public.serialize());
+        }
+        mac.update(&u32::from(number).to_be_bytes());
+        Self::from_hmac(Some(&self.secret), &mac.finalize().into_bytes())
+    }
+
+    // The left half tweaks the parent secret (or is the master secret), the right half is
+    // the chain code. `None` for the invalid keys BIP-32 says to skip.
+    fn from_hmac(parent: Option<&SecretKey>, output: &[u8]) -> Option<Self> {
+        let (left, right) = output.split_at(32);
+        let secret = match parent {
+            None => SecretKey::from_slice(left).ok()?,
+            Some(parent) => {
+                let tweak = Scalar::from_be_bytes(left.try_into().ok()?).ok()?;
+                parent.add_tweak(&tweak).ok()?
+            }
+        };
+        Some(Self {
+            secret,
+            chain_code: right.try_into().ok()?,
+        })
+    }
+}
+
+pub fn derive_candidate(stream: &KeyStream, attempt: u64, mode: &KeyMode) -> Option<CandidateKey> {
+    match mode {
+        KeyMode::Raw => {
+            let mut material = [0u8; 32];
+            key_material_from_attempt(stream, attempt, &mut material);
+            let secret = SecretKey::from_slice(&material).ok()?;
+            Some(CandidateKey {
+                secret,
+                public: PublicKey::from_secret_key(&SECP256K1, &secret),
+                mnemonic: None,
+                child_index: None,
+            })
+        }
+        KeyMode::Mnemonic {
+            path,
+            words,
+            passphrase,
+            ..
+        } => {
+            let (phrase, seed) = mnemonic_seed(stream, attempt, *words, passphrase)?;
+            let secret = HdNode::from_seed(&seed)?.derive(path.iter())?.secret;
+            Some(CandidateKey {
+                secret,
+                public: PublicKey::from_secret_key(&SECP256K1, &secret),
+                mnemonic: Some(phrase),
+                child_index: None,
+            })
+        }
+        KeyMode::Sequential => {
+            let secret = sequential_secret(&sequential_base(stream)?, attempt)?;
+            Some(CandidateKey {
+                secret,
+                public: PublicKey::from_secret_key(&SECP256K1, &secret),
+                mnemonic: None,
+                child_index: None,
+            })
+        }
+        KeyMode::FixedMnemonic { phrase, parent, .. } => {
+            let index = u32::try_from(attempt).ok()?;
+            let child = parent
+                .node
+                .child(ChildNumber::new(index, false).ok()?, Some(&parent.public))?;
+            Some(CandidateKey {
+                secret: child.secret,
+                public: PublicKey::from_secret_key(&SECP256K1, &child.secret),
+                mnemonic: Some(phrase.clone()),
+                child_index: Some(index),
+            })
+        }
+        KeyMode::SplitKey { base } => {
+            let mut material = [0u8; 32];
+            key_material_from_attempt(stream, attempt, &mut material);
+            let offset = SecretKey::from_slice(&material).ok()?;
+            let public = base.add_exp_tweak(&SECP256K1, &Scalar::from(offset)).ok()?;
+            Some(CandidateKey {
+                secret: offset,
+                public,
+                mnemonic: None,
+                child_index: None,
+            })
+        }
+    }
+}
+
+/// Starting secret of the sequential schedule, domain-separated from the random-key stream.
+pub fn sequential_base(stream: &KeyStream) -> Option<SecretKey> {
+    let mut hasher = Sha256::new();
+    hasher.update(b"vanity_bitcoin/sequential-base");
+    hasher.update(stream.root);
+    SecretKey::from_slice(&hasher.finalize()).ok()
+}
+
+fn sequential_secret(base: &SecretKey, attempt: u64) -> Option<SecretKey> {
+    if attempt == 0 {
+        return Some(*base);
+    }
+    let mut tweak = [0u8; 32];
+    tweak[24..].copy_from_slice(&attempt.to_be_bytes());
+    base.add_tweak(&Scalar::from_be_bytes(tweak).ok()?).ok()
+}
+
+// Points per batched inversion; stack-allocated (about 40 KiB for both arrays).
+const SEQUENTIAL_CHUNK: usize = 256;
+
+/// Worker-local generator for `KeyMode::Sequential`. Each refill computes one full scalar
+/// multiplication, `SEQUENTIAL_CHUNK - 1` mixed additions of G, and a single batched
+/// normalisation (Montgomery's trick), so most keys cost an addition instead of a
+/// multiplication. A chunk covers `start, start + stride, ...`; sharded searches use the
+/// shard count as the stride so every point computed is one the worker tests.
+pub struct SequentialBatch {
+    base: SecretKey,
+    start: u64,
+    stride: u64,
+    // `stride * G`.
+    step: AffinePoint,
+    publics: Vec<PublicKey>,
+}
+
+impl SequentialBatch {
+    pub fn new(stream: &KeyStream) -> Option<Self> {
+        Self::with_stride(stream, 1)
+    }
+
+    pub fn with_stride(stream: &KeyStream, stride: u64) -> Option<Self> {
+        let stride = stride.max(1);
+        Some(Self {
+            base: sequential_base(stream)?,
+            start: 0,
+            stride,
+            step: (ProjectivePoint::GENERATOR * k256::Scalar::from(stride)).to_affine(),
+            publics: Vec::with_capacity(SEQUENTIAL_CHUNK),
+        })
+    }
+
+    /// Replace `out` with the candidate for `attempt`, refilling the chunk when `attempt`
+    /// falls outside it.
+    pub fn candidates(&mut self, attempt: u64, out: &mut Vec<CandidateKey>) {
+        out.clear();
+        let in_chunk = attempt.checked_sub(self.start).is_some_and(|offset| {
+            offset % self.stride == 0 && offset / self.stride < self.publics.len() as u64
+        });
+        if !in_chunk {
+            self.refill(attempt);
+        }
+        let index = (attempt - self.start) / self.stride;
+        let Some(public) = self.publics.get(index as usize) else {
+            return;
+        };
+        let Some(secret) = sequential_secret(&self.base, attempt) else {
+            return;
+        };
+        out.push(CandidateKey {
+            secret,
+            public: *public,
+            mnemonic: None,
+            child_index: None,
+        });
+    }
+
+    fn refill(&mut self, start: u64) {
+        self.start = start;
+        self.publics.clear();
+        let Some(first) = sequential_secret(&self.base, start) else {
+            return;
+        };
+        let first = PublicKey::from_secret_key(&SECP256K1, &first).serialize_uncompressed();
+        let Ok(first) = EncodedPoint::from_bytes(first) else {
+            return;
+        };
+        let Some(first) = Option::<AffinePoint>::from(AffinePoint::from_encoded_point(&first))
+        else {
+            return;
+        };
+        let mut points = [ProjectivePoint::IDENTITY; SEQUENTIAL_CHUNK];
+        points[0] = ProjectivePoint::from(first);
+        for i in 1..SEQUENTIAL_CHUNK {
+            points[i] = points[i - 1] + self.step;
+        }
+        for affine in ProjectivePoint::batch_normalize(&points) {
+            // Stops at the point at infinity (base + attempt = n), which has no encoding.
+            let Ok(public) = PublicKey::from_slice(affine.to_encoded_point(false).as_bytes())
+            else {
+                break;
+            };
+            self.publics.push(public);
+        }
+    }
+}
+
+/// Re-derive the extended private key at the mode's path (or the scanned `child_index`
+/// under its parent) for a matched mnemonic. Kept out of `derive_candidate` so the hot loop
+/// doesn't pay for extended-key bookkeeping.
+pub fn mnemonic_xprv(phrase: &str, mode: &KeyMode, child_index: Option<u32>) -> Option<XPrv> {
+    let (path, passphrase) = mode.hd_parts()?;
+    let mnemonic = Mnemonic::parse_in(Language::English, phrase).ok()?;
+    let seed = mnemonic.to_seed(passphrase);
+    match child_index {
+        None => XPrv::derive_from_path(seed, path).ok(),
+        Some(index) => XPrv::derive_from_path(seed, &path.parent()?)
+            .ok()?
+            .derive_child(ChildNumber::new(index, false).ok()?)
+            .ok(),
+    }
+}
+
+/// The BIP-32 master key seeded by `phrase` and the mode's passphrase (mnemonic modes only).
+pub fn mnemonic_master(phrase: &str, mode: &KeyMode) -> Option<XPrv> {
+    let (_, passphrase) = mode.hd_parts()?;
+    let mnemonic = Mnemonic::parse_in(Language::English, phrase).ok()?;
+    XPrv::new(mnemonic.to_seed(passphrase)).ok()
+}
+
+/// Base58 `(xprv, xpub)` for the network (`xprv`/`xpub` on mainnet, `tprv`/`tpub` otherwise).
+pub fn extended_keys(xprv: &XPrv, network: Network) -> (String, String) {
+    let (private, public) = match network {
+        Network::Mainnet => (Prefix::XPRV, Prefix::XPUB),
+        Network::Testnet | Network::Regtest => (Prefix::TPRV, Prefix::TPUB),
+    };
+    (
+        xprv.to_string(private).to_string(),
+        xprv.public_key().to_string(public),
+    )
+}
+
+/// Why `path` is not the standard `m/purpose'/coin'/account'/change/index` path wallets use
+/// for this format (BIP-44/49/84/86), or `None` when it is. `network` is `None` for custom
+/// parameters, whose coin type is unknown.
+pub fn hd_path_mismatch(
+    path: &DerivationPath,
+    format: AddressFormat,
+    witness_version: u8,
+    network: Option<Network>,
+) -> Option<String> {
+    let purpose = match (format, witness_version) {
+        (AddressFormat::P2pkh, _) => 44,
+        (AddressFormat::P2shP2wpkh, _) => 49,
+        (AddressFormat::Bech32, 0) => 84,
+        (AddressFormat::Bech32, 1) => 86,
+        // No wallet standard covers later witness versions or single-key P2WSH scripts.
+        (AddressFormat::Bech32, _) | (AddressFormat::P2wsh, _) => return None,
+    };
+    let coin = network.map(|network| match network {
+        Network::Mainnet => 0,
+        Network::Testnet | Network::Regtest => 1,
+    });
+    let children: Vec<ChildNumber> = path.iter().collect();
+    let expected = format!(
+        "m/{}'/{}'/0'/0/0",
+        purpose,
+        coin.map_or_else(|| "<coin>".to_string(), |c| c.to_string())
+    );
+    if children.len() != 5 {
+        return Some(format!(
+            "depth {} instead of 5 (expected {})",
+            children.len(),
+            expected
+        ));
+    }
+    if let Some(level) = children[..3].iter().position(|child| !child.is_hardened()) {
+        return Some(format!(
+            "level {} is not hardened (expected {})",
+            level + 1,
+            expected
+        ));
+    }
+    if children[3..].iter().any(|child| child.is_hardened()) {
+        return Some(format!(
+            "change/index must not be hardened (expected {})",
+            expected
+        ));
+    }
+    if children[0].index() != purpose {
+        return Some(format!(
+            "purpose {}' is not {}' for this format (expected {})",
+            children[0].index(),
+            purpose,
+            expected
+        ));
+    }
+    if coin.is_some_and(|coin| children[1].index() != coin) {
+        return Some(format!(
+            "coin type {}' does not match the network (expected {})",
+            children[1].index(),
+            expected
+        ));
+    }
+    if children[3].index() > 1 {
+        return Some(format!(
+            "change level {} is neither 0 (receive) nor 1 (change)",
+            children[3].index()
+        ));
+    }
+    None
+}
+
+/// Final secret for a split-key match: `base_secret + offset (mod n)`.
+pub fn combine_split_key(base_secret: &SecretKey, offset: &SecretKey) -> Result<SecretKey> {
+    base_secret
+        .add_tweak(&Scalar::from(*offset))
+        .map_err(|_| anyhow!("base secret + offset is zero or out of range"))
+}
+
+pub fn key_material_from_attempt(stream: &KeyStream, attempt: u64, out: &mut [u8]) {
+    debug_assert!(out.len() <= 64);
+    let block = if stream.permute {
+        permute_attempt(&stream.root, attempt)
+    } else {
+        attempt
+    };
+    let mut rng = ChaCha20Rng::from_seed(stream.root);
+    // One 64-byte ChaCha block (16 words) per attempt.
+    rng.set_word_pos(u128::from(block) * 16);
+    rng.fill_bytes(out);
+}
+
+const PERMUTE_ROUNDS: usize = 4;
+
+/// A bijection on `u64` keyed by `root`: a balanced Feistel network over the two 32-bit
+/// halves, with round keys hashed from the root and a 64-bit finaliser as round function.
+/// Every attempt maps to exactly one block, so resume and `--derive-attempt` stay exact.
+pub fn permute_attempt(root: &[u8; 32], attempt: u64) -> u64 {
+    let mut hasher = Sha256::new();
+    hasher.update(b"vanity_bitcoin/permute");
+    hasher.update(root);
+    let keys = hasher.finalize();
+    let (mut left, mut right) = ((attempt >> 32) as u32, attempt as u32);
+    for key in keys.chunks_exact(8).take(PERMUTE_ROUNDS) {
+        let key = u64::from_le_bytes(key.try_into().expect("8-byte chunk"));
+        (left, right) = (right, left ^ mix(u64::from(right) ^ key) as u32);
+    }
+    (u64::from(left) << 32) | u64::from(right)
+}
+
+// MurmurHash3's 64-bit finaliser.
+fn mix(mut x: u64) -> u64 {
+    x ^= x >> 33;
+    x = x.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    x ^= x >> 33;
+    x = x.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    x ^ (x >> 33)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_key_offset_combines_to_candidate_public_key() {
+        let base_secret = SecretKey::from_slice(&[0x11; 32]).unwrap();
+        let mode = KeyMode::SplitKey {
+            base: PublicKey::from_secret_key(&SECP256K1, &base_secret),
+        };
+        let candidate = derive_candidate(&KeyStream::from_seed(1), 0, &mode).unwrap();
+        let secret = combine_split_key(&base_secret, &candidate.secret).unwrap();
+        assert_eq!(
+            PublicKey::from_secret_key(&SECP256K1, &secret),
+            candidate.public
+        );
+    }
+
+    #[test]
+    fn sequential_batch_matches_direct_derivation() {
+        let stream = KeyStream::from_seed(3);
+        let mut batch = SequentialBatch::new(&stream).unwrap();
+        let mut out = Vec::new();
+        // Crosses a chunk refill and then jumps backwards into a fresh chunk.
+        for attempt in [0, 1, 255, 256, 257, 10_000, 42] {
+            batch.candidates(attempt, &mut out);
+            let direct = derive_candidate(&stream, attempt, &KeyMode::Sequential).unwrap();
+            assert_eq!(out[0].secret, direct.secret);
+            assert_eq!(out[0].public, direct.public);
+        }
+        let mut one = [0u8; 32];
+        one[31] = 1;
+        let generator =
+            PublicKey::from_secret_key(&SECP256K1, &SecretKey::from_slice(&one).unwrap());
+        let mut strided = SequentialBatch::with_stride(&stream, 3).unwrap();
+        for attempt in [2, 5, 767, 770, 4] {
+            strided.candidates(attempt, &mut out);
+            let direct = derive_candidate(&stream, attempt, &KeyMode::Sequential).unwrap();
+            assert_eq!(out[0].public, direct.public);
+        }
+        let first = derive_candidate(&stream, 0, &KeyMode::Sequential).unwrap();
+        let second = derive_candidate(&stream, 1, &KeyMode::Sequential).unwrap();
+        assert_eq!(first.public.combine(&generator), Ok(second.public));
+    }
+
+    #[test]
+    fn permuted_attempts_are_a_keyed_bijection() {
+        let stream = KeyStream::from_seed(5);
+        // Undo the rounds in reverse: each Feistel round is its own inverse given the key.
+        let unpermute = |block: u64| {
+            let keys = Sha256::new()
+                .chain_update(b"vanity_bitcoin/permute")
+                .chain_update(stream.root)
+                .finalize();
+            let (mut left, mut right) = ((block >> 32) as u32, block as u32);
+            for key in keys.chunks_exact(8).take(PERMUTE_ROUNDS).rev() {
+                let key = u64::from_le_bytes(key.try_into().unwrap());
+                (left, right) = (right ^ mix(u64::from(left) ^ key) as u32, left);
+            }
+            (u64::from(left) << 32) | u64::from(right)
+        };
+        for attempt in [0, 1, 2, 1 << 32, u64::MAX, 123_456_789] {
+            let block = permute_attempt(&stream.root, attempt);
+            assert_eq!(unpermute(block), attempt);
+        }
+        assert_ne!(permute_attempt(&stream.root, 0), 0);
+        assert_ne!(
+            permute_attempt(&stream.root, 7),
+            permute_attempt(&KeyStream::from_seed(6).root, 7)
+        );
+
+        let permuted = stream.permuted(true);
+        let block = permute_attempt(&stream.root, 3);
+        let (mut shuffled, mut direct) = ([0u8; 32], [0u8; 32]);
+        key_material_from_attempt(&permuted, 3, &mut shuffled);
+        key_material_from_attempt(&stream, block, &mut direct);
+        assert_eq!(shuffled, direct);
+    }
+
+    #[test]
+    fn scanned_indices_match_single_path_derivation() {
+        let stream = KeyStream::from_seed(7);
+        let mode = |path: &str, scan_indices| KeyMode::Mnemonic {
+            path: path.parse().unwrap(),
+            path_string: path.to_string(),
+            words: 12,
+            passphrase: String::new(),
+            scan_indices,
+        };
+        let scanning = mode("m/44'/0'/0'/0/0", 3);
+        let mut candidates = Vec::new();
+        derive_candidates(&stream, 5, &scanning, &mut candidates);
+        assert_eq!(candidates.len(), 3);
+        let third = derive_candidate(&stream, 5, &mode("m/44'/0'/0'/0/2", 1)).unwrap();
+        assert_eq!(candidates[2].secret, third.secret);
+        assert_eq!(
+            scanning.candidate_path(&candidates[2]).as_deref(),
+            Some("m/44'/0'/0'/0/2")
+        );
+        let phrase = candidates[2].mnemonic.as_deref().unwrap();
+        let xprv = mnemonic_xprv(phrase, &scanning, Some(2)).unwrap();
+        assert_eq!(
+            xprv.private_key().to_bytes(),
+            third.secret.secret_bytes().into()
+        );
+    }
+
+    #[test]
+    fn fixed_mnemonic_attempts_are_child_indices() {
+        let phrase = "abandon abandon abandon abandon abandon abandon abandon abandon abandon \
+                      abandon abandon about";
+        let path = "m/84'/0'/0'/0/0";
+        let fixed = KeyMode::fixed_mnemonic(phrase, path.parse().unwrap(), path.into(), "x".into())
+            .unwrap();
+        let stream = KeyStream::from_seed(1);
+        let candidate = derive_candidate(&stream, 7, &fixed).unwrap();
+        assert_eq!(
+            fixed.candidate_path(&candidate).as_deref(),
+            Some("m/84'/0'/0'/0/7")
+        );
+        // The same key the wallet derives at the full path.
+        let seed = Mnemonic::parse_in(Language::English, phrase)
+            .unwrap()
+            .to_seed("x");
+        let xprv = XPrv::derive_from_path(seed, &"m/84'/0'/0'/0/7".parse().unwrap()).unwrap();
+        assert_eq!(
+            xprv.private_key().to_bytes(),
+            candidate.secret.secret_bytes().into()
+        );
+        let reported = mnemonic_xprv(candidate.mnemonic.as_deref().unwrap(), &fixed, Some(7));
+        assert_eq!(
+            reported.unwrap().to_string(Prefix::XPRV),
+            xprv.to_string(Prefix::XPRV)
+        );
+        assert!(derive_candidate(&stream, HD_INDICES, &fixed).is_none());
+        let hardened = "m/84'/0'/0'/0/0'";
+        assert!(KeyMode::fixed_mnemonic(
+            phrase,
+            hardened.parse().unwrap(),
+            hardened.into(),
+            "".into()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn hd_derivation_matches_bip32() {
+        // BIP-32 test vector 1, m/0H/1/2H/2/1000000000.
+        let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+        let path: DerivationPath = "m/0'/1/2'/2/1000000000".parse().unwrap();
+        let node = HdNode::from_seed(&seed)
+            .unwrap()
+            .derive(path.iter())
+            .unwrap();
+        assert_eq!(
+            hex::encode(node.secret.secret_bytes()),
+            "471b76e389e528d6de6d816857e012c5455051cad6660850e58372a6c3e6e7c8"
+        );
+        let xprv = XPrv::derive_from_path(&seed, &path).unwrap();
+        assert_eq!(xprv.attrs().chain_code, node.chain_code);
+    }
+
+    #[test]
+    fn hd_paths_are_checked_against_the_format_template() {
+        let check = |path: &str, format, version, network| {
+            hd_path_mismatch(&path.parse().unwrap(), format, version, network)
+        };
+        let mainnet = Some(Network::Mainnet);
+        assert_eq!(
+            check("m/44'/0'/0'/0/7", AddressFormat::P2pkh, 0, mainnet),
+            None
+        );
+        assert_eq!(
+            check("m/86'/0'/3'/1/0", AddressFormat::Bech32, 1, mainnet),
+            None
+        );
+        assert_eq!(
+            check(
+                "m/84'/1'/0'/0/0",
+                AddressFormat::Bech32,
+                0,
+                Some(Network::Testnet)
+            ),
+            None
+        );
+        assert_eq!(
+            check("m/84'/2'/0'/0/0", AddressFormat::Bech32, 0, None),
+            None
+        );
+        assert!(check("m/44'/0'/0'/0/0", AddressFormat::Bech32, 0, mainnet)
+            .unwrap()
+            .contains("purpose"));
+        assert!(
+            check("m/49'/0'/0/0/0", AddressFormat::P2shP2wpkh, 0, mainnet)
+                .unwrap()
+                .contains("not hardened")
+        );
+        assert!(check("m/44'/0'/0'/0", AddressFormat::P2pkh, 0, mainnet)
+            .unwrap()
+            .contains("depth"));
+        assert!(check(
+            "m/44'/0'/0'/0/0",
+            AddressFormat::P2pkh,
+            0,
+            Some(Network::Testnet)
+        )
+        .unwrap()
+        .contains("coin type"));
+    }
+
+    // The schedule `derive-material` documents, rebuilt from another ChaCha20 and SHA-256:
+    // the root hashes the seed, and attempt `i` is the first half of keystream block `i`.
+    #[test]
+    fn key_material_is_the_documented_chacha20_block() {
+        use chacha20::cipher::{KeyIvInit, StreamCipher, StreamCipherSeek};
+
+        let stream = KeyStream::from_seed(1);
+        let mut preimage = b"vanity_bitcoin/seed-root".to_vec();
+        preimage.extend_from_slice(&1u64.to_le_bytes());
+        assert_eq!(stream.root[..], Sha256::digest(&preimage)[..]);
+        for attempt in [0u64, 5, 1024, 1 << 31] {
+            let mut cipher = chacha20::ChaCha20Legacy::new(&stream.root.into(), &[0u8; 8].into());
+            cipher.seek(attempt * 64);
+            let mut expected = [0u8; 32];
+            cipher.apply_keystream(&mut expected);
+            let mut material = [0u8; 32];
+            key_material_from_attempt(&stream, attempt, &mut material);
+            assert_eq!(material, expected, "attempt {attempt}");
+        }
+    }
+
+    #[test]
+    fn root_hex_is_used_verbatim() {
+        let hex = "11".repeat(32);
+        let stream = KeyStream::from_root_hex(&format!("0x{}\n", hex)).unwrap();
+        assert_eq!(stream.root, [0x11; 32]);
+        assert!(stream.seed.is_none());
+        assert_eq!(stream.root_id().len(), 16);
+        assert_ne!(stream.root_id(), KeyStream::from_seed(1).root_id());
+        assert!(KeyStream::from_root_hex(&"11".repeat(31)).is_err());
+        assert!(KeyStream::from_root_hex("zz").is_err());
+    }
+}
@@ -0,0 +1,17 @@
+//! Core of the `vanity_bitcoin` search: address encoding, the deterministic key schedule,
+//! pattern matching, checkpoints and the multi-threaded search loop.
+
+pub mod address;
+pub mod bip38;
+pub mod checkpoint;
+pub mod descriptor;
+pub mod encrypt;
+pub mod error;
+pub mod keys;
+pub mod metrics;
+pub mod output;
+pub mod pattern;
+pub mod qr;
+pub mod search;
+pub mod selftest;
+pub mod verify;
@@ -38,6 +38,10 @@ struct Args {
     #[arg(long)]
     bytecode: Option<String>,
 
+    /// keccak256 of the init code (32-byte hex), used instead of an artifact or bytecode
+    #[arg(long, conflicts_with_all = ["bytecode", "constructor_args"])]
+    init_code_hash: Option<String>,
+
     /// Optional comma-separated constructor arguments (parsed against the artifact ABI)
     #[arg(long = "constructor-args", value_delimiter = ',', num_args = 0..)]
     constructor_args: Option<Vec<String>>,
@@ -85,6 +89,10 @@ struct Args {
     /// Optional path to write the result (JSON) when a matching salt is found.
     #[arg(long)]
     output: Option<PathBuf>,
+
+    /// Print "unlimited" instead of the infinity sign for terminals without UTF-8.
+    #[arg(long)]
+    ascii: bool,
 }
 
 #[derive(Deserialize)]
@@ -189,6 +197,7 @@ fn main() -> Result<()> {
 
     let artifact_path_str = args.artifact.display().to_string();
     let bytecode_source = match (args.bytecode.is_some(), args.constructor_args.as_ref()) {
+        _ if args.init_code_hash.is_some() => "init-code-hash".to_string(),
         (true, Some(_)) => "inline-bytecode+constructor-args".to_string(),
         (true, None) => "inline-bytecode".to_string(),
         (false, Some(_)) => "artifact+constructor-args".to_string(),
@@ -200,32 +209,38 @@ fn main() -> Result<()> {
         .unwrap_or_else(|| PathBuf::from("results/salt.json"));
 
     let factory = parse_address(&args.factory)?;
-    let need_artifact = args.bytecode.is_none() || args.constructor_args.is_some();
-    let artifact = if need_artifact {
-        Some(load_artifact(&args.artifact)?)
+    let init_hash = if let Some(hash_hex) = &args.init_code_hash {
+        let bytes = parse_hex_bytes(hash_hex)?;
+        bytes
+            .try_into()
+            .map_err(|_| anyhow!("--init-code-hash must be 32 bytes (64 hex chars)"))?
     } else {
-        None
-    };
-    let mut bytecode_hex = if let Some(custom) = &args.bytecode {
-        custom.clone()
-    } else {
-        artifact
-            .as_ref()
-            .map(|a| a.bytecode.clone())
-            .expect("artifact must be loaded when --bytecode is not provided")
+        let need_artifact = args.bytecode.is_none() || args.constructor_args.is_some();
+        let artifact = if need_artifact {
+            Some(load_artifact(&args.artifact)?)
+        } else {
+            None
+        };
+        let mut bytecode_hex = if let Some(custom) = &args.bytecode {
+            custom.clone()
+        } else {
+            artifact
+                .as_ref()
+                .map(|a| a.bytecode.clone())
+                .expect("artifact must be loaded when --bytecode is not provided")
+        };
+        if let Some(constructor_args) = &args.constructor_args {
+            let artifact = artifact
+                .as_ref()
+                .ok_or_else(|| anyhow!("--constructor-args requires an artifact with ABI"))?;
+            bytecode_hex = encode_constructor(bytecode_hex, artifact, constructor_args)?;
+        }
+        let bytecode = parse_hex_bytes(&bytecode_hex)?.into_boxed_slice();
+        if bytecode.is_empty() {
+            return Err(anyhow!("Bytecode payload is empty"));
+        }
+        keccak(&bytecode)
     };
-    if let Some(constructor_args) = &args.constructor_args {
-        let artifact = artifact
-            .as_ref()
-            .ok_or_else(|| anyhow!("--constructor-args requires an artifact with ABI"))?;
-        bytecode_hex = encode_constructor(bytecode_hex, artifact, constructor_args)?;
-    }
-    let bytecode = parse_hex_bytes(&bytecode_hex)?.into_boxed_slice();
-    if bytecode.is_empty() {
-        return Err(anyhow!("Bytecode payload is empty"));
-    }
-
-    let init_hash = keccak(&bytecode);
 
     if let Some(salt_hex) = &args.salt {
         let salt = parse_salt(salt_hex)?;
@@ -238,16 +253,16 @@ fn main() -> Result<()> {
         return Ok(());
     }
 
-    let prefix = if args.checksum_match {
-        args.prefix.clone()
-    } else {
-        args.prefix.as_deref().map(|s| s.to_ascii_lowercase())
-    };
-    let suffix = if args.checksum_match {
-        args.suffix.clone()
-    } else {
-        args.suffix.as_deref().map(|s| s.to_ascii_lowercase())
-    };
+    let prefix = args
+        .prefix
+        .as_deref()
+        .map(|p| hex_pattern("--prefix", p, args.checksum_match))
+        .transpose()?;
+    let suffix = args
+        .suffix
+        .as_deref()
+        .map(|s| hex_pattern("--suffix", s, args.checksum_match))
+        .transpose()?;
     if prefix.is_none() && suffix.is_none() {
         return Err(anyhow!("Provide --prefix/--suffix or --salt"));
     }
@@ -315,7 +330,9 @@ fn main() -> Result<()> {
 
     println!("Searching for vanity salt...");
     println!("Factory   : {}", format_hex(&factory));
-    if args.bytecode.is_some() && args.constructor_args.is_none() {
+    if args.init_code_hash.is_some() {
+        println!("Bytecode  : not needed (--init-code-hash)");
+    } else if args.bytecode.is_some() && args.constructor_args.is_none() {
         println!("Bytecode  : provided via --bytecode");
     } else {
         println!("Artifact  : {}", args.artifact.display());
@@ -333,7 +350,11 @@ fn main() -> Result<()> {
         println!("Matching  : lowercase hex");
     }
     let max_display = if max_attempts == u64::MAX {
-        "∞".to_string()
+        if args.ascii {
+            "unlimited".to_string()
+        } else {
+            "\u{221E}".to_string()
+        }
     } else {
         max_attempts.to_string()
     };
@@ -392,11 +413,8 @@ fn main() -> Result<()> {
                 let attempts_done = Arc::clone(&attempts_done);
                 let found = Arc::clone(&found);
                 let result = Arc::clone(&result);
-                let factory = factory;
-                let init_hash = init_hash;
                 let prefix = prefix.clone();
                 let suffix = suffix.clone();
-                let checksum_mode = checksum_mode;
                 let checkpoint = checkpoint_writer.clone();
 
                 s.spawn(move |_| {
@@ -423,7 +441,10 @@ fn main() -> Result<()> {
                                 break;
                             }
 
-                            if worker_idx == 0 && attempt != 0 && attempt % PROGRESS_INTERVAL == 0 {
+                            if worker_idx == 0
+                                && attempt != 0
+                                && attempt.is_multiple_of(PROGRESS_INTERVAL)
+                            {
                                 println!("Checked {} salts...", attempt);
                             }
 
@@ -593,6 +614,23 @@ fn keccak(input: &[u8]) -> [u8; 32] {
     out
 }
 
+// Lowercased unless matching the EIP-55 casing, where the user's mixed case is the target.
+fn hex_pattern(flag: &str, value: &str, checksum_mode: bool) -> Result<String> {
+    if let Some(bad) = value.chars().find(|c| !c.is_ascii_hexdigit()) {
+        return Err(anyhow!(
+            "{flag} contains non-hex character '{bad}' (omit any 0x)"
+        ));
+    }
+    if value.len() > 40 {
+        return Err(anyhow!("{flag} is longer than a 40-nibble address"));
+    }
+    Ok(if checksum_mode {
+        value.to_string()
+    } else {
+        value.to_ascii_lowercase()
+    })
+}
+
 fn matches_pattern(
     address: &[u8; 20],
     prefix: Option<&str>,
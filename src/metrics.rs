@@ -0,0 +1,218 @@
+//! Live search statistics: the record behind the stats lines and the `--metrics-addr`
+//! Prometheus endpoint that serves it.
+
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+use anyhow::Result;
+use serde::Serialize;
+
+// A stalled client must not hold up the end of the search for long.
+const METRICS_TIMEOUT: Duration = Duration::from_secs(2);
+// Only the request line matters; headers are read up to this much.
+const MAX_REQUEST: usize = 8192;
+
+#[derive(Serialize)]
+pub struct ProgressStats {
+    pub attempts: u64,
+    pub session_attempts: u64,
+    pub attempts_per_sec: f64,
+    pub elapsed_ms: u128,
+    pub matches_found: u64,
+    pub matches_target: u64,
+    // Attempts this session whose key material was rejected; included in `attempts`.
+    pub discarded: u64,
+    // Omitted for --regex, which has no estimate.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub match_probability: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expected_attempts: Option<f64>,
+    // Chance that at least one match should have turned up within the non-discarded
+    // `attempts`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub found_probability: Option<f64>,
+}
+
+/// Answer one HTTP request on `stream`: `GET /metrics` gets `metrics_text` of `stats()`,
+/// other paths a 404 and other methods a 405. The connection is closed afterwards.
+pub fn serve_metrics(mut stream: TcpStream, stats: impl FnOnce() -> ProgressStats) -> Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(METRICS_TIMEOUT))?;
+    stream.set_write_timeout(Some(METRICS_TIMEOUT))?;
+    let mut request = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < MAX_REQUEST {
+        let n = stream.read(&mut chunk)?;
+        if n == 0 {
+            break;
+        }
+        request.extend_from_slice(&chunk[..n]);
+    }
+    let request = String::from_utf8_lossy(&request);
+    let mut parts = request.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", metrics_text(&stats())),
+        (Some("GET"), _) => ("404 Not Found", "Not found; try /metrics\n".to_string()),
+        _ => (
+            "405 Method Not Allowed",
+            "Only GET is supported\n".to_string(),
+        ),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()?;
+    Ok(())
+}
+
+/// Prometheus text exposition format, one HELP/TYPE block per metric.
+pub fn metrics_text(stats: &ProgressStats) -> String {
+    let mut metrics = vec![
+        (
+            "attempts_total",
+            "counter",
+            "Attempts made, including resumed sessions.",
+            stats.attempts as f64,
+        ),
+        (
+            "session_attempts_total",
+            "counter",
+            "Attempts made by this process.",
+            stats.session_attempts as f64,
+        ),
+        (
+            "attempts_per_second",
+            "gauge",
+            "Average attempt rate of this process.",
+            stats.attempts_per_sec,
+        ),
+        (
+            "matches_found_total",
+            "counter",
+            "Matches found by this process.",
+            stats.matches_found as f64,
+        ),
+        (
+            "matches_target",
+            "gauge",
+            "Matches this process is looking for.",
+            stats.matches_target as f64,
+        ),
+        (
+            "discarded_total",
+            "counter",
+            "Attempts whose key material was rejected.",
+            stats.discarded as f64,
+        ),
+        (
+            "uptime_seconds",
+            "gauge",
+            "Time since the search started.",
+            stats.elapsed_ms as f64 / 1000.0,
+        ),
+    ];
+    if let Some(found) = stats.found_probability {
+        metrics.push((
+            "found_probability",
+            "gauge",
+            "Chance a match should have turned up by now.",
+            found,
+        ));
+    }
+    let mut text = String::new();
+    for (name, kind, help, value) in metrics {
+        text.push_str(&format!(
+            "# HELP vanity_bitcoin_{name} {help}\n# TYPE vanity_bitcoin_{name} {kind}\nvanity_bitcoin_{name} {value}\n"
+        ));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    fn sample(found_probability: Option<f64>) -> ProgressStats {
+        ProgressStats {
+            attempts: 1500,
+            session_attempts: 500,
+            attempts_per_sec: 250.5,
+            elapsed_ms: 2000,
+            matches_found: 1,
+            matches_target: 3,
+            discarded: 2,
+            match_probability: None,
+            expected_attempts: None,
+            found_probability,
+        }
+    }
+
+    #[test]
+    fn metrics_use_the_text_exposition_format() {
+        let text = metrics_text(&sample(None));
+        assert!(text.starts_with(
+            "# HELP vanity_bitcoin_attempts_total Attempts made, including resumed sessions.\n\
+             # TYPE vanity_bitcoin_attempts_total counter\n\
+             vanity_bitcoin_attempts_total 1500\n"
+        ));
+        for sample in [
+            "vanity_bitcoin_session_attempts_total 500\n",
+            "vanity_bitcoin_attempts_per_second 250.5\n",
+            "vanity_bitcoin_matches_found_total 1\n",
+            "vanity_bitcoin_matches_target 3\n",
+            "vanity_bitcoin_discarded_total 2\n",
+            "vanity_bitcoin_uptime_seconds 2\n",
+        ] {
+            assert!(text.contains(sample), "{sample:?} missing from\n{text}");
+        }
+        // Every sample line has its HELP and TYPE lines.
+        assert_eq!(text.lines().count(), 7 * 3);
+        assert!(!text.contains("found_probability"));
+        let text = metrics_text(&sample(Some(0.25)));
+        assert!(text.ends_with(
+            "# TYPE vanity_bitcoin_found_probability gauge\nvanity_bitcoin_found_probability 0.25\n"
+        ));
+    }
+
+    // The request is written before the server accepts it, so one thread plays both sides.
+    fn exchange(request: &str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client.write_all(request.as_bytes()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        serve_metrics(stream, || sample(None)).unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn only_get_metrics_is_served() {
+        let response = exchange("GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"), "{head}");
+        assert!(head.contains("Content-Type: text/plain; version=0.0.4\r\n"));
+        assert!(head.contains(&format!("Content-Length: {}\r\n", body.len())));
+        assert_eq!(body, metrics_text(&sample(None)));
+
+        let response = exchange("GET / HTTP/1.1\r\n\r\n");
+        assert!(
+            response.starts_with("HTTP/1.1 404 Not Found\r\n"),
+            "{response}"
+        );
+        assert!(response.ends_with("\r\n\r\nNot found; try /metrics\n"));
+        let response = exchange("POST /metrics HTTP/1.1\r\nContent-Length: 0\r\n\r\n");
+        assert!(
+            response.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"),
+            "{response}"
+        );
+        assert!(response.ends_with("\r\n\r\nOnly GET is supported\n"));
+    }
+}
@@ -0,0 +1,1172 @@
+//! Result records and the result files they are appended to.
+
+use std::{
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use flate2::{read::MultiGzDecoder, write::GzEncoder, Compression};
+use serde::{
+    de::{Deserializer as _, Error as _, SeqAccess, Visitor},
+    Deserialize, Serialize,
+};
+use serde_json::Value;
+
+use crate::{
+    address::NetworkParams,
+    encrypt::{open, seal, EncryptedContainer},
+};
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+#[value(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    /// A single pretty-printed JSON array, rewritten through a temp file on every match.
+    JsonArray,
+    /// One compact JSON object per line, appended in place.
+    Ndjson,
+    /// A header row followed by one appended row per match.
+    Csv,
+}
+
+impl OutputFormat {
+    pub fn default_path(self) -> &'static str {
+        match self {
+            OutputFormat::JsonArray => "results/vanity-bitcoin.json",
+            OutputFormat::Ndjson => "results/vanity-bitcoin.ndjson",
+            OutputFormat::Csv => "results/vanity-bitcoin.csv",
+        }
+    }
+}
+
+/// Bumped whenever a `VanityResult` field changes meaning or is removed; new optional
+/// fields keep the version. Entries from before versioning count as version 1.
+pub const RESULT_SCHEMA_VERSION: u64 = 1;
+
+/// JSON Schema of one result entry at `RESULT_SCHEMA_VERSION`.
+pub const RESULT_SCHEMA: &str = include_str!("../schema/vanity-bitcoin-result.schema.json");
+
+#[derive(Serialize)]
+pub struct VanityResult {
+    pub schema_version: u64,
+    // Absent in split-key mode, where only the offset is known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub private_key_hex: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wif: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bip38: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub split_key_offset: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub split_key_base: Option<String>,
+    pub address: String,
+    // The key the address commits to (uncompressed with --uncompressed).
+    pub public_key_hex: String,
+    pub format: String,
+    pub witness_version: Option<u8>,
+    // P2WSH: the `<pubkey> OP_CHECKSIG` witness script the address hashes, needed to spend.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub witness_script: Option<String>,
+    pub compressed: bool,
+    // --emit-both-compressions: the same key's P2PKH address under the other encoding.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub other_compression: Option<OtherCompression>,
+    pub network: String,
+    // Version bytes and HRP when `network` is `Custom`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network_params: Option<NetworkParams>,
+    // One-based position of the match in the attempt order; any attempt in flight in
+    // other threads is not included.
+    pub attempts: u64,
+    // The attempt that produced the key: `--derive-attempt <attempt_index>` with the same
+    // seed re-derives it. Always `attempts - 1`; absent from older entries.
+    pub attempt_index: u64,
+    pub attempts_limit: Option<u64>,
+    pub seed: Option<u64>,
+    // Without a u64 `seed`, names the 256-bit root (OS random or --seed-hex/--seed-file)
+    // via `KeyStream::root_id`, so the root that re-derives this entry can be recognised.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed_root_id: Option<String>,
+    // Hex `config_fingerprint` of the search; --resume counts entries carrying it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config_hash: Option<String>,
+    pub prefix: Option<String>,
+    // --prefix-offset: `prefix` starts this many characters past the fixed HRP part.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prefix_offset: Option<usize>,
+    // --target: `prefix` is its first characters and `near_miss_score` counts shared ones.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    pub suffix: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub any_prefix: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub any_suffix: Vec<String>,
+    // Which --any-prefix/--any-suffix entries the address satisfied (both may).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub matched_prefix: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub matched_suffix: Option<String>,
+    pub contains: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    pub regex: Option<String>,
+    // --hash-prefix, and the payload it matched: the hash160 or witness program `address`
+    // encodes (see `address_payload`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hash_prefix: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address_hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub leading_char: Option<char>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub leading_run: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub matched_word: Option<String>,
+    pub mnemonic: Option<String>,
+    pub hd_path: Option<String>,
+    // The scanned child index that matched (--scan-indices); `hd_path` already ends in it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hd_index: Option<u32>,
+    // Extended keys at `hd_path`, mnemonic mode only.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub xprv: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub xpub: Option<String>,
+    // BIP-380 descriptor with checksum for `importdescriptors`: the WIF in the format's
+    // script, or in mnemonic mode the master xprv ranged over `hd_path`'s branch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub descriptor: Option<String>,
+    pub passphrase_used: bool,
+    // Leading --prefix characters matched; only set in the --track-best file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub near_miss_score: Option<usize>,
+    // Patterns above are lowercased when set.
+    pub ignore_case: bool,
+    // The patterns were matched against `wif`, not `address`.
+    pub match_wif: bool,
+    // --permute: `attempt_index` went through `permute_attempt` before key derivation.
+    pub permuted: bool,
+}
+
+/// `--record-misses`: what a search that ended without all its matches leaves in the
+/// result file, so the file logs every session. Readers tell it apart by `found: false`.
+#[derive(Serialize)]
+pub struct SearchMiss {
+    pub schema_version: u64,
+    pub found: bool,
+    // "exhausted", "interrupted", "time_limit" or "save_failed", as in the --summary-json
+    // summary.
+    pub stop_reason: String,
+    // Matches this search already saved (fewer than `matches_target`).
+    pub matches: u64,
+    pub matches_target: u64,
+    // Totals across resumed sessions, like a match's.
+    pub attempts: u64,
+    pub attempts_limit: Option<u64>,
+    pub elapsed_ms: u64,
+    // Every attempt below this was tested; --resume picks up here.
+    pub next_attempt: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub checkpoint: Option<String>,
+    pub format: String,
+    pub witness_version: Option<u8>,
+    pub compressed: bool,
+    pub network: String,
+    pub seed: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed_root_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config_hash: Option<String>,
+    pub prefix: Option<String>,
+    pub suffix: Option<String>,
+    pub contains: Vec<String>,
+    pub regex: Option<String>,
+    pub hd_path: Option<String>,
+}
+
+/// A key's P2PKH address, public key and WIF under the compression `compressed` names,
+/// the opposite of the entry's own. Old coins may sit on either.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct OtherCompression {
+    pub compressed: bool,
+    pub address: String,
+    pub public_key_hex: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wif: Option<String>,
+}
+
+/// `password` encrypts the whole JSON array (json-array format only); existing encrypted
+/// files are decrypted, extended and re-sealed with a fresh salt and nonce.
+pub fn append_result_file(
+    path: &Path,
+    report: &VanityResult,
+    format: OutputFormat,
+    password: Option<&str>,
+) -> Result<()> {
+    if format == OutputFormat::Csv && password.is_none() {
+        create_result_dir(path)?;
+        return append_csv(path, report);
+    }
+    append_entry(
+        path,
+        serde_json::to_value(report)?,
+        report.schema_version,
+        format,
+        password,
+    )
+}
+
+/// Append a `--record-misses` record; json-array and ndjson only, as csv has no columns
+/// for it.
+pub fn append_search_miss(
+    path: &Path,
+    miss: &SearchMiss,
+    format: OutputFormat,
+    password: Option<&str>,
+) -> Result<()> {
+    if format == OutputFormat::Csv {
+        return Err(anyhow!(
+            "--record-misses needs --output-format json-array or ndjson"
+        ));
+    }
+    append_entry(
+        path,
+        serde_json::to_value(miss)?,
+        miss.schema_version,
+        format,
+        password,
+    )
+}
+
+fn create_result_dir(path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create result dir {}", parent.display()))?;
+    }
+    Ok(())
+}
+
+fn append_entry(
+    path: &Path,
+    entry: Value,
+    schema_version: u64,
+    format: OutputFormat,
+    password: Option<&str>,
+) -> Result<()> {
+    create_result_dir(path)?;
+    if let Some(password) = password {
+        if format != OutputFormat::JsonArray {
+            return Err(anyhow!(
+                "Encrypted output requires --output-format json-array"
+            ));
+        }
+        return append_encrypted(path, entry, schema_version, password);
+    }
+    match format {
+        OutputFormat::JsonArray => append_json_array(path, entry, schema_version),
+        OutputFormat::Ndjson => append_ndjson(path, &entry, schema_version),
+        OutputFormat::Csv => unreachable!("csv rows are written by append_csv"),
+    }
+}
+
+/// Write `reports` to `out` instead of a file (`--output-stdout`). A json-array run with a
+/// single match prints the bare object; ndjson and csv look like their files would.
+pub fn write_results(
+    out: &mut impl Write,
+    reports: &[VanityResult],
+    format: OutputFormat,
+) -> Result<()> {
+    match format {
+        OutputFormat::JsonArray => {
+            match reports {
+                [report] => serde_json::to_writer_pretty(&mut *out, report)?,
+                _ => serde_json::to_writer_pretty(&mut *out, reports)?,
+            }
+            out.write_all(b"\n")?;
+        }
+        OutputFormat::Ndjson => {
+            for report in reports {
+                serde_json::to_writer(&mut *out, report)?;
+                out.write_all(b"\n")?;
+            }
+        }
+        OutputFormat::Csv => {
+            out.write_all(CSV_HEADER.as_bytes())?;
+            for report in reports {
+                out.write_all(csv_row(report).as_bytes())?;
+            }
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn append_json_array(path: &Path, entry: Value, schema_version: u64) -> Result<()> {
+    let (opening, first) = if path.exists() {
+        peek_entries(path)
+            .with_context(|| format!("Failed to parse existing result file {}", path.display()))?
+    } else {
+        (None, None)
+    };
+    if let Some(first) = &first {
+        if is_container(first) {
+            return Err(anyhow!(
+                "{} is encrypted; pass --encrypt-output to append to it",
+                path.display()
+            ));
+        }
+        ensure_schema(path, std::slice::from_ref(first), schema_version)?;
+    }
+    let item = serde_json::to_string_pretty(&entry)?;
+    rewrite_array(path, opening.is_some(), &item)
+        .with_context(|| format!("Failed to write result file {}", path.display()))
+}
+
+// The existing entries are streamed into a replacement file that ends with `item`, which
+// is then renamed over the original: memory stays flat, and a crash leaves either the old
+// or the new file, never a cut-off array. A file already cut off mid-entry (by a full disk,
+// or a build that wrote in place) loses only that fragment; the match it held lies past
+// the checkpoint, so a resumed search finds it again.
+fn rewrite_array(path: &Path, existing: bool, item: &str) -> Result<()> {
+    let reader = if existing {
+        Some(result_reader(path)?)
+    } else {
+        None
+    };
+    write_atomic_with(path, |file| {
+        if is_gzip_path(path) {
+            let mut out = GzEncoder::new(file, Compression::default());
+            copy_array(reader, &mut out, item)?;
+            out.finish()?;
+        } else {
+            let mut out = io::BufWriter::new(file);
+            copy_array(reader, &mut out, item)?;
+            out.flush()?;
+        }
+        Ok(())
+    })
+}
+
+// Lays entries out as `to_vec_pretty` does; a lone object becomes the first of them.
+fn copy_array(reader: Option<impl BufRead>, out: &mut impl Write, item: &str) -> Result<()> {
+    let mut separator = "[\n  ";
+    let mut write_item = |out: &mut dyn Write, item: &str| -> Result<()> {
+        // JSON strings hold no raw newlines, so this only indents the layout.
+        write!(out, "{separator}{}", item.replace('\n', "\n  "))?;
+        separator = ",\n  ";
+        Ok(())
+    };
+    if let Some(reader) = reader {
+        let copied = stream_entries(reader, |entry| {
+            write_item(out, &serde_json::to_string_pretty(&entry)?)
+        });
+        copied.map(drop).or_else(allow_truncated)?;
+    }
+    write_item(out, item)?;
+    out.write_all(b"\n]")?;
+    Ok(())
+}
+
+// A file that ends mid-entry is kept up to its last whole one; other damage is an error.
+fn allow_truncated(err: anyhow::Error) -> Result<()> {
+    match err.downcast_ref::<serde_json::Error>() {
+        Some(json) if json.is_eof() => Ok(()),
+        _ => Err(err),
+    }
+}
+
+fn append_encrypted(path: &Path, entry: Value, schema_version: u64, password: &str) -> Result<()> {
+    let mut entries: Vec<Value> = Vec::new();
+    if path.exists() {
+        let raw = read_text(path)
+            .with_context(|| format!("Failed to read existing result file {}", path.display()))?;
+        if !raw.trim().is_empty() {
+            let plaintext = decrypt_result_file(path, password)?;
+            entries = parse_entries(&String::from_utf8(plaintext)?)?;
+        }
+    }
+    ensure_schema(path, &entries, schema_version)?;
+    entries.push(entry);
+    let container = seal(&serde_json::to_vec_pretty(&entries)?, password)?;
+    let data = serde_json::to_vec_pretty(&container)?;
+    write_atomic(path, &compress_for_path(path, &data)?)
+        .with_context(|| format!("Failed to write result file {}", path.display()))?;
+    Ok(())
+}
+
+/// Plaintext JSON of a file written with a password.
+pub fn decrypt_result_file(path: &Path, password: &str) -> Result<Vec<u8>> {
+    let raw = read_text(path)
+        .with_context(|| format!("Failed to read result file {}", path.display()))?;
+    let container: EncryptedContainer = serde_json::from_str(&raw)
+        .with_context(|| format!("{} is not an encrypted result file", path.display()))?;
+    open(&container, password)
+}
+
+/// Entries of a json-array or ndjson result file (already decrypted, if it was encrypted).
+pub fn load_result_entries(raw: &str) -> Result<Vec<Value>> {
+    let mut entries = Vec::new();
+    stream_entries(raw.as_bytes(), |entry| {
+        entries.push(entry);
+        Ok(())
+    })?;
+    Ok(entries)
+}
+
+/// Hand each entry of the json-array or ndjson result file at `path` to `visit` as it is
+/// parsed, so memory stays flat however large the file is. Gzip is detected from the
+/// magic bytes; an encrypted file is opened with `password()`, and only its decrypted
+/// plaintext is held in memory. Returns the number of entries.
+pub fn for_each_result_entry(
+    path: &Path,
+    password: impl FnOnce() -> Result<String>,
+    mut visit: impl FnMut(Value) -> Result<()>,
+) -> Result<u64> {
+    let mut sealed = None;
+    let mut first = true;
+    let count = stream_entries(result_reader(path)?, |entry| {
+        // A container is a lone object, which the ndjson branch hands over as-is.
+        if std::mem::take(&mut first) && entry.get("ciphertext").is_some() {
+            if let Ok(container) = serde_json::from_value::<EncryptedContainer>(entry.clone()) {
+                sealed = Some(container);
+                return Ok(());
+            }
+        }
+        visit(entry)
+    })
+    .with_context(|| format!("Failed to read result file {}", path.display()))?;
+    match sealed {
+        Some(container) if count == 1 => {
+            let plaintext = open(&container, &password()?)?;
+            stream_entries(plaintext.as_slice(), visit)
+        }
+        _ => Ok(count),
+    }
+}
+
+/// `(attempt_index, address)` of each match in the result file at `path` written by the
+/// search with this `config_hash` (`--record-misses` records don't count).
+pub fn config_matches(
+    path: &Path,
+    password: impl FnOnce() -> Result<String>,
+    config_hash: &str,
+) -> Result<Vec<(u64, String)>> {
+    let mut matches = Vec::new();
+    let read = for_each_result_entry(path, password, |entry| {
+        if entry.get("config_hash").and_then(Value::as_str) == Some(config_hash)
+            && !is_search_miss(&entry)
+        {
+            let index = entry.get("attempt_index").and_then(Value::as_u64);
+            let address = entry.get("address").and_then(Value::as_str);
+            matches.push((index.unwrap_or(0), address.unwrap_or("").to_string()));
+        }
+        Ok(())
+    });
+    // A file cut off mid-entry counts its whole entries; the next append drops the rest.
+    read.map(drop).or_else(allow_truncated)?;
+    Ok(matches)
+}
+
+/// Addresses of the rows in the csv result file at `path`.
+pub fn csv_addresses(path: &Path) -> Result<Vec<String>> {
+    let mut addresses = Vec::new();
+    for line in result_reader(path)?.lines().skip(1) {
+        // Addresses never need quoting, so the first field ends at the first comma.
+        if let Some((address, _)) = line?.split_once(',') {
+            addresses.push(address.to_string());
+        }
+    }
+    Ok(addresses)
+}
+
+/// Whether the result file at `path` is an encrypted container. Only its first value is
+/// parsed, so a large plaintext file is never read in full.
+pub fn is_encrypted_result_file(path: &Path) -> bool {
+    peek_entries(path).is_ok_and(|(_, first)| first.as_ref().is_some_and(is_container))
+}
+
+fn is_container(value: &Value) -> bool {
+    value.get("ciphertext").is_some() && EncryptedContainer::deserialize(value).is_ok()
+}
+
+// The first non-whitespace byte of a result file and its first entry (a json-array's first
+// element, or the first ndjson value), read without the rest of the file.
+fn peek_entries(path: &Path) -> Result<(Option<u8>, Option<Value>)> {
+    let mut reader = result_reader(path)?;
+    let opening = skip_whitespace(&mut reader)?;
+    if opening == Some(b'[') {
+        reader.consume(1);
+        if matches!(skip_whitespace(&mut reader)?, None | Some(b']')) {
+            return Ok((opening, None));
+        }
+    }
+    let first = serde_json::Deserializer::from_reader(reader)
+        .into_iter::<Value>()
+        .next()
+        .transpose();
+    // A first entry cut off mid-write is as good as none.
+    let first = first.or_else(|err| if err.is_eof() { Ok(None) } else { Err(err) })?;
+    Ok((opening, first))
+}
+
+// Consumes leading whitespace and returns the byte after it, which stays unread.
+fn skip_whitespace(reader: &mut impl BufRead) -> io::Result<Option<u8>> {
+    loop {
+        let buf = reader.fill_buf()?;
+        match buf.iter().position(|b| !b.is_ascii_whitespace()) {
+            Some(at) => {
+                let first = buf[at];
+                reader.consume(at);
+                return Ok(Some(first));
+            }
+            None if buf.is_empty() => return Ok(None),
+            None => {
+                let len = buf.len();
+                reader.consume(len);
+            }
+        }
+    }
+}
+
+/// Whether a result file entry is a `SearchMiss` rather than a match.
+pub fn is_search_miss(entry: &Value) -> bool {
+    entry.get("found") == Some(&Value::Bool(false))
+}
+
+// A json-array is walked element by element through `SeqAccess`; anything else is read as
+// whitespace-separated values (ndjson, or a single object).
+fn stream_entries<R: BufRead>(
+    mut reader: R,
+    mut visit: impl FnMut(Value) -> Result<()>,
+) -> Result<u64> {
+    match skip_whitespace(&mut reader)? {
+        None => Ok(0),
+        Some(b'[') => {
+            let mut failure = None;
+            let mut de = serde_json::Deserializer::from_reader(reader);
+            let seq = EntrySeq {
+                visit: &mut visit,
+                failure: &mut failure,
+            };
+            let count = de.deserialize_seq(seq);
+            if let Some(err) = failure {
+                return Err(err);
+            }
+            let count = count?;
+            de.end()?;
+            Ok(count)
+        }
+        Some(_) => {
+            let mut count = 0;
+            for entry in serde_json::Deserializer::from_reader(reader).into_iter::<Value>() {
+                visit(entry.with_context(|| format!("Invalid JSON after entry {}", count))?)?;
+                count += 1;
+            }
+            Ok(count)
+        }
+    }
+}
+
+struct EntrySeq<'a, F> {
+    visit: &'a mut F,
+    // The visitor's own error, which serde can only carry as a message.
+    failure: &'a mut Option<anyhow::Error>,
+}
+
+impl<'de, F: FnMut(Value) -> Result<()>> Visitor<'de> for EntrySeq<'_, F> {
+    type Value = u64;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an array of result entries")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<u64, A::Error> {
+        let mut count = 0;
+        while let Some(entry) = seq.next_element::<Value>()? {
+            if let Err(err) = (self.visit)(entry) {
+                *self.failure = Some(err);
+                return Err(A::Error::custom("stopped"));
+            }
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+/// Refuse to add an entry to a file whose entries use another schema version. Appends keep
+/// a file uniform, so plaintext appends pass only its first entry.
+fn ensure_schema(path: &Path, entries: &[Value], schema_version: u64) -> Result<()> {
+    let found = entries
+        .iter()
+        .map(|entry| {
+            entry
+                .get("schema_version")
+                .and_then(Value::as_u64)
+                .unwrap_or(1)
+        })
+        .find(|&version| version != schema_version);
+    match found {
+        Some(version) => Err(anyhow!(
+            "{} holds schema_version {} results but this build writes version {}; use a new --output file",
+            path.display(),
+            version,
+            schema_version
+        )),
+        None => Ok(()),
+    }
+}
+
+fn parse_entries(raw: &str) -> Result<Vec<Value>> {
+    if raw.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    Ok(match serde_json::from_str(raw)? {
+        Value::Array(arr) => arr,
+        other => vec![other],
+    })
+}
+
+// A single `write` of one whole line: earlier lines are never rewritten, so a crash can at
+// worst truncate the entry being written.
+fn append_ndjson(path: &Path, entry: &Value, schema_version: u64) -> Result<()> {
+    if let Some(first) = first_line(path)? {
+        let first: Value = serde_json::from_str(&first)
+            .with_context(|| format!("Failed to parse existing result file {}", path.display()))?;
+        ensure_schema(path, &[first], schema_version)?;
+    }
+    let mut line = serde_json::to_vec(entry)?;
+    line.push(b'\n');
+    append_line(path, &line)
+}
+
+const CSV_HEADER: &str =
+    "address,private_key_hex,wif,format,witness_version,attempts,seed,mnemonic,hd_path\n";
+
+fn append_csv(path: &Path, report: &VanityResult) -> Result<()> {
+    let mut line = String::new();
+    let is_new = fs::metadata(path).map(|m| m.len() == 0).unwrap_or(true);
+    if is_new {
+        line.push_str(CSV_HEADER);
+    }
+    line.push_str(&csv_row(report));
+    append_line(path, line.as_bytes())
+}
+
+fn csv_row(report: &VanityResult) -> String {
+    let fields = [
+        report.address.clone(),
+        report
+            .private_key_hex
+            .clone()
+            .or_else(|| report.split_key_offset.clone())
+            .unwrap_or_default(),
+        report.wif.clone().unwrap_or_default(),
+        report.format.clone(),
+        report
+            .witness_version
+            .map(|v| v.to_string())
+            .unwrap_or_default(),
+        report.attempts.to_string(),
+        report.seed.map(|s| s.to_string()).unwrap_or_default(),
+        report.mnemonic.clone().unwrap_or_default(),
+        report.hd_path.clone().unwrap_or_default(),
+    ];
+    let row: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+    format!("{}\n", row.join(","))
+}
+
+// RFC 4180: quote fields containing separators, quotes or whitespace; double inner quotes.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', ' ', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+// On a `.gz` path each line is its own gzip member; concatenated members are still one
+// valid gzip stream, so appends never rewrite what is already there.
+fn append_line(path: &Path, line: &[u8]) -> Result<()> {
+    let line = compress_for_path(path, line)?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open result file {}", path.display()))?;
+    file.write_all(&line)
+        .with_context(|| format!("Failed to append to result file {}", path.display()))?;
+    file.sync_data()?;
+    Ok(())
+}
+
+// First non-empty line of an existing (possibly gzipped) file, without reading the rest.
+fn first_line(path: &Path) -> Result<Option<String>> {
+    let reader = match result_reader(path) {
+        Ok(reader) => reader,
+        Err(err)
+            if err
+                .downcast_ref::<io::Error>()
+                .is_some_and(|err| err.kind() == io::ErrorKind::NotFound) =>
+        {
+            return Ok(None)
+        }
+        Err(err) => return Err(err),
+    };
+    for line in reader.lines() {
+        let line = line.with_context(|| format!("Failed to read {}", path.display()))?;
+        if !line.trim().is_empty() {
+            return Ok(Some(line));
+        }
+    }
+    Ok(None)
+}
+
+/// A buffered reader over a result or checkpoint file, decompressing it when it starts with
+/// the gzip magic bytes (whatever its extension).
+pub fn result_reader(path: &Path) -> Result<Box<dyn BufRead>> {
+    let mut file = File::open(path)?;
+    let mut magic = [0u8; 2];
+    let gzip = file.read(&mut magic)? == 2 && magic == [0x1f, 0x8b];
+    file.seek(SeekFrom::Start(0))?;
+    Ok(if gzip {
+        Box::new(BufReader::new(MultiGzDecoder::new(file)))
+    } else {
+        Box::new(BufReader::new(file))
+    })
+}
+
+/// Whether files at `path` are written gzip-compressed (a `.gz` extension).
+pub fn is_gzip_path(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("gz"))
+}
+
+/// `path` with `.gz` appended, unless it already ends in it.
+pub fn gzip_path(path: &Path) -> std::path::PathBuf {
+    if is_gzip_path(path) {
+        return path.to_path_buf();
+    }
+    let mut name = path.as_os_str().to_owned();
+    name.push(".gz");
+    name.into()
+}
+
+/// Read a result or checkpoint file as text, decompressing it when it starts with the gzip
+/// magic bytes (whatever its extension).
+pub fn read_text(path: &Path) -> Result<String> {
+    let bytes = fs::read(path)?;
+    if !bytes.starts_with(&[0x1f, 0x8b]) {
+        return Ok(String::from_utf8(bytes)?);
+    }
+    let mut text = String::new();
+    MultiGzDecoder::new(bytes.as_slice())
+        .read_to_string(&mut text)
+        .with_context(|| format!("Failed to decompress {}", path.display()))?;
+    Ok(text)
+}
+
+pub(crate) fn compress_for_path(path: &Path, data: &[u8]) -> Result<Vec<u8>> {
+    if !is_gzip_path(path) {
+        return Ok(data.to_vec());
+    }
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+// Write to a sibling temp file, fsync, then rename over the target so readers only ever
+// see the old or the new contents (rename is atomic within a directory on POSIX).
+pub fn write_atomic(path: &Path, data: &[u8]) -> Result<()> {
+    write_atomic_with(path, |file| Ok(file.write_all(data)?))
+}
+
+fn write_atomic_with(path: &Path, write: impl FnOnce(&mut File) -> Result<()>) -> Result<()> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("{} has no file name", path.display()))?;
+    let mut tmp_name = std::ffi::OsString::from(".");
+    tmp_name.push(file_name);
+    tmp_name.push(format!(".tmp-{}", std::process::id()));
+    let tmp_path = path.with_file_name(tmp_name);
+    let written = File::create(&tmp_path)
+        .map_err(anyhow::Error::from)
+        .and_then(|mut file| {
+            write(&mut file)?;
+            Ok(file.sync_all()?)
+        });
+    if let Err(err) = written.and_then(|()| Ok(fs::rename(&tmp_path, path)?)) {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// Create `path`'s directory and write and delete a scratch file in it, and check that an
+/// existing `path` opens for appending, so an unwritable location fails up front rather
+/// than when the first result arrives. Leaves `path` itself untouched.
+pub fn probe_writable(path: &Path) -> Result<()> {
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+    let probe = dir.join(format!(".vanity-probe-{}", std::process::id()));
+    fs::write(&probe, b"").with_context(|| format!("{} is not writable", dir.display()))?;
+    fs::remove_file(&probe)
+        .with_context(|| format!("Failed to remove probe file {}", probe.display()))?;
+    if path.exists() {
+        OpenOptions::new()
+            .append(true)
+            .open(path)
+            .with_context(|| format!("{} is not writable", path.display()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> VanityResult {
+        let some = || Some("x".to_string());
+        VanityResult {
+            schema_version: RESULT_SCHEMA_VERSION,
+            private_key_hex: some(),
+            wif: some(),
+            bip38: some(),
+            split_key_offset: some(),
+            split_key_base: some(),
+            address: "1x".into(),
+            public_key_hex: "02".into(),
+            format: "P2pkh".into(),
+            witness_version: Some(0),
+            witness_script: some(),
+            compressed: true,
+            other_compression: Some(OtherCompression {
+                compressed: false,
+                address: "1x".into(),
+                public_key_hex: "04".into(),
+                wif: some(),
+            }),
+            network: "Custom".into(),
+            network_params: Some(crate::address::Network::Mainnet.params()),
+            attempts: 1,
+            attempt_index: 0,
+            attempts_limit: Some(1),
+            seed: Some(1),
+            seed_root_id: some(),
+            config_hash: some(),
+            prefix: some(),
+            prefix_offset: Some(1),
+            target: some(),
+            suffix: some(),
+            any_prefix: vec!["x".into()],
+            any_suffix: vec!["x".into()],
+            matched_prefix: some(),
+            matched_suffix: some(),
+            contains: vec!["x".into()],
+            exclude: vec!["x".into()],
+            regex: some(),
+            hash_prefix: some(),
+            address_hash: some(),
+            leading_char: Some('1'),
+            leading_run: Some(1),
+            matched_word: some(),
+            mnemonic: some(),
+            hd_path: some(),
+            hd_index: Some(0),
+            xprv: some(),
+            xpub: some(),
+            descriptor: some(),
+            passphrase_used: false,
+            near_miss_score: Some(1),
+            ignore_case: false,
+            match_wif: false,
+            permuted: false,
+        }
+    }
+
+    fn sample_miss() -> SearchMiss {
+        SearchMiss {
+            schema_version: RESULT_SCHEMA_VERSION,
+            found: false,
+            stop_reason: "exhausted".into(),
+            matches: 0,
+            matches_target: 1,
+            attempts: 1,
+            attempts_limit: Some(1),
+            elapsed_ms: 1,
+            next_attempt: 1,
+            checkpoint: Some("x".into()),
+            format: "P2pkh".into(),
+            witness_version: None,
+            compressed: true,
+            network: "Mainnet".into(),
+            seed: Some(1),
+            seed_root_id: Some("x".into()),
+            config_hash: Some("x".into()),
+            prefix: Some("x".into()),
+            suffix: None,
+            contains: Vec::new(),
+            regex: None,
+            hd_path: None,
+        }
+    }
+
+    // Fails when a field is added without describing it in the published schema.
+    #[test]
+    fn schema_covers_every_result_field() {
+        let schema: Value = serde_json::from_str(RESULT_SCHEMA).unwrap();
+        assert_eq!(
+            schema["properties"]["schema_version"]["const"],
+            RESULT_SCHEMA_VERSION
+        );
+        let Value::Object(entry) = serde_json::to_value(sample()).unwrap() else {
+            panic!("results serialize as objects");
+        };
+        for key in entry.keys() {
+            assert!(
+                schema["properties"].get(key).is_some(),
+                "schema lacks '{}'",
+                key
+            );
+        }
+        for key in schema["required"].as_array().unwrap() {
+            assert!(entry.contains_key(key.as_str().unwrap()));
+        }
+        let miss_schema = &schema["$defs"]["miss"];
+        let Value::Object(miss) = serde_json::to_value(sample_miss()).unwrap() else {
+            panic!("misses serialize as objects");
+        };
+        for key in miss.keys() {
+            assert!(
+                miss_schema["properties"].get(key).is_some(),
+                "miss schema lacks '{}'",
+                key
+            );
+        }
+        for key in miss_schema["required"].as_array().unwrap() {
+            assert!(miss.contains_key(key.as_str().unwrap()));
+        }
+    }
+
+    #[test]
+    fn stdout_results_mirror_the_file_formats() {
+        let write = |reports: &[VanityResult], format| {
+            let mut out = Vec::new();
+            write_results(&mut out, reports, format).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let one = write(&[sample()], OutputFormat::JsonArray);
+        assert_eq!(
+            serde_json::from_str::<Value>(&one).unwrap()["address"],
+            "1x"
+        );
+        let two = write(&[sample(), sample()], OutputFormat::JsonArray);
+        assert_eq!(load_result_entries(&two).unwrap().len(), 2);
+        let lines = write(&[sample(), sample()], OutputFormat::Ndjson);
+        assert_eq!(load_result_entries(&lines).unwrap().len(), 2);
+        let csv = write(&[sample()], OutputFormat::Csv);
+        assert!(csv.starts_with(CSV_HEADER));
+        assert_eq!(csv.lines().count(), 2);
+    }
+
+    #[test]
+    fn probes_create_the_directory_and_leave_no_trace() {
+        let dir = std::env::temp_dir().join(format!("vanity-probe-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("nested/results.json");
+        probe_writable(&path).unwrap();
+        assert!(path.parent().unwrap().is_dir());
+        assert_eq!(fs::read_dir(path.parent().unwrap()).unwrap().count(), 0);
+
+        // A file where the directory should be.
+        fs::write(dir.join("file"), b"").unwrap();
+        assert!(probe_writable(&dir.join("file/results.json")).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn appends_refuse_other_schema_versions() {
+        let dir = std::env::temp_dir().join(format!("vanity-schema-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for format in [OutputFormat::JsonArray, OutputFormat::Ndjson] {
+            let path = dir.join(format!("{:?}.json", format));
+            fs::write(&path, r#"{"schema_version":2,"address":"1x"}"#).unwrap();
+            let err = append_result_file(&path, &sample(), format, None).unwrap_err();
+            assert!(err.to_string().contains("schema_version 2"));
+            // Unversioned entries are version 1.
+            fs::write(&path, r#"{"address":"1x"}"#).unwrap();
+            append_result_file(&path, &sample(), format, None).unwrap();
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn arrays_keep_their_layout_across_appends() {
+        let dir = std::env::temp_dir().join(format!("vanity-extend-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let other = || VanityResult {
+            address: "1y".into(),
+            ..sample()
+        };
+        let entries = [sample(), other(), sample()].map(|r| serde_json::to_value(r).unwrap());
+        let expected = serde_json::to_vec_pretty(&entries).unwrap();
+        for name in ["plain.json", "packed.json.gz"] {
+            let path = dir.join(name);
+            for report in [sample(), other(), sample()] {
+                append_result_file(&path, &report, OutputFormat::JsonArray, None).unwrap();
+            }
+            assert_eq!(read_text(&path).unwrap().as_bytes(), expected, "{name}");
+            assert!(!is_encrypted_result_file(&path));
+        }
+        // Trailing whitespace after the bracket is dropped, not kept in the middle.
+        let path = dir.join("spaced.json");
+        fs::write(&path, "[]\n\n").unwrap();
+        append_result_file(&path, &sample(), OutputFormat::JsonArray, None).unwrap();
+        append_result_file(&path, &sample(), OutputFormat::JsonArray, None).unwrap();
+        assert_eq!(
+            load_result_entries(&read_text(&path).unwrap())
+                .unwrap()
+                .len(),
+            2
+        );
+        fs::write(&path, r#"[{"address":"1x"}}"#).unwrap();
+        assert!(append_result_file(&path, &sample(), OutputFormat::JsonArray, None).is_err());
+
+        let sealed = dir.join("sealed.json");
+        append_result_file(&sealed, &sample(), OutputFormat::JsonArray, Some("pw")).unwrap();
+        assert!(is_encrypted_result_file(&sealed));
+        let err = append_result_file(&sealed, &sample(), OutputFormat::JsonArray, None);
+        assert!(err.unwrap_err().to_string().contains("encrypted"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn arrays_cut_off_mid_entry_keep_their_whole_entries() {
+        let dir = std::env::temp_dir().join(format!("vanity-truncated-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("result.json");
+        let other = || VanityResult {
+            address: "1y".into(),
+            ..sample()
+        };
+        for report in [sample(), other()] {
+            append_result_file(&path, &report, OutputFormat::JsonArray, None).unwrap();
+        }
+        let whole = fs::read(&path).unwrap();
+        let no_password = || Err(anyhow!("not encrypted"));
+        let expected = (sample().attempt_index, sample().address);
+        // Cut inside the second entry, then inside the first.
+        for cut in [whole.len() - 20, 20] {
+            fs::write(&path, &whole[..cut]).unwrap();
+            // --resume counts the whole entries, and the next match is saved after them.
+            let whole_entries = usize::from(cut != 20);
+            let matches = config_matches(&path, no_password, "x").unwrap();
+            assert_eq!(
+                matches,
+                vec![expected.clone(); whole_entries],
+                "cut at {cut}"
+            );
+            append_result_file(&path, &sample(), OutputFormat::JsonArray, None).unwrap();
+            let entries = load_result_entries(&read_text(&path).unwrap()).unwrap();
+            let addresses: Vec<_> = entries.iter().map(|e| e["address"].clone()).collect();
+            assert_eq!(addresses, vec!["1x"; whole_entries + 1], "cut at {cut}");
+        }
+        // No temp file is left next to it.
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn only_entries_of_the_same_search_are_counted() {
+        let dir = std::env::temp_dir().join(format!("vanity-count-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let other = || VanityResult {
+            config_hash: Some("y".into()),
+            ..sample()
+        };
+        let no_password = || Err(anyhow!("not encrypted"));
+        for format in [OutputFormat::JsonArray, OutputFormat::Ndjson] {
+            let path = dir.join(format!("{:?}.json.gz", format));
+            for report in [sample(), other(), sample()] {
+                append_result_file(&path, &report, format, None).unwrap();
+            }
+            // A session record of the same search is not a match.
+            append_search_miss(&path, &sample_miss(), format, None).unwrap();
+            let matches = config_matches(&path, no_password, "x").unwrap();
+            let expected = (sample().attempt_index, sample().address);
+            assert_eq!(matches, [expected.clone(), expected]);
+            assert!(config_matches(&path, no_password, "z").unwrap().is_empty());
+        }
+        let sealed = dir.join("sealed.json");
+        append_result_file(&sealed, &sample(), OutputFormat::JsonArray, Some("pw")).unwrap();
+        let password = || Ok("pw".to_string());
+        assert_eq!(config_matches(&sealed, password, "x").unwrap().len(), 1);
+        let csv = dir.join("rows.csv.gz");
+        for report in [sample(), other()] {
+            append_result_file(&csv, &report, OutputFormat::Csv, None).unwrap();
+        }
+        assert_eq!(
+            csv_addresses(&csv).unwrap(),
+            [sample().address, sample().address]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // A generated array far larger than the reader's buffer: each entry must arrive before
+    // much more than itself has been read, i.e. nothing is collected ahead of the visitor.
+    #[test]
+    fn large_arrays_stream_in_bounded_memory() {
+        struct Synthetic {
+            entries: u64,
+            next: u64,
+            pending: Vec<u8>,
+            produced: std::rc::Rc<std::cell::Cell<u64>>,
+        }
+        impl Read for Synthetic {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                if self.pending.is_empty() && self.next <= self.entries {
+                    self.pending = match self.next {
+                        0 => b"[".to_vec(),
+                        n if n == self.entries => b"]".to_vec(),
+                        n => format!("{}{{\"attempts\":{:>10}}}", if n > 1 { "," } else { "" }, n)
+                            .into_bytes(),
+                    };
+                    self.next += 1;
+                }
+                let len = buf.len().min(self.pending.len());
+                buf[..len].copy_from_slice(&self.pending[..len]);
+                self.pending.drain(..len);
+                self.produced.set(self.produced.get() + len as u64);
+                Ok(len)
+            }
+        }
+        const ENTRY_LEN: u64 = 24;
+        const ENTRIES: u64 = 200_000;
+        let produced = std::rc::Rc::default();
+        let reader = BufReader::new(Synthetic {
+            entries: ENTRIES,
+            next: 0,
+            pending: Vec::new(),
+            produced: std::rc::Rc::clone(&produced),
+        });
+        let mut seen = 0;
+        let count = stream_entries(reader, |entry| {
+            seen += 1;
+            assert_eq!(entry["attempts"], seen);
+            let read_ahead = produced.get().saturating_sub(seen * ENTRY_LEN);
+            assert!(read_ahead < 64 * 1024, "read {read_ahead} bytes ahead");
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!((count, seen), (ENTRIES - 1, ENTRIES - 1));
+        assert!(produced.get() >= (ENTRIES - 1) * ENTRY_LEN);
+    }
+}
@@ -0,0 +1,1049 @@
+//! Pattern validation and per-candidate matching.
+
+use anyhow::{anyhow, Context, Result};
+use regex::Regex;
+
+use crate::{
+    address::{AddressFormat, Base58Range, BECH32_CHARSET},
+    error::VanityError,
+};
+
+#[derive(Clone, Default)]
+pub struct Pattern {
+    pub prefix: Option<String>,
+    // Where `prefix` is compared: 0, or past the fixed part with Bech32 --prefix-offset.
+    pub prefix_at: usize,
+    pub suffix: Option<String>,
+    // --any-prefix/--any-suffix: at least one entry of either list must match.
+    pub any_prefix: Vec<String>,
+    pub any_suffix: Vec<String>,
+    pub contains: Vec<String>,
+    // Applied to the full encoded address; anchors are up to the user.
+    pub regex: Option<Regex>,
+    // At least `min` copies of `char` right after the fixed part ("most leading 1s").
+    pub leading: Option<LeadingRun>,
+    pub wordlist: Option<Wordlist>,
+    // Rejects a match whose variable part contains any of these; checked last.
+    pub exclude: Vec<String>,
+    // Base58 only: patterns are stored lowercase and compared with ASCII case folding.
+    pub ignore_case: bool,
+    // Everything above applies to the key's WIF rather than the address (--match-wif).
+    pub match_wif: bool,
+    // Leading characters pinned by the version byte or HRP; `contains` only searches past them.
+    pub fixed_len: usize,
+    // Checked against the address payload before it is encoded, so misses skip the encoder.
+    pub hash_prefix: Option<HashPrefix>,
+}
+
+impl Pattern {
+    pub fn is_empty(&self) -> bool {
+        self.prefix.is_none()
+            && self.suffix.is_none()
+            && self.any_prefix.is_empty()
+            && self.any_suffix.is_empty()
+            && self.contains.is_empty()
+            && self.regex.is_none()
+            && self.leading.is_none()
+            && self.wordlist.is_none()
+            && self.hash_prefix.is_none()
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct LeadingRun {
+    pub char: char,
+    pub min: usize,
+}
+
+/// Leading hex digits of the payload an address encodes (`--hash-prefix`), whatever its
+/// text encoding: see `address_payload`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HashPrefix {
+    // Lowercase without `0x`; an odd length pins the high nibble of the last byte.
+    pub hex: String,
+    nibbles: Vec<u8>,
+}
+
+impl HashPrefix {
+    pub fn matches(&self, payload: &[u8]) -> bool {
+        self.nibbles.len() <= payload.len() * 2
+            && self.nibbles.iter().enumerate().all(|(at, &nibble)| {
+                let byte = payload[at / 2];
+                let digit = if at % 2 == 0 { byte >> 4 } else { byte & 0x0f };
+                digit == nibble
+            })
+    }
+}
+
+/// Parses a `--hash-prefix` (hex, optionally `0x`-prefixed) for a payload of
+/// `payload_len` bytes, which it must fit.
+pub fn prepare_hash_prefix(
+    value: Option<String>,
+    payload_len: usize,
+) -> Result<Option<HashPrefix>, VanityError> {
+    value
+        .map(|value| {
+            let hex = value.trim_start_matches("0x").to_ascii_lowercase();
+            let nibbles = hex
+                .chars()
+                .map(|c| c.to_digit(16).map(|digit| digit as u8))
+                .collect::<Option<Vec<u8>>>()
+                .filter(|nibbles| !nibbles.is_empty())
+                .ok_or_else(|| VanityError::InvalidPattern {
+                    message: format!("Hash prefix '{}' must be hex digits", value),
+                    pattern: value.clone(),
+                })?;
+            if nibbles.len() > payload_len * 2 {
+                return Err(VanityError::InfeasiblePattern(format!(
+                    "Hash prefix '{}' has {} hex digits but the address payload is only {} bytes",
+                    value,
+                    nibbles.len(),
+                    payload_len
+                )));
+            }
+            Ok(HashPrefix { hex, nibbles })
+        })
+        .transpose()
+}
+
+/// Any-of word set matched right after the fixed part (or anywhere past it with
+/// `contains`). Stored as a byte trie so each candidate costs one walk per start position
+/// instead of one comparison per word.
+#[derive(Clone, Debug)]
+pub struct Wordlist {
+    pub words: Vec<String>,
+    pub contains: bool,
+    // Words are lowercase; address bytes are folded before each trie step.
+    pub ignore_case: bool,
+    nodes: Vec<TrieNode>,
+}
+
+#[derive(Clone, Debug, Default)]
+struct TrieNode {
+    // Sorted by byte; address alphabets are small, so a binary search beats a map.
+    children: Vec<(u8, u32)>,
+    // Index into `words` of the word ending here.
+    word: Option<u32>,
+}
+
+impl Wordlist {
+    pub fn new(words: Vec<String>, contains: bool, ignore_case: bool) -> Self {
+        let mut nodes = vec![TrieNode::default()];
+        for (index, word) in words.iter().enumerate() {
+            let mut node = 0usize;
+            for &byte in word.as_bytes() {
+                node = match nodes[node]
+                    .children
+                    .binary_search_by_key(&byte, |&(b, _)| b)
+                {
+                    Ok(pos) => nodes[node].children[pos].1 as usize,
+                    Err(pos) => {
+                        let next = nodes.len();
+                        nodes.push(TrieNode::default());
+                        nodes[node].children.insert(pos, (byte, next as u32));
+                        next
+                    }
+                };
+            }
+            nodes[node].word.get_or_insert(index as u32);
+        }
+        Self {
+            words,
+            contains,
+            ignore_case,
+            nodes,
+        }
+    }
+
+    /// The longest word found in `body` (at its start, or at the earliest position that has
+    /// one in `contains` mode).
+    pub fn find<'a>(&'a self, body: &str) -> Option<&'a str> {
+        let bytes = body.as_bytes();
+        let starts = if self.contains { bytes.len() } else { 1 };
+        (0..starts.min(bytes.len()))
+            .find_map(|start| self.longest_at(&bytes[start..]))
+            .map(|index| self.words[index].as_str())
+    }
+
+    fn longest_at(&self, bytes: &[u8]) -> Option<usize> {
+        let mut node = &self.nodes[0];
+        let mut found = None;
+        for &byte in bytes {
+            let byte = if self.ignore_case {
+                byte.to_ascii_lowercase()
+            } else {
+                byte
+            };
+            match node.children.binary_search_by_key(&byte, |&(b, _)| b) {
+                Ok(pos) => node = &self.nodes[node.children[pos].1 as usize],
+                Err(_) => break,
+            }
+            if let Some(word) = node.word {
+                found = Some(word as usize);
+            }
+        }
+        found
+    }
+}
+
+/// Reads newline-separated words, skipping blank lines and `#` comments. Each word is
+/// normalised and charset-checked like `prepare_pattern`; duplicates are dropped.
+pub fn load_wordlist(
+    raw: &str,
+    format: AddressFormat,
+    contains: bool,
+    ignore_case: bool,
+) -> Result<Wordlist> {
+    let mut words = Vec::new();
+    for (line_no, line) in raw.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let word = prepare_pattern(Some(line.to_string()), format, ignore_case)
+            .with_context(|| format!("Wordlist line {}", line_no + 1))?
+            .unwrap_or_default();
+        if !words.contains(&word) {
+            words.push(word);
+        }
+    }
+    if words.is_empty() {
+        return Err(anyhow!("Wordlist contains no words"));
+    }
+    Ok(Wordlist::new(words, contains, ignore_case))
+}
+
+/// Length of the run of `c` starting right after the first `fixed_len` characters.
+pub fn leading_run(address: &str, fixed_len: usize, c: char) -> usize {
+    address
+        .get(fixed_len..)
+        .unwrap_or("")
+        .chars()
+        .take_while(|&ch| ch == c)
+        .count()
+}
+
+/// With `ignore_case` (Base58 only) the pattern is lowercased, and each letter only needs
+/// one of its cases in the alphabet: `o` and `L` are fine, `0` is still rejected.
+pub fn prepare_pattern(
+    pattern: Option<String>,
+    format: AddressFormat,
+    ignore_case: bool,
+) -> Result<Option<String>, VanityError> {
+    pattern
+        .map(|value| {
+            if ignore_case && !format.is_bech32() {
+                // Check the case variant that exists, so errors only flag truly absent chars.
+                let probe: String = value
+                    .chars()
+                    .map(|c| {
+                        if is_base58_char(c.to_ascii_lowercase()) {
+                            c.to_ascii_lowercase()
+                        } else {
+                            c.to_ascii_uppercase()
+                        }
+                    })
+                    .collect();
+                ensure_charset(&probe, format).map_err(|_| charset_error(&value, format))?;
+                return Ok(value.to_ascii_lowercase());
+            }
+            let normalized = match format {
+                AddressFormat::P2pkh | AddressFormat::P2shP2wpkh => value,
+                AddressFormat::Bech32 | AddressFormat::P2wsh => value.to_lowercase(),
+            };
+            ensure_charset(&normalized, format)?;
+            Ok(normalized)
+        })
+        .transpose()
+}
+
+/// Like `prepare_pattern`, but Bech32 prefixes must spell out the `fixed` HRP + separator +
+/// witness version (or a leading part of it); only the remainder is charset-checked.
+pub fn prepare_prefix(
+    pattern: Option<String>,
+    format: AddressFormat,
+    fixed: &str,
+    ignore_case: bool,
+) -> Result<Option<String>, VanityError> {
+    if !format.is_bech32() {
+        return prepare_pattern(pattern, format, ignore_case);
+    }
+    pattern
+        .map(|value| {
+            let normalized = value.to_lowercase();
+            // Bytes, not chars: the fixed part is ASCII, while the input may not be.
+            let pinned = normalized.len().min(fixed.len());
+            if normalized.as_bytes()[..pinned] != fixed.as_bytes()[..pinned] {
+                let hint = if normalized.starts_with(&fixed[..fixed.len() - 1]) {
+                    Some(format!(
+                        "the witness version fixes the first {} characters to '{}'",
+                        fixed.len(),
+                        fixed
+                    ))
+                } else if ensure_charset(&normalized, format).is_ok() {
+                    Some(format!("did you mean '{}{}'?", fixed, normalized))
+                } else {
+                    None
+                };
+                let hint = hint.map(|h| format!(" ({h})")).unwrap_or_default();
+                return Err(VanityError::InvalidPattern {
+                    message: format!(
+                        "Bech32 prefix '{}' must start with the fixed '{}'{}",
+                        value, fixed, hint
+                    ),
+                    pattern: value,
+                });
+            }
+            ensure_charset(&normalized[pinned..], format).map_err(|e| {
+                VanityError::InvalidPattern {
+                    message: format!("Invalid Bech32 prefix '{}': {}", value, e),
+                    pattern: value.clone(),
+                }
+            })?;
+            Ok(normalized)
+        })
+        .transpose()
+}
+
+/// Validates a `--match-wif` prefix: Base58, and inside `range`, the WIFs the version byte
+/// allows (see `wif_range`).
+pub fn prepare_wif_prefix(
+    pattern: Option<String>,
+    range: &Base58Range,
+    ignore_case: bool,
+) -> Result<Option<String>, VanityError> {
+    prepare_base58_prefix(pattern, range, "WIF prefix", "these keys", ignore_case)
+}
+
+/// Validates a Base58 prefix against `range`, the encodings the version byte allows
+/// (`wif_range`, `address_range`): it must start with one of their leading characters and
+/// not sort past either bound. `label` and `what` name the prefix and the strings it is
+/// matched against in the error.
+pub fn prepare_base58_prefix(
+    pattern: Option<String>,
+    range: &Base58Range,
+    label: &str,
+    what: &str,
+    ignore_case: bool,
+) -> Result<Option<String>, VanityError> {
+    let Some(prefix) = prepare_pattern(pattern, AddressFormat::P2pkh, ignore_case)? else {
+        return Ok(None);
+    };
+    let leading = range.leading_chars();
+    let first = prefix.chars().next();
+    let allowed = |c: char| {
+        leading.chars().any(|l| {
+            if ignore_case {
+                l.eq_ignore_ascii_case(&c)
+            } else {
+                l == c
+            }
+        })
+    };
+    if !first.is_some_and(allowed) {
+        let options: Vec<String> = leading.chars().map(String::from).collect();
+        let hint = match leading.len() {
+            1 => format!(" (did you mean '{}{}'?)", leading, prefix),
+            _ => String::new(),
+        };
+        return Err(VanityError::InfeasiblePattern(format!(
+            "{} '{}' can never match: {} always start with {}{}",
+            label,
+            prefix,
+            what,
+            options.join(" or "),
+            hint
+        )));
+    }
+    // The version byte pins more than the first character (`3[1-9A-R]` for P2SH).
+    if range.prefix_fraction(&prefix, ignore_case) <= 0.0 {
+        let shown = |bound: &str| -> String { bound.chars().take(prefix.len() + 1).collect() };
+        return Err(VanityError::InfeasiblePattern(format!(
+            "{} '{}' can never match: {} run from {}… to {}…",
+            label,
+            prefix,
+            what,
+            shown(&range.low),
+            shown(&range.high)
+        )));
+    }
+    Ok(Some(prefix))
+}
+
+pub fn prepare_patterns(
+    values: &[String],
+    format: AddressFormat,
+    ignore_case: bool,
+) -> Result<Vec<String>, VanityError> {
+    values
+        .iter()
+        .map(|value| {
+            Ok(prepare_pattern(Some(value.clone()), format, ignore_case)?.unwrap_or_default())
+        })
+        .collect()
+}
+
+/// Rejects a prefix contradicting the leading characters every address of the format
+/// shares, read off any `sample` address of it (a `3…` prefix for P2PKH, say). With
+/// `--any-prefix`, one entry that fits is enough.
+pub fn check_fixed_part(pattern: &Pattern, sample: &str) -> Result<(), VanityError> {
+    let fixed = sample.get(..pattern.fixed_len).unwrap_or(sample);
+    let fits = |p: &str| {
+        let n = p.len().min(fixed.len());
+        let (p, fixed) = (&p.as_bytes()[..n], &fixed.as_bytes()[..n]);
+        if pattern.ignore_case {
+            p.eq_ignore_ascii_case(fixed)
+        } else {
+            p == fixed
+        }
+    };
+    if let Some(p) = pattern.prefix.as_deref().filter(|_| pattern.prefix_at == 0) {
+        if !fits(p) {
+            return Err(VanityError::InfeasiblePattern(format!(
+                "prefix '{}' can never match addresses starting with '{}'",
+                p, fixed
+            )));
+        }
+    }
+    if pattern.any_suffix.is_empty()
+        && !pattern.any_prefix.is_empty()
+        && !pattern.any_prefix.iter().any(|p| fits(p))
+    {
+        return Err(VanityError::InfeasiblePattern(format!(
+            "no --any-prefix entry can match addresses starting with '{}'",
+            fixed
+        )));
+    }
+    Ok(())
+}
+
+/// Rejects patterns that can never fit an encoding at most `max_len` characters long (see
+/// `max_encoded_len`): a prefix running past the end, a suffix or substring reaching into
+/// the fixed leading part, or a prefix and suffix needing more characters than exist.
+pub fn check_lengths(pattern: &Pattern, max_len: usize) -> Result<(), VanityError> {
+    let what = if pattern.match_wif {
+        "WIFs"
+    } else {
+        "addresses"
+    };
+    let body_len = max_len.saturating_sub(pattern.fixed_len);
+    let prefix_end = |p: &String| pattern.prefix_at + p.len();
+    for p in pattern.prefix.iter().chain(&pattern.any_prefix) {
+        if prefix_end(p) > max_len {
+            return Err(VanityError::InfeasiblePattern(format!(
+                "Prefix '{}' would end at character {} but these {} have at most {}",
+                p,
+                prefix_end(p),
+                what,
+                max_len
+            )));
+        }
+    }
+    for s in pattern.suffix.iter().chain(&pattern.any_suffix) {
+        if s.len() > body_len {
+            return Err(VanityError::InfeasiblePattern(format!(
+                "Suffix '{}' reaches into the {} fixed leading characters: these {} have at most {} characters after them",
+                s,
+                pattern.fixed_len,
+                what,
+                body_len
+            )));
+        }
+    }
+    if let (Some(p), Some(s)) = (&pattern.prefix, &pattern.suffix) {
+        if prefix_end(p) + s.len() > max_len {
+            return Err(VanityError::InfeasiblePattern(format!(
+                "Prefix '{}' and suffix '{}' need {} characters together but these {} have at most {}",
+                p,
+                s,
+                prefix_end(p) + s.len(),
+                what,
+                max_len
+            )));
+        }
+    }
+    for c in &pattern.contains {
+        if c.len() > body_len {
+            return Err(VanityError::InfeasiblePattern(format!(
+                "Substring '{}' is longer than the {} characters past the fixed part of these {}",
+                c, body_len, what
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn is_base58_char(c: char) -> bool {
+    matches!(c, '1'..='9' | 'A'..='H' | 'J'..='N' | 'P'..='Z' | 'a'..='k' | 'm'..='z')
+}
+
+pub fn ensure_charset(value: &str, format: AddressFormat) -> Result<(), VanityError> {
+    let valid = match format {
+        AddressFormat::P2pkh | AddressFormat::P2shP2wpkh => value.chars().all(is_base58_char),
+        AddressFormat::Bech32 | AddressFormat::P2wsh => {
+            value.chars().all(|c| BECH32_CHARSET.contains(c))
+        }
+    };
+    if valid {
+        return Ok(());
+    }
+    Err(charset_error(value, format))
+}
+
+fn charset_error(value: &str, format: AddressFormat) -> VanityError {
+    let note = match format {
+        AddressFormat::P2pkh | AddressFormat::P2shP2wpkh => "Base58 characters (no 0 O I l)",
+        AddressFormat::Bech32 | AddressFormat::P2wsh => "Bech32 data characters (no 1 b i o)",
+    };
+    VanityError::InvalidPattern {
+        pattern: value.to_string(),
+        message: format!(
+            "Pattern '{}' contains invalid characters for {:?} ({})",
+            value, format, note
+        ),
+    }
+}
+
+pub fn matches_pattern(address: &str, pattern: &Pattern) -> bool {
+    let fold = pattern.ignore_case;
+    if let Some(p) = pattern.prefix.as_deref() {
+        if !has_prefix(address, pattern.prefix_at, p, fold) {
+            return false;
+        }
+    }
+    if let Some(s) = pattern.suffix.as_deref() {
+        if !has_suffix(address, s, fold) {
+            return false;
+        }
+    }
+    if (!pattern.any_prefix.is_empty() || !pattern.any_suffix.is_empty())
+        && matched_any_prefix(address, pattern).is_none()
+        && matched_any_suffix(address, pattern).is_none()
+    {
+        return false;
+    }
+    if !pattern.contains.is_empty() {
+        let body = address.get(pattern.fixed_len..).unwrap_or("").as_bytes();
+        let found =
+            |c: &String| (0..body.len()).any(|at| starts_with(&body[at..], c.as_bytes(), fold));
+        if !pattern.contains.iter().all(found) {
+            return false;
+        }
+    }
+    if let Some(re) = &pattern.regex {
+        if !re.is_match(address) {
+            return false;
+        }
+    }
+    if let Some(run) = pattern.leading {
+        if leading_run(address, pattern.fixed_len, run.char) < run.min {
+            return false;
+        }
+    }
+    if let Some(words) = &pattern.wordlist {
+        if words
+            .find(address.get(pattern.fixed_len..).unwrap_or(""))
+            .is_none()
+        {
+            return false;
+        }
+    }
+    if !pattern.exclude.is_empty() {
+        let body = address.get(pattern.fixed_len..).unwrap_or("").as_bytes();
+        let found =
+            |e: &String| (0..body.len()).any(|at| starts_with(&body[at..], e.as_bytes(), fold));
+        if pattern.exclude.iter().any(found) {
+            return false;
+        }
+    }
+    true
+}
+
+/// The first --any-prefix entry `address` starts with.
+pub fn matched_any_prefix<'a>(address: &str, pattern: &'a Pattern) -> Option<&'a str> {
+    pattern
+        .any_prefix
+        .iter()
+        .find(|p| has_prefix(address, 0, p, pattern.ignore_case))
+        .map(String::as_str)
+}
+
+/// The first --any-suffix entry `address` ends with.
+pub fn matched_any_suffix<'a>(address: &str, pattern: &'a Pattern) -> Option<&'a str> {
+    pattern
+        .any_suffix
+        .iter()
+        .find(|s| has_suffix(address, s, pattern.ignore_case))
+        .map(String::as_str)
+}
+
+fn has_prefix(address: &str, at: usize, prefix: &str, fold: bool) -> bool {
+    let rest = address.as_bytes().get(at..);
+    rest.is_some_and(|rest| starts_with(rest, prefix.as_bytes(), fold))
+}
+
+fn has_suffix(address: &str, suffix: &str, fold: bool) -> bool {
+    let bytes = address.as_bytes();
+    let tail = bytes.len().checked_sub(suffix.len()).map(|at| &bytes[at..]);
+    tail.is_some_and(|tail| starts_with(tail, suffix.as_bytes(), fold))
+}
+
+/// How many leading characters of `pattern.prefix` the address reproduces (0 without a
+/// prefix). Ranks near misses for --track-best; a full match scores the prefix length.
+pub fn prefix_score(address: &str, pattern: &Pattern) -> usize {
+    let Some(prefix) = pattern.prefix.as_deref() else {
+        return 0;
+    };
+    address
+        .bytes()
+        .skip(pattern.prefix_at)
+        .zip(prefix.bytes())
+        .take_while(|&(a, p)| {
+            if pattern.ignore_case {
+                a.to_ascii_lowercase() == p
+            } else {
+                a == p
+            }
+        })
+        .count()
+}
+
+// `needle` is already lowercase when folding.
+fn starts_with(haystack: &[u8], needle: &[u8], fold: bool) -> bool {
+    haystack.len() >= needle.len()
+        && if fold {
+            haystack
+                .iter()
+                .zip(needle)
+                .all(|(h, n)| h.to_ascii_lowercase() == *n)
+        } else {
+            haystack.starts_with(needle)
+        }
+}
+
+/// Approximate chance that one random address matches `pattern`, treating every character
+/// past the fixed part as uniform over the format's alphabet. `sample` is any address of the
+/// same format/network and supplies the fixed leading characters and typical length. Base58
+/// prefixes are instead scored against `range` (`address_range`, `wif_range`), since the
+/// version byte skews their first characters.
+/// Returns `None` for regex patterns, which can't be estimated this way.
+pub fn match_probability(
+    pattern: &Pattern,
+    format: AddressFormat,
+    sample: &str,
+    range: Option<&Base58Range>,
+) -> Option<f64> {
+    if pattern.regex.is_some() {
+        return None;
+    }
+    let base: f64 = match format {
+        AddressFormat::P2pkh | AddressFormat::P2shP2wpkh => 58.0,
+        AddressFormat::Bech32 | AddressFormat::P2wsh => 32.0,
+    };
+    let fixed_len = pattern.fixed_len.min(sample.len());
+    let body_len = sample.len() - fixed_len;
+    // A folded letter matches whichever of its cases the alphabet has.
+    let odds = |s: &str| -> f64 {
+        s.chars()
+            .map(|c| {
+                let both = pattern.ignore_case
+                    && c.is_ascii_alphabetic()
+                    && is_base58_char(c.to_ascii_lowercase())
+                    && is_base58_char(c.to_ascii_uppercase());
+                if both {
+                    2.0 / base
+                } else {
+                    1.0 / base
+                }
+            })
+            .product()
+    };
+    // The HRP/witness version are exact; Base58 prefixes take their share of the range.
+    let prefix_odds = |prefix: &str| -> f64 {
+        if let Some(range) = range {
+            return range.prefix_fraction(prefix, pattern.ignore_case);
+        }
+        let pinned = prefix.len().min(fixed_len);
+        if format.is_bech32() && prefix[..pinned] != sample[..pinned] {
+            return 0.0;
+        }
+        odds(&prefix[pinned..])
+    };
+    let mut p = 1.0f64;
+    if let Some(prefix) = pattern.prefix.as_deref().filter(|_| pattern.prefix_at > 0) {
+        if pattern.prefix_at + prefix.len() > sample.len() {
+            return Some(0.0);
+        }
+        p *= odds(prefix);
+    } else if let Some(prefix) = pattern.prefix.as_deref() {
+        p *= prefix_odds(prefix);
+    }
+    if let Some(suffix) = pattern.suffix.as_deref() {
+        p *= odds(suffix);
+    }
+    if !pattern.any_prefix.is_empty() || !pattern.any_suffix.is_empty() {
+        // Union bound over the alternatives, as for wordlists.
+        let any: f64 = pattern
+            .any_prefix
+            .iter()
+            .map(|a| prefix_odds(a))
+            .sum::<f64>()
+            + pattern.any_suffix.iter().map(|a| odds(a)).sum::<f64>();
+        p *= any.min(1.0);
+    }
+    if let Some(run) = pattern.leading {
+        // Each extra leading Base58 '1' is a whole zero byte of the payload, not one digit.
+        let per_char = if !format.is_bech32() && run.char == '1' {
+            256.0
+        } else {
+            base
+        };
+        p *= per_char.powi(-(run.min as i32));
+    }
+    for c in &pattern.contains {
+        let positions = (body_len + 1).saturating_sub(c.len()) as f64;
+        p *= (positions * odds(c)).min(1.0);
+    }
+    if let Some(list) = &pattern.wordlist {
+        // Union bound over the words; close enough when they are long and few overlap.
+        let any: f64 = list
+            .words
+            .iter()
+            .map(|w| {
+                let positions = if list.contains {
+                    (body_len + 1).saturating_sub(w.len()) as f64
+                } else {
+                    1.0
+                };
+                positions * odds(w)
+            })
+            .sum();
+        p *= any.min(1.0);
+    }
+    if let Some(hash) = &pattern.hash_prefix {
+        p *= 16f64.powi(-(hash.nibbles.len() as i32));
+    }
+    // Union bound on hitting an excluded substring anywhere in the body.
+    let excluded: f64 = pattern
+        .exclude
+        .iter()
+        .map(|e| (body_len + 1).saturating_sub(e.len()) as f64 * odds(e))
+        .sum();
+    p *= (1.0 - excluded).max(0.0);
+    Some(p)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::{address_range, wif_range, Network};
+
+    #[test]
+    fn bech32_prefix_requires_the_fixed_part() {
+        let fixed = "bc1q";
+        let ok = |p: &str| prepare_prefix(Some(p.into()), AddressFormat::Bech32, fixed, false);
+        assert_eq!(ok("BC1QCAFE").unwrap().as_deref(), Some("bc1qcafe"));
+        assert_eq!(ok("bc1").unwrap().as_deref(), Some("bc1"));
+        assert!(ok("cafe").is_err());
+        assert!(ok("bc1pcafe").is_err());
+        // 'b' is not a Bech32 data character.
+        assert!(ok("bc1qcab").is_err());
+        // Multibyte input is rejected, not sliced mid-character, and gets no hint that
+        // would itself be invalid.
+        for prefix in ["xxxé", "ééé", "bc1é"] {
+            let Err(VanityError::InvalidPattern { message, .. }) = ok(prefix) else {
+                panic!("{prefix} was accepted");
+            };
+            assert!(!message.contains("did you mean"), "{message}");
+        }
+        let Err(VanityError::InvalidPattern { message, .. }) = ok("cafe") else {
+            panic!("cafe was accepted");
+        };
+        assert!(message.contains("did you mean 'bc1qcafe'?"), "{message}");
+    }
+
+    #[test]
+    fn wordlist_matches_prefix_or_anywhere() {
+        let raw = "# words\ncafe\n\nca\nbeef\ncafe\n";
+        let prefix = load_wordlist(raw, AddressFormat::P2pkh, false, false).unwrap();
+        assert_eq!(prefix.words, ["cafe", "ca", "beef"]);
+        assert_eq!(prefix.find("cafeXyz"), Some("cafe"));
+        assert_eq!(prefix.find("caXyz"), Some("ca"));
+        assert_eq!(prefix.find("XbeefX"), None);
+        let anywhere = Wordlist::new(prefix.words.clone(), true, false);
+        assert_eq!(anywhere.find("XbeefX"), Some("beef"));
+        assert!(load_wordlist("c0ffee\n", AddressFormat::P2pkh, false, false).is_err());
+    }
+
+    #[test]
+    fn wif_prefix_must_start_with_a_leading_char() {
+        let wif = wif_range(&Network::Mainnet.params(), true);
+        assert_eq!(
+            prepare_wif_prefix(Some("L1ke".into()), &wif, false)
+                .unwrap()
+                .as_deref(),
+            Some("L1ke")
+        );
+        assert!(prepare_wif_prefix(Some("5Kx".into()), &wif, false).is_err());
+        assert!(prepare_wif_prefix(Some("kwx".into()), &wif, true).is_ok());
+        assert!(prepare_wif_prefix(None, &wif, false).unwrap().is_none());
+    }
+
+    #[test]
+    fn pattern_failures_are_typed() {
+        let err = prepare_pattern(Some("c0ffee".into()), AddressFormat::P2pkh, false);
+        assert!(
+            matches!(err, Err(VanityError::InvalidPattern { pattern, .. }) if pattern == "c0ffee")
+        );
+        let err = prepare_prefix(Some("cafe".into()), AddressFormat::Bech32, "bc1q", false);
+        assert!(matches!(err, Err(VanityError::InvalidPattern { .. })));
+        let wif = wif_range(&Network::Mainnet.params(), true);
+        let err = prepare_wif_prefix(Some("5Kx".into()), &wif, false);
+        assert!(matches!(err, Err(VanityError::InfeasiblePattern(_))));
+    }
+
+    #[test]
+    fn base58_prefixes_must_start_with_the_version_char() {
+        let mainnet = address_range(AddressFormat::P2pkh, &Network::Mainnet.params()).unwrap();
+        let testnet = address_range(AddressFormat::P2pkh, &Network::Testnet.params()).unwrap();
+        let prepare = |p: &str, range| {
+            prepare_base58_prefix(Some(p.into()), range, "Prefix", "addresses", false)
+        };
+        assert!(prepare("1Cat", &mainnet).is_ok());
+        let err = prepare("5Cat", &mainnet).unwrap_err().to_string();
+        assert!(
+            err.contains("always start with 1 (did you mean '15Cat'?)"),
+            "{err}"
+        );
+        assert!(prepare("n3Cat", &testnet).is_ok());
+        assert!(!prepare("1Cat", &testnet)
+            .unwrap_err()
+            .to_string()
+            .contains("did you mean"));
+    }
+
+    #[test]
+    fn base58_prefixes_must_sort_inside_the_version_range() {
+        let mainnet = Network::Mainnet.params();
+        let testnet = Network::Testnet.params();
+        let p2sh = address_range(AddressFormat::P2shP2wpkh, &mainnet).unwrap();
+        let p2pkh = address_range(AddressFormat::P2pkh, &testnet).unwrap();
+        let prepare = |p: &str, range, ignore_case| {
+            prepare_base58_prefix(
+                Some(p.into()),
+                range,
+                "Prefix",
+                "P2SH addresses",
+                ignore_case,
+            )
+        };
+        let err = prepare("3a", &p2sh, false).unwrap_err();
+        assert!(matches!(err, VanityError::InfeasiblePattern(_)));
+        assert!(
+            err.to_string()
+                .contains("P2SH addresses run from 31h… to 3R2…"),
+            "{err}"
+        );
+        assert!(prepare("3R", &p2sh, false).is_ok());
+        assert!(prepare("31h", &p2sh, false).is_ok());
+        // 'r' folds to the allowed 'R'; neither spelling of 's' is allowed.
+        assert!(prepare("3r", &p2sh, true).is_ok());
+        assert!(prepare("3s", &p2sh, true).is_err());
+        assert!(prepare("n4", &p2pkh, false).is_ok());
+        assert!(prepare("nz", &p2pkh, false).is_err());
+        assert!(prepare("mf", &p2pkh, false).is_ok());
+        assert!(prepare("me", &p2pkh, false).is_err());
+        for (compressed, bad) in [(true, "Lz"), (true, "Kv"), (false, "5z"), (false, "5G")] {
+            let wif = wif_range(&mainnet, compressed);
+            assert!(
+                prepare_wif_prefix(Some(bad.into()), &wif, false).is_err(),
+                "{bad}"
+            );
+        }
+        for (compressed, good) in [(true, "L5"), (true, "Kw"), (false, "5K"), (false, "5H")] {
+            let wif = wif_range(&mainnet, compressed);
+            assert!(
+                prepare_wif_prefix(Some(good.into()), &wif, false).is_ok(),
+                "{good}"
+            );
+        }
+    }
+
+    #[test]
+    fn ignore_case_folds_base58_letters() {
+        let prepare = |p: &str| prepare_pattern(Some(p.into()), AddressFormat::P2pkh, true);
+        // 'l' and 'O' are not Base58, but their other cases are.
+        assert_eq!(prepare("1LOL").unwrap().as_deref(), Some("1lol"));
+        assert!(prepare("10").is_err());
+        let pattern = Pattern {
+            prefix: prepare("1sAtoshi").unwrap(),
+            suffix: prepare("xY").unwrap(),
+            ignore_case: true,
+            ..Pattern::default()
+        };
+        assert!(matches_pattern("1SatoShiabcXY", &pattern));
+        assert!(!matches_pattern("1Satoshiabcxz", &pattern));
+        assert_eq!(prefix_score("1SatOx", &pattern), 5);
+        let exact = Pattern {
+            ignore_case: false,
+            ..pattern
+        };
+        assert!(!matches_pattern("1SatoShiabcXY", &exact));
+        assert_eq!(prefix_score("1satoShiabcXY", &exact), 5);
+    }
+
+    #[test]
+    fn exclude_rejects_otherwise_matching_addresses() {
+        let mut pattern = Pattern {
+            prefix: Some("1Ab".into()),
+            exclude: vec!["xX".into()],
+            fixed_len: 1,
+            ..Pattern::default()
+        };
+        assert!(matches_pattern("1Abcdef", &pattern));
+        assert!(!matches_pattern("1AbcxXf", &pattern));
+        // Only the variable part is searched, so the fixed '1' can be excluded.
+        pattern.exclude = vec!["1".into()];
+        assert!(matches_pattern("1Abcdef", &pattern));
+        pattern.ignore_case = true;
+        pattern.prefix = Some("1ab".into());
+        pattern.exclude = vec!["cd".into()];
+        assert!(!matches_pattern("1AbCDef", &pattern));
+    }
+
+    #[test]
+    fn any_prefix_or_suffix_is_enough() {
+        let pattern = Pattern {
+            any_prefix: vec!["1Ab".into(), "1Cd".into()],
+            any_suffix: vec!["zz".into()],
+            fixed_len: 1,
+            ..Pattern::default()
+        };
+        assert!(matches_pattern("1Cdxxxx", &pattern));
+        assert!(matches_pattern("1xxxxzz", &pattern));
+        assert!(!matches_pattern("1xxxxxx", &pattern));
+        assert_eq!(matched_any_prefix("1Cdxxzz", &pattern), Some("1Cd"));
+        assert_eq!(matched_any_suffix("1Cdxxzz", &pattern), Some("zz"));
+        // Other flags still apply on top of the alternatives.
+        let narrowed = Pattern {
+            suffix: Some("q".into()),
+            ..pattern.clone()
+        };
+        assert!(!matches_pattern("1Abxxxx", &narrowed));
+        assert!(matches_pattern("1Abxxxq", &narrowed));
+        let sample = "1xxxxxxxxxx";
+        let p = match_probability(&pattern, AddressFormat::P2pkh, sample, None).unwrap();
+        assert!((p - 3.0 * 58f64.powi(-2)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn base58_prefix_odds_follow_the_version_byte() {
+        let (mainnet, testnet) = (Network::Mainnet.params(), Network::Testnet.params());
+        let odds = |prefix: &str, range: &Base58Range| {
+            let pattern = Pattern {
+                prefix: Some(prefix.into()),
+                fixed_len: 1,
+                ..Pattern::default()
+            };
+            match_probability(&pattern, AddressFormat::P2pkh, "1xxxx", Some(range)).unwrap()
+        };
+        // Neither testnet leading character is certain.
+        let p2pkh = address_range(AddressFormat::P2pkh, &testnet).unwrap();
+        let (m, n) = (odds("m", &p2pkh), odds("n", &p2pkh));
+        assert!(m < 1.0 && n < 1.0 && (m + n - 1.0).abs() < 1e-9, "{m} {n}");
+        let wif = wif_range(&mainnet, true);
+        let (k, l) = (odds("K", &wif), odds("L", &wif));
+        assert!(k < 1.0 && l < 1.0 && (k + l - 1.0).abs() < 1e-9, "{k} {l}");
+        assert_eq!(odds("Lz", &wif), 0.0);
+        let p2sh = address_range(AddressFormat::P2shP2wpkh, &mainnet).unwrap();
+        assert_eq!(odds("3a", &p2sh), 0.0);
+        assert!(odds("3R", &p2sh) > 0.0);
+    }
+
+    #[test]
+    fn impossible_lengths_are_rejected() {
+        // Bech32 v0 mainnet: 42 characters, the first 4 fixed.
+        let pattern = |prefix: &str, suffix: &str| Pattern {
+            prefix: Some(prefix.into()).filter(|p: &String| !p.is_empty()),
+            suffix: Some(suffix.into()).filter(|s: &String| !s.is_empty()),
+            fixed_len: 4,
+            ..Pattern::default()
+        };
+        let q = |n: usize| "q".repeat(n);
+        assert!(check_lengths(&pattern(&format!("bc1q{}", q(20)), &q(18)), 42).is_ok());
+        assert!(check_lengths(&pattern(&format!("bc1q{}", q(26)), &q(20)), 42).is_err());
+        assert!(check_lengths(&pattern(&format!("bc1q{}", q(39)), ""), 42).is_err());
+        assert!(check_lengths(&pattern("", &q(38)), 42).is_ok());
+        assert!(check_lengths(&pattern("", &q(39)), 42).is_err());
+        let offset = Pattern {
+            prefix_at: 40,
+            ..pattern("qqq", "")
+        };
+        assert!(check_lengths(&offset, 42).is_err());
+    }
+
+    #[test]
+    fn prefixes_must_agree_with_the_fixed_part() {
+        let pattern = |prefix: &str, any: &[&str]| Pattern {
+            prefix: Some(prefix.into()).filter(|p: &String| !p.is_empty()),
+            any_prefix: any.iter().map(|p| p.to_string()).collect(),
+            fixed_len: 1,
+            ..Pattern::default()
+        };
+        let p2pkh = "1BgGZ9tcN4rm9KBzDn7KprQz87SZ26SAMH";
+        assert!(check_fixed_part(&pattern("1abc", &[]), p2pkh).is_ok());
+        assert!(check_fixed_part(&pattern("3abc", &[]), p2pkh).is_err());
+        assert!(check_fixed_part(&pattern("", &["3ab", "1ab"]), p2pkh).is_ok());
+        assert!(check_fixed_part(&pattern("", &["3ab", "bc1q"]), p2pkh).is_err());
+        // No shared leading characters (testnet P2PKH starts with `m` or `n`).
+        let loose = Pattern {
+            fixed_len: 0,
+            ..pattern("3abc", &[])
+        };
+        assert!(check_fixed_part(&loose, "mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn").is_ok());
+    }
+
+    #[test]
+    fn prefix_offset_matches_inside_the_data_part() {
+        let pattern = Pattern {
+            prefix: Some("cat".into()),
+            prefix_at: 4 + 2,
+            fixed_len: 4,
+            ..Pattern::default()
+        };
+        assert!(matches_pattern("bc1qxxcatxxxxx", &pattern));
+        assert!(!matches_pattern("bc1qcatxxxxxxx", &pattern));
+        assert!(!matches_pattern("bc1qxxca", &pattern));
+        assert_eq!(prefix_score("bc1qxxcaxx", &pattern), 2);
+        let sample = "bc1qxxxxxxxxxx";
+        let p = match_probability(&pattern, AddressFormat::Bech32, sample, None).unwrap();
+        assert!((p - 32f64.powi(-3)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn hash_prefixes_match_leading_nibbles() {
+        let hash = prepare_hash_prefix(Some("0x00aB1".into()), 20)
+            .unwrap()
+            .unwrap();
+        assert_eq!(hash.hex, "00ab1");
+        assert!(hash.matches(&[0x00, 0xab, 0x1f, 0x00]));
+        assert!(!hash.matches(&[0x00, 0xab, 0x2f, 0x00]));
+        assert!(!hash.matches(&[0x00, 0xab]));
+        assert!(matches!(
+            prepare_hash_prefix(Some("00xg".into()), 20),
+            Err(VanityError::InvalidPattern { .. })
+        ));
+        assert!(matches!(
+            prepare_hash_prefix(Some("0".repeat(41)), 20),
+            Err(VanityError::InfeasiblePattern(_))
+        ));
+        assert!(prepare_hash_prefix(Some("0".repeat(64)), 32).is_ok());
+        let pattern = Pattern {
+            hash_prefix: Some(hash),
+            ..Pattern::default()
+        };
+        assert!(!pattern.is_empty());
+        let p = match_probability(&pattern, AddressFormat::P2pkh, "1xxxx", None).unwrap();
+        assert!((p - 16f64.powi(-5)).abs() < 1e-15);
+    }
+}
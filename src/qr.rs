@@ -0,0 +1,119 @@
+//! QR codes for paper wallets: Unicode half-block art for the terminal and PNG files.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use image::Luma;
+use qrcode::{render::unicode, EcLevel, QrCode};
+
+// Pixels per module in PNG output; large enough to scan after printing.
+const PNG_MODULE_PX: u32 = 8;
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+#[value(rename_all = "kebab-case")]
+pub enum QrMode {
+    /// Print the codes to the terminal.
+    Terminal,
+    /// Write `<address>-<label>.png` files next to the output.
+    Png,
+    /// Both of the above.
+    Both,
+}
+
+impl QrMode {
+    pub fn terminal(self) -> bool {
+        matches!(self, QrMode::Terminal | QrMode::Both)
+    }
+
+    pub fn png(self) -> bool {
+        matches!(self, QrMode::Png | QrMode::Both)
+    }
+}
+
+fn encode(data: &str) -> Result<QrCode> {
+    // Medium error correction survives smudged paper without growing keys past version 4.
+    QrCode::with_error_correction_level(data.as_bytes(), EcLevel::M)
+        .with_context(|| format!("Unable to encode {} bytes as a QR code", data.len()))
+}
+
+/// `data` as rows of `█▀▄` characters, or `##` pairs with `ascii`; light modules are the
+/// filled cells, so it scans on dark terminal backgrounds.
+pub fn render_terminal(data: &str, ascii: bool) -> Result<String> {
+    let code = encode(data)?;
+    if ascii {
+        // Two characters per module keep the code roughly square in a monospace font.
+        return Ok(code
+            .render::<char>()
+            .dark_color(' ')
+            .light_color('#')
+            .module_dimensions(2, 1)
+            .quiet_zone(true)
+            .build());
+    }
+    Ok(code
+        .render::<unicode::Dense1x2>()
+        .dark_color(unicode::Dense1x2::Light)
+        .light_color(unicode::Dense1x2::Dark)
+        .quiet_zone(true)
+        .build())
+}
+
+/// Where the PNG for `label` ("address", "wif", ...) of `address` goes, beside `output`.
+pub fn png_path(output: &Path, address: &str, label: &str) -> PathBuf {
+    output
+        .parent()
+        .unwrap_or_else(|| Path::new(""))
+        .join(format!("{}-{}.png", address, label))
+}
+
+pub fn write_png(data: &str, path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create QR dir {}", parent.display()))?;
+    }
+    encode(data)?
+        .render::<Luma<u8>>()
+        .module_dimensions(PNG_MODULE_PX, PNG_MODULE_PX)
+        .build()
+        .save(path)
+        .with_context(|| format!("Failed to write QR code {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn png_files_sit_beside_the_output() {
+        assert_eq!(
+            png_path(Path::new("results/run.json"), "1Abc", "wif"),
+            PathBuf::from("results/1Abc-wif.png")
+        );
+        assert_eq!(
+            png_path(Path::new("run.json"), "1Abc", "address"),
+            PathBuf::from("1Abc-address.png")
+        );
+    }
+
+    #[test]
+    fn terminal_codes_are_square_block_art() {
+        let art = render_terminal("1BitcoinEaterAddressDontSendf59kuE", false).unwrap();
+        let rows: Vec<&str> = art.lines().collect();
+        let width = rows[0].chars().count();
+        // Dense1x2 packs two module rows per line.
+        assert!(rows.len() * 2 >= width && rows.len() * 2 <= width + 1);
+        assert!(rows.iter().all(|row| row.chars().count() == width));
+    }
+
+    #[test]
+    fn ascii_codes_avoid_unicode() {
+        let art = render_terminal("1BitcoinEaterAddressDontSendf59kuE", true).unwrap();
+        assert!(art.is_ascii());
+        let rows: Vec<&str> = art.lines().collect();
+        assert_eq!(rows[0].len(), rows.len() * 2);
+    }
+}
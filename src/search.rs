@@ -0,0 +1,1209 @@
+//! Multi-threaded search over the attempt space of a `KeyStream`.
+
+use std::{
+    any::Any,
+    cell::Cell,
+    collections::BTreeMap,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Mutex, MutexGuard, PoisonError,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context, Result};
+use rayon::ThreadPoolBuilder;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    address::{
+        address_payload, encode_payload, encode_public_address_with, encode_wif_with,
+        AddressFormat, EncodeScratch, Network, NetworkParams,
+    },
+    checkpoint::CheckpointWriter,
+    keys::{derive_candidates, CandidateKey, KeyMode, KeyStream, SequentialBatch},
+    pattern::{leading_run, matches_pattern, prefix_score, Pattern},
+};
+
+pub const DEFAULT_BATCH_SIZE: u64 = 2048;
+// Mnemonic attempts run about 100x slower, so 2048 of them would take a worker seconds.
+pub const DEFAULT_MNEMONIC_BATCH_SIZE: u64 = 16;
+// With `adaptive_batch`, workers start here and grow or shrink batches towards one taking
+// about `ADAPTIVE_BATCH_TARGET`: long enough that the scheduler is fetched ~100 times a
+// second per worker, short enough that little work is wasted after the final match.
+const ADAPTIVE_BATCH_START: u64 = 16;
+const ADAPTIVE_BATCH_TARGET: Duration = Duration::from_millis(10);
+// How often (in attempts) a worker polls the clock when a deadline is set.
+const DEADLINE_CHECK: u64 = 256;
+// `max_rate` is measured over windows this long, and batches are capped to about a tenth
+// of one so a throttled worker sleeps in short steps.
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+const RATE_SLEEP_STEP: Duration = Duration::from_millis(50);
+
+pub struct SearchConfig {
+    pub format: AddressFormat,
+    // Further formats each candidate is also encoded in and matched against (`--formats`);
+    // a candidate still yields at most one match. Near misses use `format` only.
+    pub extra_formats: Vec<ExtraFormat>,
+    pub witness_version: u8,
+    pub compressed: bool,
+    pub network: NetworkParams,
+    pub key_mode: KeyMode,
+    pub stream: KeyStream,
+    pub pattern: Pattern,
+    pub start_attempt: u64,
+    // Exclusive upper bound on attempt indices; `u64::MAX` means unbounded.
+    pub max_attempts: u64,
+    pub count: u64,
+    pub threads: usize,
+    // Attempts claimed from the shared scheduler per fetch. Larger batches mean less
+    // contention but up to `threads * batch_size` wasted attempts after the final match.
+    pub batch_size: u64,
+    // Size each worker's batches by their measured duration instead, up to `batch_size`.
+    pub adaptive_batch: bool,
+    // Keep this many non-matching candidates with the longest matched prefix (0 = off).
+    pub track_best: usize,
+    pub shard: Shard,
+    // Log every Nth candidate of worker 0 under `SAMPLE_LOG_TARGET` (0 = off).
+    pub sample_every: u64,
+    // Pin worker N to the Nth CPU the process may run on (Linux only; ignored elsewhere).
+    pub pin_threads: bool,
+    // Cooperative cap on attempts per second across all workers (0 = unlimited).
+    pub max_rate: u64,
+}
+
+/// A further address format matched in the same pass, with `pattern` prepared for its
+/// charset and fixed leading part. Shares the config's witness version.
+pub struct ExtraFormat {
+    pub format: AddressFormat,
+    pub pattern: Pattern,
+}
+
+/// Log target of `--sample-every` lines (info level).
+pub const SAMPLE_LOG_TARGET: &str = "vanity::sample";
+
+/// One lane of a search split across machines: only attempts with
+/// `attempt % count == index` are tested. All shards share the key stream and config
+/// fingerprint, so together they cover the attempt space exactly once.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Shard {
+    pub index: u64,
+    pub count: u64,
+}
+
+impl Default for Shard {
+    fn default() -> Self {
+        Self { index: 0, count: 1 }
+    }
+}
+
+impl Shard {
+    pub fn new(index: u64, count: u64) -> Result<Self> {
+        if count == 0 {
+            return Err(anyhow!("--shard-count must be at least 1"));
+        }
+        if index >= count {
+            return Err(anyhow!(
+                "--shard-index {} is out of range for --shard-count {}",
+                index,
+                count
+            ));
+        }
+        Ok(Self { index, count })
+    }
+
+    pub fn is_sharded(&self) -> bool {
+        self.count > 1
+    }
+
+    /// The attempt index of this shard's `lane`-th attempt.
+    pub fn attempt(&self, lane: u64) -> u64 {
+        lane.saturating_mul(self.count).saturating_add(self.index)
+    }
+
+    /// How many of this shard's attempts lie below attempt index `attempt`.
+    pub fn lanes_below(&self, attempt: u64) -> u64 {
+        attempt.saturating_sub(self.index).div_ceil(self.count)
+    }
+}
+
+// Shared with observers (stats thread, signal handler) while `search` runs. Sharded searches
+// count only their own attempts, so seed `attempts_done` with `Shard::lanes_below`.
+pub struct SearchProgress {
+    pub attempts_done: AtomicU64,
+    pub matches_found: AtomicU64,
+    // Attempts of this session whose key material derivation rejected (a secret of zero or
+    // past the curve order, an invalid BIP-32 step). Still counted in `attempts_done`.
+    pub discarded: AtomicU64,
+    pub stop: AtomicBool,
+}
+
+impl SearchProgress {
+    pub fn new(start_attempt: u64) -> Self {
+        Self {
+            attempts_done: AtomicU64::new(start_attempt),
+            matches_found: AtomicU64::new(0),
+            discarded: AtomicU64::new(0),
+            stop: AtomicBool::new(false),
+        }
+    }
+}
+
+pub struct SearchMatch {
+    pub candidate: CandidateKey,
+    pub address: String,
+    // The format `address` is in, which with `extra_formats` may not be the config's.
+    pub format: AddressFormat,
+    // 1-based: the match came from attempt index `attempts - 1`.
+    pub attempts: u64,
+    // Why the `search` sink failed to store this match, if it did.
+    pub save_error: Option<anyhow::Error>,
+}
+
+/// Called by `search` for each match before its batch completes, so no checkpoint moves
+/// past a match until this has stored it. Calls are serialized; an error stops the search
+/// with that match's attempt left untested.
+pub type MatchSink<'a> = dyn Fn(&SearchMatch) -> Result<()> + Sync + 'a;
+
+pub struct SearchOutcome {
+    // Sorted by attempt.
+    pub matches: Vec<SearchMatch>,
+    // Lanes tested, ending at the last match when the search found all `count`.
+    pub attempts_made: u64,
+    // This session's `SearchProgress::discarded`.
+    pub discarded: u64,
+    // Every attempt below this was tested; batches stopped early can leave holes above it,
+    // so this (not `attempts_made`) is the safe resume point.
+    pub next_attempt: u64,
+    // Up to `track_best` near misses, longest matched prefix first.
+    pub best: Vec<SearchMatch>,
+    // `[start, end)` attempt ranges (lane bounds mapped through the shard) whose batch a
+    // panicked worker abandoned; `next_attempt` stops at the first.
+    pub untested: Vec<(u64, u64)>,
+}
+
+// Everything workers report, behind one mutex so near misses and matches never race.
+#[derive(Default)]
+struct Found {
+    matches: Vec<SearchMatch>,
+    best: Vec<(usize, SearchMatch)>,
+}
+
+impl Found {
+    // Keeps the `limit` highest scores (earliest attempt first on ties); returns the score a
+    // newcomer must beat once the reservoir is full.
+    fn offer_near_miss(&mut self, score: usize, hit: SearchMatch, limit: usize) -> usize {
+        self.best.push((score, hit));
+        self.best
+            .sort_by(|a, b| b.0.cmp(&a.0).then(a.1.attempts.cmp(&b.1.attempts)));
+        self.best.truncate(limit);
+        if self.best.len() < limit {
+            0
+        } else {
+            self.best.last().map_or(0, |(score, _)| *score)
+        }
+    }
+}
+
+// Merges completed `[start, end)` ranges into the highest contiguous tested attempt.
+struct Watermark {
+    next: u64,
+    pending: BTreeMap<u64, u64>,
+    // Ranges a panicked worker left untested. They still merge, so `pending` keeps
+    // draining, but the safe resume point never passes the first.
+    abandoned: Vec<(u64, u64)>,
+}
+
+impl Watermark {
+    fn new(start: u64) -> Self {
+        Self {
+            next: start,
+            pending: BTreeMap::new(),
+            abandoned: Vec::new(),
+        }
+    }
+
+    fn complete(&mut self, start: u64, end: u64) -> u64 {
+        if start != end {
+            self.pending.insert(start, end);
+            while let Some(end) = self.pending.remove(&self.next) {
+                self.next = end;
+            }
+        }
+        self.resume_point()
+    }
+
+    // Marks `[start, end)` as given up on; returns the new resume point.
+    fn abandon(&mut self, start: u64, end: u64) -> u64 {
+        self.abandoned.push((start, end));
+        self.complete(start, end)
+    }
+
+    fn resume_point(&self) -> u64 {
+        let hole = self.abandoned.iter().map(|&(start, _)| start).min();
+        hole.map_or(self.next, |hole| hole.min(self.next))
+    }
+}
+
+/// Workers stop at `deadline` as if the attempt budget ran out; the outcome's
+/// `next_attempt` then resumes where they left off.
+pub fn search(
+    config: &SearchConfig,
+    progress: &SearchProgress,
+    checkpoint: Option<&CheckpointWriter>,
+    on_match: Option<&MatchSink>,
+    deadline: Option<Instant>,
+) -> Result<SearchOutcome> {
+    run(config, progress, checkpoint, on_match, false, deadline)
+}
+
+/// Derive and encode addresses for `duration` without pattern matching; returns the number
+/// of attempts completed and the time actually spent. Uses the same pool and hot path as
+/// `search`.
+pub fn benchmark(config: &SearchConfig, duration: Duration) -> Result<(u64, Duration)> {
+    let progress = SearchProgress::new(config.start_attempt);
+    let start = Instant::now();
+    let outcome = run(config, &progress, None, None, true, Some(start + duration))?;
+    Ok((
+        outcome.attempts_made - config.start_attempt,
+        start.elapsed(),
+    ))
+}
+
+fn run(
+    config: &SearchConfig,
+    progress: &SearchProgress,
+    checkpoint: Option<&CheckpointWriter>,
+    on_match: Option<&MatchSink>,
+    benchmarking: bool,
+    deadline: Option<Instant>,
+) -> Result<SearchOutcome> {
+    let network = &config.network;
+    let max_attempts = config.max_attempts;
+    let threads = config.threads.max(1) as u64;
+    let max_batch = match config.max_rate {
+        0 => config.batch_size.max(1),
+        rate => config.batch_size.clamp(1, (rate / threads / 10).max(1)),
+    };
+    let throttle = (config.max_rate != 0).then(|| {
+        Throttle::new(
+            config.max_rate,
+            Instant::now(),
+            progress.attempts_done.load(Ordering::Relaxed),
+        )
+    });
+    // The scheduler and watermark hand out lanes; `shard.attempt` maps them to attempt indices.
+    let shard = config.shard;
+    let start_lane = shard.lanes_below(config.start_attempt);
+    let end_lane = shard.lanes_below(max_attempts);
+    let scheduler = AtomicU64::new(start_lane);
+    let result = Mutex::new(Found::default());
+    let watermark = Mutex::new(Watermark::new(start_lane));
+    // With a leading-run pattern each further match must beat the best run so far.
+    let best_run = AtomicUsize::new(0);
+    // Lock-free pre-check for --track-best: only scores above this take the lock.
+    let near_miss_floor = AtomicUsize::new(0);
+
+    let mut pool = ThreadPoolBuilder::new().num_threads(config.threads.max(1));
+    if config.pin_threads {
+        match allowed_cpus() {
+            Some(cpus) if !cpus.is_empty() => {
+                pool = pool.start_handler(move |index| {
+                    let cpu = cpus[index % cpus.len()];
+                    if !pin_current_thread(cpu) {
+                        log::warn!("Failed to pin search worker {} to CPU {}", index, cpu);
+                    }
+                });
+            }
+            _ => log::warn!("--pin-threads is not supported on this platform; ignoring it"),
+        }
+    }
+    let pool = pool.build().context("Failed to build rayon thread pool")?;
+
+    pool.install(|| {
+        rayon::scope(|s| {
+            for worker in 0..config.threads.max(1) {
+                let scheduler = &scheduler;
+                let result = &result;
+                let watermark = &watermark;
+                let best_run = &best_run;
+                let near_miss_floor = &near_miss_floor;
+                let network = &network;
+                let throttle = &throttle;
+
+                // The batch in hand, so a panic can hand its range back to the watermark.
+                let work = move |batch: &Cell<Option<(u64, u64)>>| {
+                    let mut stop = false;
+                    let sample_every = if worker == 0 { config.sample_every } else { 0 };
+                    let mut sampled = 0u64;
+                    let mut address_buf = String::with_capacity(40);
+                    let mut wif_buf = String::with_capacity(52);
+                    let mut payload = [0u8; 32];
+                    let mut candidates = Vec::new();
+                    let mut scratch = EncodeScratch::default();
+                    let mut batch_size = if config.adaptive_batch {
+                        ADAPTIVE_BATCH_START.min(max_batch)
+                    } else {
+                        max_batch
+                    };
+                    let mut sequential = match config.key_mode {
+                        KeyMode::Sequential => {
+                            SequentialBatch::with_stride(&config.stream, shard.count)
+                        }
+                        _ => None,
+                    };
+
+                    while !stop {
+                        if progress.stop.load(Ordering::Acquire) {
+                            break;
+                        }
+                        if deadline.is_some_and(|d| Instant::now() >= d) {
+                            break;
+                        }
+
+                        let start = scheduler.fetch_add(batch_size, Ordering::Relaxed);
+                        if start >= end_lane {
+                            break;
+                        }
+
+                        let end = start.saturating_add(batch_size).min(end_lane);
+                        batch.set(Some((start, end)));
+                        let mut processed = 0u64;
+                        let mut discarded = 0u64;
+                        let batch_started = config.adaptive_batch.then(Instant::now);
+
+                        'attempts: for lane in start..end {
+                            let attempt = shard.attempt(lane);
+                            if progress.stop.load(Ordering::Acquire) {
+                                stop = true;
+                                break;
+                            }
+                            if lane % DEADLINE_CHECK == 0
+                                && deadline.is_some_and(|d| Instant::now() >= d)
+                            {
+                                stop = true;
+                                break;
+                            }
+
+                            processed += 1;
+
+                            match sequential.as_mut() {
+                                Some(batch) => batch.candidates(attempt, &mut candidates),
+                                None => derive_candidates(
+                                    &config.stream,
+                                    attempt,
+                                    &config.key_mode,
+                                    &mut candidates,
+                                ),
+                            }
+                            if candidates.is_empty() {
+                                discarded += 1;
+                            }
+                            for candidate in candidates.drain(..) {
+                                let targets = std::iter::once((config.format, &config.pattern))
+                                    .chain(
+                                        config
+                                            .extra_formats
+                                            .iter()
+                                            .map(|extra| (extra.format, &extra.pattern)),
+                                    );
+                                for (format, pattern) in targets {
+                                    let target: &str = if pattern.match_wif {
+                                        encode_wif_with(
+                                            &candidate.secret,
+                                            network,
+                                            config.compressed,
+                                            &mut scratch,
+                                            &mut wif_buf,
+                                        );
+                                        &wif_buf
+                                    } else {
+                                        let Ok(len) = address_payload(
+                                            &candidate.public,
+                                            format,
+                                            config.witness_version,
+                                            config.compressed,
+                                            &mut payload,
+                                        ) else {
+                                            continue;
+                                        };
+                                        let payload = &payload[..len];
+                                        if !benchmarking
+                                            && pattern
+                                                .hash_prefix
+                                                .as_ref()
+                                                .is_some_and(|hash| !hash.matches(payload))
+                                        {
+                                            continue;
+                                        }
+                                        if encode_payload(
+                                            payload,
+                                            format,
+                                            config.witness_version,
+                                            network,
+                                            &mut scratch,
+                                            &mut address_buf,
+                                        )
+                                        .is_err()
+                                        {
+                                            continue;
+                                        }
+                                        &address_buf
+                                    };
+
+                                    if sample_every != 0 {
+                                        sampled += 1;
+                                        if sampled.is_multiple_of(sample_every) {
+                                            log::info!(
+                                                target: SAMPLE_LOG_TARGET,
+                                                "attempt {} {}",
+                                                attempt,
+                                                target
+                                            );
+                                        }
+                                    }
+                                    if benchmarking {
+                                        continue;
+                                    }
+                                    if !matches_pattern(target, pattern) {
+                                        if config.track_best == 0 || format != config.format {
+                                            continue;
+                                        }
+                                        let score = prefix_score(target, pattern);
+                                        if score > near_miss_floor.load(Ordering::Relaxed) {
+                                            let Some(address) = hit_address(
+                                                config,
+                                                network,
+                                                &candidate,
+                                                target,
+                                                &mut scratch,
+                                            ) else {
+                                                continue;
+                                            };
+                                            let floor = lock_or_recover(result).offer_near_miss(
+                                                score,
+                                                SearchMatch {
+                                                    candidate: candidate.clone(),
+                                                    address,
+                                                    format,
+                                                    attempts: attempt + 1,
+                                                    save_error: None,
+                                                },
+                                                config.track_best,
+                                            );
+                                            near_miss_floor.store(floor, Ordering::Relaxed);
+                                        }
+                                        continue;
+                                    }
+                                    let mut found = lock_or_recover(result);
+                                    let guard = &mut found.matches;
+                                    if let Some(run) = pattern.leading {
+                                        let len = leading_run(target, pattern.fixed_len, run.char);
+                                        // Checked under the lock so improvements are strictly ordered.
+                                        if len <= best_run.load(Ordering::Relaxed) {
+                                            continue;
+                                        }
+                                        best_run.store(len, Ordering::Relaxed);
+                                    }
+                                    if (guard.len() as u64) < config.count {
+                                        let Some(address) = hit_address(
+                                            config,
+                                            network,
+                                            &candidate,
+                                            target,
+                                            &mut scratch,
+                                        ) else {
+                                            continue;
+                                        };
+                                        guard.push(SearchMatch {
+                                            candidate,
+                                            address,
+                                            format,
+                                            attempts: attempt + 1,
+                                            save_error: None,
+                                        });
+                                        // Under the lock, which keeps the sink's writes in order.
+                                        if let (Some(save), Some(hit)) =
+                                            (on_match, guard.last_mut())
+                                        {
+                                            hit.save_error = save(hit).err();
+                                            if hit.save_error.is_some() {
+                                                processed -= 1;
+                                                progress.stop.store(true, Ordering::Release);
+                                                stop = true;
+                                                break 'attempts;
+                                            }
+                                        }
+                                        progress
+                                            .matches_found
+                                            .store(guard.len() as u64, Ordering::Relaxed);
+                                    }
+                                    if guard.len() as u64 >= config.count {
+                                        progress.stop.store(true, Ordering::Release);
+                                        stop = true;
+                                        break 'attempts;
+                                    }
+                                    // One match per candidate, whichever format hit first.
+                                    break;
+                                }
+                            }
+                        }
+
+                        if let Some(started) = batch_started.filter(|_| processed == batch_size) {
+                            batch_size = adapt_batch(batch_size, started.elapsed(), max_batch);
+                        }
+                        let done = lock_or_recover(watermark).complete(start, start + processed);
+                        batch.set(None);
+                        if discarded != 0 {
+                            progress.discarded.fetch_add(discarded, Ordering::Relaxed);
+                        }
+                        if processed != 0 {
+                            progress
+                                .attempts_done
+                                .fetch_add(processed, Ordering::Relaxed);
+                            // Checkpoint the contiguous watermark, not the raw count: lanes
+                            // below the count may still sit in another worker's batch.
+                            if let Some(writer) = checkpoint {
+                                let next = shard.attempt(done).min(max_attempts);
+                                // A resumed search finds the matches above it again.
+                                writer.maybe_write(next, || {
+                                    lock_or_recover(result)
+                                        .matches
+                                        .iter()
+                                        .filter(|m| m.attempts <= next && m.save_error.is_none())
+                                        .count() as u64
+                                });
+                            }
+                        }
+                        if let Some(throttle) = throttle {
+                            let done = progress.attempts_done.load(Ordering::Relaxed);
+                            let mut pause = throttle.pause(done, Instant::now());
+                            // Sleep in steps so a stop or deadline still lands promptly.
+                            while !pause.is_zero()
+                                && !progress.stop.load(Ordering::Acquire)
+                                && deadline.is_none_or(|d| Instant::now() < d)
+                            {
+                                let step = pause.min(RATE_SLEEP_STEP);
+                                std::thread::sleep(step);
+                                pause -= step;
+                            }
+                        }
+                    }
+                };
+                // A panicking worker abandons its batch: checkpoints stay below it from then
+                // on, and the outcome reports it; the other workers carry on.
+                s.spawn(move |_| {
+                    let batch = Cell::new(None);
+                    let Err(panic) = panic::catch_unwind(AssertUnwindSafe(|| work(&batch))) else {
+                        return;
+                    };
+                    log::error!(
+                        "Search worker {} panicked ({}); continuing without it",
+                        worker,
+                        panic_message(&panic)
+                    );
+                    if let Some((start, end)) = batch.get() {
+                        let resume = lock_or_recover(watermark).abandon(start, end);
+                        log::error!(
+                            "Attempts {}..{} were left untested; checkpoints will not advance \
+                             past attempt {}",
+                            shard.attempt(start),
+                            shard.attempt(end).min(max_attempts),
+                            shard.attempt(resume).min(max_attempts)
+                        );
+                    }
+                });
+            }
+        });
+    });
+
+    let found = result.into_inner().unwrap_or_else(PoisonError::into_inner);
+    let mut matches = found.matches;
+    matches.sort_by_key(|m| m.attempts);
+    let watermark = watermark
+        .into_inner()
+        .unwrap_or_else(PoisonError::into_inner);
+    let next_attempt = shard.attempt(watermark.resume_point());
+    let untested = watermark
+        .abandoned
+        .iter()
+        .map(|&(start, end)| {
+            (
+                shard.attempt(start).min(max_attempts),
+                shard.attempt(end).min(max_attempts),
+            )
+        })
+        .collect();
+    // Workers only see `stop` at their next attempt, so a search that found everything it
+    // wanted has usually tested a few attempts past the last match (and, with more threads,
+    // maybe not all below it). It reports the attempts up to that match, like the match does.
+    let mut attempts_made = progress.attempts_done.load(Ordering::Relaxed).min(end_lane);
+    if let Some(last) = matches
+        .last()
+        .filter(|_| matches.len() as u64 >= config.count)
+    {
+        attempts_made = shard.lanes_below(last.attempts);
+    }
+    Ok(SearchOutcome {
+        matches,
+        next_attempt: next_attempt.min(max_attempts),
+        best: found.best.into_iter().map(|(_, hit)| hit).collect(),
+        untested,
+        attempts_made,
+        discarded: progress.discarded.load(Ordering::Relaxed),
+    })
+}
+
+// Shared by the workers of a `max_rate` search: the start of the current window and the
+// attempt count when it opened.
+struct Throttle {
+    max_rate: u64,
+    window: Mutex<(Instant, u64)>,
+}
+
+impl Throttle {
+    fn new(max_rate: u64, now: Instant, attempts_done: u64) -> Self {
+        Self {
+            max_rate,
+            window: Mutex::new((now, attempts_done)),
+        }
+    }
+
+    // How long to sleep so that the attempts done in this window (`attempts_done` is the
+    // running total) stay within `max_rate`. Once the window is over the next one opens
+    // when that sleep ends, so other workers keep paying off the excess meanwhile.
+    fn pause(&self, attempts_done: u64, now: Instant) -> Duration {
+        let mut window = lock_or_recover(&self.window);
+        let (opened, base) = &mut *window;
+        let due = Duration::from_secs_f64(
+            attempts_done.saturating_sub(*base) as f64 / self.max_rate as f64,
+        );
+        let elapsed = now.saturating_duration_since(*opened);
+        let pause = due.saturating_sub(elapsed);
+        if elapsed >= RATE_WINDOW {
+            *opened = now + pause;
+            *base = attempts_done;
+        }
+        pause
+    }
+}
+
+// Double a batch that finished well under the target, halve one that ran well over it.
+fn adapt_batch(size: u64, elapsed: Duration, max: u64) -> u64 {
+    if elapsed < ADAPTIVE_BATCH_TARGET / 2 {
+        size.saturating_mul(2).min(max)
+    } else if elapsed > ADAPTIVE_BATCH_TARGET * 2 {
+        (size / 2).max(1)
+    } else {
+        size
+    }
+}
+
+// The address to report for a hit; `target` already is it unless the pattern ran on the WIF.
+fn hit_address(
+    config: &SearchConfig,
+    network: &NetworkParams,
+    candidate: &CandidateKey,
+    target: &str,
+    scratch: &mut EncodeScratch,
+) -> Option<String> {
+    if !config.pattern.match_wif {
+        return Some(target.to_string());
+    }
+    let mut address = String::with_capacity(64);
+    encode_public_address_with(
+        &candidate.public,
+        config.format,
+        config.witness_version,
+        config.compressed,
+        network,
+        scratch,
+        &mut address,
+    )
+    .ok()?;
+    Some(address)
+}
+
+/// CPUs in the calling thread's affinity mask, in ascending order; `None` where thread
+/// affinity isn't supported.
+#[cfg(target_os = "linux")]
+pub fn allowed_cpus() -> Option<Vec<usize>> {
+    // SAFETY: `cpu_set_t` is plain data, and the kernel writes at most `size_of` bytes.
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        if libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &mut set) != 0 {
+            return None;
+        }
+        Some(
+            (0..libc::CPU_SETSIZE as usize)
+                .filter(|&cpu| libc::CPU_ISSET(cpu, &set))
+                .collect(),
+        )
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn allowed_cpus() -> Option<Vec<usize>> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn pin_current_thread(cpu: usize) -> bool {
+    // SAFETY: as in `allowed_cpus`; `CPU_SET` only touches the set it is given.
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(cpu, &mut set);
+        libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) == 0
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn pin_current_thread(_cpu: usize) -> bool {
+    false
+}
+
+/// Lock `mutex`, taking over the data if a panicking thread poisoned it. Every guarded
+/// update here is a single step, so the data is consistent even then.
+pub fn lock_or_recover<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| {
+        log::warn!("Recovered a mutex poisoned by a panicked thread");
+        mutex.clear_poison();
+        poisoned.into_inner()
+    })
+}
+
+fn panic_message(panic: &Box<dyn Any + Send>) -> &str {
+    panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("non-string payload")
+}
+
+/// Hash of everything that decides which keys a search tests and which of them match;
+/// `--resume` only accepts a checkpoint with the same hash. Options added after the first
+/// release are only tagged when they differ from their old default (scanning, extra
+/// formats, uncompressed keys, permuted order...), so existing checkpoints keep their hash.
+pub fn config_fingerprint(
+    stream: &KeyStream,
+    pattern: &Pattern,
+    mode: &KeyMode,
+    // The search's format, then any `extra_formats`.
+    formats: &[AddressFormat],
+    witness_version: u8,
+    compressed: bool,
+    network: &NetworkParams,
+) -> [u8; 32] {
+    let mut data = Vec::new();
+    data.extend_from_slice(&stream.root);
+    if let Some(p) = &pattern.prefix {
+        data.extend_from_slice(p.as_bytes());
+        data.push(0xff);
+    }
+    if let Some(s) = &pattern.suffix {
+        data.extend_from_slice(s.as_bytes());
+        data.push(0x01);
+    }
+    for p in &pattern.any_prefix {
+        data.extend_from_slice(p.as_bytes());
+        data.push(0x0c);
+    }
+    for s in &pattern.any_suffix {
+        data.extend_from_slice(s.as_bytes());
+        data.push(0x0d);
+    }
+    for c in &pattern.contains {
+        data.extend_from_slice(c.as_bytes());
+        data.push(0x03);
+    }
+    if let Some(re) = &pattern.regex {
+        data.extend_from_slice(re.as_str().as_bytes());
+        data.push(0x04);
+    }
+    if let Some(run) = pattern.leading {
+        data.extend_from_slice(run.char.to_string().as_bytes());
+        data.extend_from_slice(&(run.min as u64).to_le_bytes());
+        data.push(0x05);
+    }
+    if let Some(list) = &pattern.wordlist {
+        for word in &list.words {
+            data.extend_from_slice(word.as_bytes());
+            data.push(0x06);
+        }
+        data.push(if list.contains { 0x08 } else { 0x07 });
+    }
+    if pattern.ignore_case {
+        data.push(0x09);
+    }
+    if pattern.prefix_at > 0 {
+        data.extend_from_slice(&(pattern.prefix_at as u64).to_le_bytes());
+        data.push(0x0b);
+    }
+    for e in &pattern.exclude {
+        data.extend_from_slice(e.as_bytes());
+        data.push(0x0a);
+    }
+    if let Some(hash) = &pattern.hash_prefix {
+        data.extend_from_slice(hash.hex.as_bytes());
+        data.push(0x0e);
+    }
+    match mode {
+        KeyMode::Raw => data.push(0x10),
+        KeyMode::Mnemonic {
+            path_string,
+            words,
+            passphrase,
+            scan_indices,
+            ..
+        } => {
+            data.push(0x22);
+            data.extend_from_slice(path_string.as_bytes());
+            data.push(*words as u8);
+            data.extend_from_slice(&Sha256::digest(passphrase.as_bytes()));
+            if *scan_indices > 1 {
+                data.push(0x23);
+                data.extend_from_slice(&scan_indices.to_le_bytes());
+            }
+        }
+        KeyMode::SplitKey { base } => {
+            data.push(0x50);
+            data.extend_from_slice(&base.serialize());
+        }
+        KeyMode::Sequential => data.push(0x60),
+        KeyMode::FixedMnemonic {
+            phrase,
+            path_string,
+            passphrase,
+            ..
+        } => {
+            data.push(0x26);
+            data.extend_from_slice(&Sha256::digest(phrase.as_bytes()));
+            data.extend_from_slice(path_string.as_bytes());
+            data.extend_from_slice(&Sha256::digest(passphrase.as_bytes()));
+        }
+    }
+    let format_tag = |format: AddressFormat, data: &mut Vec<u8>| match format {
+        AddressFormat::P2pkh => data.push(0x01),
+        AddressFormat::P2shP2wpkh => data.push(0x03),
+        AddressFormat::Bech32 => {
+            data.push(0x02);
+            data.push(witness_version);
+        }
+        AddressFormat::P2wsh => data.push(0x04),
+    };
+    let (&format, extra_formats) = formats.split_first().expect("at least one format");
+    format_tag(format, &mut data);
+    for &extra in extra_formats {
+        data.push(0x24);
+        format_tag(extra, &mut data);
+    }
+    if !compressed {
+        data.push(0x40);
+    }
+    match network.preset() {
+        Some(Network::Mainnet) => data.push(0x30),
+        Some(Network::Testnet) => data.push(0x31),
+        Some(Network::Regtest) => data.push(0x32),
+        None => {
+            data.push(0x33);
+            data.extend_from_slice(&[
+                network.p2pkh_version,
+                network.p2sh_version,
+                network.wif_version,
+            ]);
+            data.extend_from_slice(network.hrp.as_bytes());
+        }
+    }
+    if pattern.match_wif {
+        data.push(0x70);
+    }
+    if stream.permute {
+        data.push(0x71);
+    }
+    let mut hasher = Sha256::new();
+    hasher.update(&data);
+    let first = hasher.finalize();
+    let second = Sha256::digest(first);
+    let mut out = [0u8; 32];
+    out.copy_from_slice(&second);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extra_formats_are_matched_in_the_same_pass() {
+        let never = Pattern {
+            prefix: Some("1".repeat(34)),
+            fixed_len: 1,
+            ..Pattern::default()
+        };
+        let always = |prefix: &str| Pattern {
+            prefix: Some(prefix.into()),
+            fixed_len: prefix.len(),
+            ..Pattern::default()
+        };
+        let config = SearchConfig {
+            format: AddressFormat::P2pkh,
+            extra_formats: vec![
+                ExtraFormat {
+                    format: AddressFormat::Bech32,
+                    pattern: always("bc1q"),
+                },
+                ExtraFormat {
+                    format: AddressFormat::P2shP2wpkh,
+                    pattern: always("3"),
+                },
+            ],
+            witness_version: 0,
+            compressed: true,
+            network: Network::Mainnet.params(),
+            key_mode: KeyMode::Raw,
+            stream: KeyStream::from_seed(1),
+            pattern: never.clone(),
+            start_attempt: 0,
+            max_attempts: 100,
+            count: 3,
+            threads: 1,
+            batch_size: 8,
+            adaptive_batch: false,
+            track_best: 0,
+            shard: Shard::default(),
+            sample_every: 0,
+            pin_threads: false,
+            max_rate: 0,
+        };
+        let outcome = search(&config, &SearchProgress::new(0), None, None, None).unwrap();
+        // Each candidate counts once, in the first format that hit.
+        let attempts: Vec<u64> = outcome.matches.iter().map(|m| m.attempts).collect();
+        assert_eq!(attempts, [1, 2, 3]);
+        for m in &outcome.matches {
+            assert_eq!(m.format, AddressFormat::Bech32);
+            assert!(m.address.starts_with("bc1q"));
+        }
+
+        let fingerprint = |formats: &[AddressFormat]| {
+            let network = Network::Mainnet.params();
+            config_fingerprint(
+                &config.stream,
+                &never,
+                &KeyMode::Raw,
+                formats,
+                0,
+                true,
+                &network,
+            )
+        };
+        let p2pkh = AddressFormat::P2pkh;
+        assert_ne!(
+            fingerprint(&[p2pkh]),
+            fingerprint(&[p2pkh, AddressFormat::Bech32])
+        );
+    }
+
+    #[test]
+    fn attempts_without_a_key_are_counted_as_discarded() {
+        // 13 words is no valid entropy length, so every attempt's mnemonic is rejected.
+        let key_mode = KeyMode::Mnemonic {
+            path: "m/84'/0'/0'/0/0".parse().unwrap(),
+            path_string: "m/84'/0'/0'/0/0".into(),
+            words: 13,
+            passphrase: String::new(),
+            scan_indices: 1,
+        };
+        let config = SearchConfig {
+            format: AddressFormat::Bech32,
+            extra_formats: Vec::new(),
+            witness_version: 0,
+            compressed: true,
+            network: Network::Mainnet.params(),
+            key_mode,
+            stream: KeyStream::from_seed(1),
+            pattern: Pattern {
+                prefix: Some("bc1q".into()),
+                fixed_len: 4,
+                ..Pattern::default()
+            },
+            start_attempt: 0,
+            max_attempts: 20,
+            count: 1,
+            threads: 2,
+            batch_size: 4,
+            adaptive_batch: false,
+            track_best: 0,
+            shard: Shard::default(),
+            sample_every: 0,
+            pin_threads: false,
+            max_rate: 0,
+        };
+        let progress = SearchProgress::new(0);
+        let outcome = search(&config, &progress, None, None, None).unwrap();
+        assert!(outcome.matches.is_empty());
+        assert_eq!((outcome.attempts_made, outcome.discarded), (20, 20));
+        assert_eq!(progress.discarded.load(Ordering::Relaxed), 20);
+    }
+
+    #[test]
+    fn finished_searches_count_attempts_up_to_the_last_match() {
+        let config = SearchConfig {
+            format: AddressFormat::P2pkh,
+            extra_formats: Vec::new(),
+            witness_version: 0,
+            compressed: true,
+            network: Network::Mainnet.params(),
+            key_mode: KeyMode::Raw,
+            stream: KeyStream::from_seed(1),
+            pattern: Pattern {
+                prefix: Some("1a".into()),
+                fixed_len: 1,
+                ..Pattern::default()
+            },
+            start_attempt: 0,
+            max_attempts: u64::MAX,
+            count: 2,
+            threads: 4,
+            batch_size: 100_000,
+            adaptive_batch: false,
+            track_best: 0,
+            shard: Shard::default(),
+            sample_every: 0,
+            pin_threads: false,
+            max_rate: 0,
+        };
+        let outcome = search(&config, &SearchProgress::new(0), None, None, None).unwrap();
+        assert_eq!(outcome.matches.len(), 2);
+        // The other workers' batches ran concurrently; only the winner's range counts.
+        assert_eq!(outcome.attempts_made, outcome.matches[1].attempts);
+    }
+
+    #[test]
+    fn a_match_that_could_not_be_saved_stays_above_the_resume_point() {
+        let config = SearchConfig {
+            format: AddressFormat::P2pkh,
+            extra_formats: Vec::new(),
+            witness_version: 0,
+            compressed: true,
+            network: Network::Mainnet.params(),
+            key_mode: KeyMode::Raw,
+            stream: KeyStream::from_seed(1),
+            pattern: Pattern {
+                prefix: Some("1a".into()),
+                fixed_len: 1,
+                ..Pattern::default()
+            },
+            start_attempt: 0,
+            max_attempts: u64::MAX,
+            count: 3,
+            threads: 1,
+            batch_size: 4096,
+            adaptive_batch: false,
+            track_best: 0,
+            shard: Shard::default(),
+            sample_every: 0,
+            pin_threads: false,
+            max_rate: 0,
+        };
+        let saves = AtomicUsize::new(0);
+        let save = |_: &SearchMatch| match saves.fetch_add(1, Ordering::Relaxed) {
+            0 => Ok(()),
+            _ => Err(anyhow::anyhow!("disk full")),
+        };
+        let outcome = search(&config, &SearchProgress::new(0), None, Some(&save), None).unwrap();
+        // The failure stops the search, so the third match is never looked for.
+        assert_eq!(outcome.matches.len(), 2);
+        assert!(outcome.matches[0].save_error.is_none());
+        let unsaved = &outcome.matches[1];
+        assert!(unsaved.save_error.is_some());
+        assert_eq!(outcome.next_attempt, unsaved.attempts - 1);
+    }
+
+    #[test]
+    fn watermark_only_advances_over_contiguous_ranges() {
+        let mut mark = Watermark::new(100);
+        assert_eq!(mark.complete(200, 300), 100);
+        assert_eq!(mark.complete(150, 150), 100);
+        assert_eq!(mark.complete(100, 150), 150);
+        assert_eq!(mark.complete(150, 200), 300);
+    }
+
+    #[test]
+    fn an_abandoned_range_holds_the_resume_point_but_still_drains() {
+        let mut mark = Watermark::new(0);
+        assert_eq!(mark.complete(100, 200), 0);
+        assert_eq!(mark.abandon(0, 100), 0);
+        assert_eq!(mark.complete(200, 300), 0);
+        assert_eq!(mark.next, 300);
+        assert!(mark.pending.is_empty());
+        let mut mark = Watermark::new(0);
+        mark.complete(0, 100);
+        assert_eq!(mark.abandon(100, 150), 100);
+        assert_eq!(mark.complete(150, 400), 100);
+        assert!(mark.pending.is_empty());
+    }
+
+    #[test]
+    fn shards_partition_the_attempt_space() {
+        let shards: Vec<_> = (0..3).map(|i| Shard::new(i, 3).unwrap()).collect();
+        let mut seen: Vec<u64> = shards
+            .iter()
+            .flat_map(|shard| (0..shard.lanes_below(10)).map(|lane| shard.attempt(lane)))
+            .collect();
+        seen.sort_unstable();
+        assert_eq!(seen, (0..10).collect::<Vec<_>>());
+        // A resumed shard picks up at its next unseen lane.
+        assert_eq!(shards[1].lanes_below(shards[1].attempt(4)), 4);
+        assert_eq!(Shard::default().lanes_below(7), 7);
+        assert!(Shard::new(3, 3).is_err());
+    }
+
+    #[test]
+    fn throttle_pauses_workers_that_run_ahead_of_the_cap() {
+        let start = Instant::now();
+        let throttle = Throttle::new(1000, start, 500);
+        // 500 resumed attempts don't count; 250 more in 100 ms is 150 ms ahead.
+        let pause = throttle.pause(750, start + Duration::from_millis(100));
+        assert_eq!(pause, Duration::from_millis(150));
+        assert!(throttle
+            .pause(800, start + Duration::from_millis(800))
+            .is_zero());
+
+        // Over a window behind: the next one opens after the pause, so attempts made
+        // during it still owe their share.
+        let pause = throttle.pause(2700, start + Duration::from_secs(1));
+        assert_eq!(pause, Duration::from_millis(1200));
+        let reopened = start + Duration::from_millis(2200);
+        assert_eq!(
+            throttle.pause(2800, start + Duration::from_millis(1500)),
+            Duration::from_millis(100)
+        );
+        assert!(throttle
+            .pause(2800, reopened + Duration::from_millis(100))
+            .is_zero());
+    }
+
+    #[test]
+    fn adaptive_batches_converge_on_the_target_duration() {
+        let fast = Duration::from_millis(1);
+        let slow = Duration::from_millis(50);
+        assert_eq!(adapt_batch(16, fast, 2048), 32);
+        assert_eq!(adapt_batch(2048, fast, 2048), 2048);
+        assert_eq!(adapt_batch(16, slow, 2048), 8);
+        assert_eq!(adapt_batch(1, slow, 2048), 1);
+        assert_eq!(adapt_batch(64, ADAPTIVE_BATCH_TARGET, 2048), 64);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn pinned_threads_run_on_their_cpu() {
+        let cpus = allowed_cpus().unwrap();
+        assert!(!cpus.is_empty());
+        let cpu = *cpus.last().unwrap();
+        let pinned =
+            std::thread::spawn(move || pin_current_thread(cpu) && allowed_cpus().unwrap() == [cpu]);
+        assert!(pinned.join().unwrap());
+    }
+}
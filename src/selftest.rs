@@ -0,0 +1,420 @@
+//! Runtime smoke test of the key and encoding pipeline (`vanity_bitcoin selftest`): known
+//! vectors, then a few derived candidates per network, format and key mode, each checked
+//! against independent decoders. Meant for trusting a build on a new platform.
+
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context, Result};
+use bech32::{FromBase32, Variant};
+use bip32::DerivationPath;
+use ripemd::Ripemd160;
+use secp256k1::{PublicKey, SecretKey};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    address::{
+        decode_base58check, decode_wif, double_sha256, encode_address, encode_base58,
+        encode_public_address_with, taproot_output_key, wif_from_secret, AddressFormat,
+        EncodeScratch, Network, NetworkParams, SECP256K1,
+    },
+    keys::{
+        derive_candidate, hd_path_mismatch, mnemonic_xprv, CandidateKey, KeyMode, KeyStream,
+        SequentialBatch,
+    },
+};
+
+// Every format/witness version/compression combination the CLI accepts.
+const FORMATS: [(AddressFormat, u8, bool); 6] = [
+    (AddressFormat::P2pkh, 0, true),
+    (AddressFormat::P2pkh, 0, false),
+    (AddressFormat::P2shP2wpkh, 0, true),
+    (AddressFormat::Bech32, 0, true),
+    (AddressFormat::Bech32, 1, true),
+    (AddressFormat::P2wsh, 0, true),
+];
+
+const BIP39_TEST_PHRASE: &str =
+    "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+
+// Raw secret for `--seed 1`, attempt 0; pins the ChaCha20 key schedule.
+const SEED_ONE_ATTEMPT_ZERO: &str =
+    "4e4997c6b99979b41d6aadb68d5a217a4b15e0784fe7c6d93d4de3b84bcf6d57";
+
+/// One group of checks, e.g. `Testnet Bech32 v1 mnemonic`, with the first inconsistency.
+pub struct CaseReport {
+    pub label: String,
+    pub candidates: u64,
+    pub result: Result<()>,
+}
+
+/// Known vectors, then `samples` candidates from `stream` for every network, format and
+/// key mode.
+pub fn run(stream: &KeyStream, samples: u64) -> Vec<CaseReport> {
+    let mut reports = vec![CaseReport {
+        label: "known vectors".into(),
+        candidates: 0,
+        result: known_vectors(),
+    }];
+    for network in [Network::Mainnet, Network::Testnet, Network::Regtest] {
+        for (format, witness_version, compressed) in FORMATS {
+            for mode_name in ["raw", "mnemonic", "sequential"] {
+                let label = format!(
+                    "{:?} {:?}{}{} {}",
+                    network,
+                    format,
+                    if format.is_bech32() {
+                        format!(" v{}", witness_version)
+                    } else {
+                        String::new()
+                    },
+                    if compressed { "" } else { " uncompressed" },
+                    mode_name
+                );
+                let result =
+                    key_mode(mode_name, format, witness_version, network).and_then(|mode| {
+                        check_case(
+                            stream,
+                            samples,
+                            &mode,
+                            format,
+                            witness_version,
+                            compressed,
+                            &network.params(),
+                        )
+                    });
+                reports.push(CaseReport {
+                    label,
+                    candidates: samples,
+                    result,
+                });
+            }
+        }
+    }
+    reports
+}
+
+// Mnemonic cases use the standard BIP-44/49/84/86 path for the format and network, and
+// BIP-48's native SegWit script path for P2WSH.
+fn key_mode(
+    name: &str,
+    format: AddressFormat,
+    witness_version: u8,
+    network: Network,
+) -> Result<KeyMode> {
+    match name {
+        "raw" => Ok(KeyMode::Raw),
+        "sequential" => Ok(KeyMode::Sequential),
+        _ => {
+            let coin = u32::from(network != Network::Mainnet);
+            let path_string = match (format, witness_version) {
+                (AddressFormat::P2pkh, _) => format!("m/44'/{}'/0'/0/0", coin),
+                (AddressFormat::P2shP2wpkh, _) => format!("m/49'/{}'/0'/0/0", coin),
+                (AddressFormat::Bech32, 0) => format!("m/84'/{}'/0'/0/0", coin),
+                (AddressFormat::Bech32, _) => format!("m/86'/{}'/0'/0/0", coin),
+                (AddressFormat::P2wsh, _) => format!("m/48'/{}'/0'/2'/0/0", coin),
+            };
+            let path = DerivationPath::from_str(&path_string)
+                .map_err(|e| anyhow!("Invalid path {}: {e}", path_string))?;
+            if let Some(problem) = hd_path_mismatch(&path, format, witness_version, Some(network)) {
+                return Err(anyhow!(
+                    "standard path {} flagged: {}",
+                    path_string,
+                    problem
+                ));
+            }
+            Ok(KeyMode::Mnemonic {
+                path,
+                path_string,
+                words: 12,
+                passphrase: String::new(),
+                scan_indices: 1,
+            })
+        }
+    }
+}
+
+fn known_vectors() -> Result<()> {
+    let mainnet = Network::Mainnet.params();
+    let mut one = [0u8; 32];
+    one[31] = 1;
+    let secret = SecretKey::from_slice(&one)?;
+    // Private key 1 (the generator point), as encoded by Bitcoin Core; the P2WSH one is
+    // BIP-173's vector for key 1's `<pubkey> OP_CHECKSIG` script.
+    for (format, expected) in [
+        (AddressFormat::P2pkh, "1BgGZ9tcN4rm9KBzDn7KprQz87SZ26SAMH"),
+        (
+            AddressFormat::P2shP2wpkh,
+            "3JvL6Ymt8MVWiCNHC7oWU6nLeHNJKLZGLN",
+        ),
+        (
+            AddressFormat::Bech32,
+            "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4",
+        ),
+        (
+            AddressFormat::P2wsh,
+            "bc1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3qccfmv3",
+        ),
+    ] {
+        expect_address(&secret, format, 0, &mainnet, expected)?;
+    }
+    let wif = wif_from_secret(&secret, &mainnet, true);
+    if wif != "KwDiBf89QgGbjEhKnhXJuH7LrciVrZi3qYjgd9M7rFU73sVHnoWn" {
+        return Err(anyhow!("key 1 encodes to WIF {}", wif));
+    }
+    // BIP-86 first receive address of the all-`abandon` test mnemonic.
+    let mode = key_mode("mnemonic", AddressFormat::Bech32, 1, Network::Mainnet)?;
+    let xprv = mnemonic_xprv(BIP39_TEST_PHRASE, &mode, None)
+        .ok_or_else(|| anyhow!("BIP-39 test mnemonic did not derive"))?;
+    let secret = SecretKey::from_slice(&xprv.private_key().to_bytes())?;
+    expect_address(
+        &secret,
+        AddressFormat::Bech32,
+        1,
+        &mainnet,
+        "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr",
+    )?;
+    // The key schedule itself: --seed runs must reproduce across platforms.
+    let candidate = derive_candidate(&KeyStream::from_seed(1), 0, &KeyMode::Raw)
+        .ok_or_else(|| anyhow!("--seed 1 attempt 0 derived no key"))?;
+    let hex = hex::encode(candidate.secret.secret_bytes());
+    if hex != SEED_ONE_ATTEMPT_ZERO {
+        return Err(anyhow!(
+            "--seed 1 attempt 0 derives {}, not {}",
+            hex,
+            SEED_ONE_ATTEMPT_ZERO
+        ));
+    }
+    Ok(())
+}
+
+fn expect_address(
+    secret: &SecretKey,
+    format: AddressFormat,
+    witness_version: u8,
+    network: &NetworkParams,
+    expected: &str,
+) -> Result<()> {
+    let mut address = String::new();
+    encode_address(secret, format, witness_version, true, network, &mut address)?;
+    if address != expected {
+        return Err(anyhow!(
+            "known {:?} vector encodes to {}, not {}",
+            format,
+            address,
+            expected
+        ));
+    }
+    Ok(())
+}
+
+fn check_case(
+    stream: &KeyStream,
+    samples: u64,
+    mode: &KeyMode,
+    format: AddressFormat,
+    witness_version: u8,
+    compressed: bool,
+    network: &NetworkParams,
+) -> Result<()> {
+    // The search loop builds sequential keys by point addition; it must agree.
+    let mut batch = match mode {
+        KeyMode::Sequential => {
+            Some(SequentialBatch::new(stream).ok_or_else(|| anyhow!("no sequential base key"))?)
+        }
+        _ => None,
+    };
+    let mut batched = Vec::new();
+    for attempt in 0..samples {
+        let candidate = derive_candidate(stream, attempt, mode)
+            .ok_or_else(|| anyhow!("attempt {} derived no key", attempt))?;
+        let again = derive_candidate(stream, attempt, mode).map(|c| c.secret);
+        if again != Some(candidate.secret) {
+            return Err(anyhow!("attempt {} is not deterministic", attempt));
+        }
+        if let Some(batch) = &mut batch {
+            batch.candidates(attempt, &mut batched);
+            let first = batched.first().map(|c| (c.secret, c.public));
+            if first != Some((candidate.secret, candidate.public)) {
+                return Err(anyhow!(
+                    "attempt {}: batched sequential key differs from derive_candidate",
+                    attempt
+                ));
+            }
+        }
+        check_candidate(
+            &candidate,
+            mode,
+            format,
+            witness_version,
+            compressed,
+            network,
+        )
+        .with_context(|| format!("attempt {}", attempt))?;
+    }
+    Ok(())
+}
+
+fn check_candidate(
+    candidate: &CandidateKey,
+    mode: &KeyMode,
+    format: AddressFormat,
+    witness_version: u8,
+    compressed: bool,
+    network: &NetworkParams,
+) -> Result<()> {
+    if PublicKey::from_secret_key(&SECP256K1, &candidate.secret) != candidate.public {
+        return Err(anyhow!("public key is not secret * G"));
+    }
+    if let Some(phrase) = &candidate.mnemonic {
+        let xprv = mnemonic_xprv(phrase, mode, candidate.child_index)
+            .ok_or_else(|| anyhow!("mnemonic does not re-derive"))?;
+        if SecretKey::from_slice(&xprv.private_key().to_bytes()).ok() != Some(candidate.secret) {
+            return Err(anyhow!("mnemonic re-derives a different key"));
+        }
+    }
+
+    let wif = wif_from_secret(&candidate.secret, network, compressed);
+    let decoded = decode_wif(&wif).with_context(|| format!("WIF {}", wif))?;
+    if decoded != (network.wif_version, candidate.secret, compressed) {
+        return Err(anyhow!("WIF {} decodes to a different key", wif));
+    }
+
+    let mut address = String::new();
+    encode_address(
+        &candidate.secret,
+        format,
+        witness_version,
+        compressed,
+        network,
+        &mut address,
+    )?;
+    // The search loop reuses one scratch buffer per worker; a dirty buffer must not leak.
+    let mut scratch = EncodeScratch::default();
+    let mut reused = String::new();
+    for _ in 0..2 {
+        encode_public_address_with(
+            &candidate.public,
+            format,
+            witness_version,
+            compressed,
+            network,
+            &mut scratch,
+            &mut reused,
+        )?;
+    }
+    if reused != address {
+        return Err(anyhow!(
+            "scratch encoder gives {}, plain encoder {}",
+            reused,
+            address
+        ));
+    }
+    check_address(
+        &address,
+        &candidate.public,
+        format,
+        witness_version,
+        compressed,
+        network,
+    )
+    .with_context(|| format!("address {}", address))
+}
+
+// Decodes `address` and checks every field against the key, then re-encodes it.
+fn check_address(
+    address: &str,
+    public: &PublicKey,
+    format: AddressFormat,
+    witness_version: u8,
+    compressed: bool,
+    network: &NetworkParams,
+) -> Result<()> {
+    let key_hash = if compressed {
+        hash160(&public.serialize())
+    } else {
+        hash160(&public.serialize_uncompressed())
+    };
+    let reencoded = match format {
+        AddressFormat::P2pkh | AddressFormat::P2shP2wpkh => {
+            let payload = decode_base58check(address)?;
+            let (version, hash) = if format == AddressFormat::P2pkh {
+                (network.p2pkh_version, key_hash)
+            } else {
+                let mut redeem_script = vec![0x00, 0x14];
+                redeem_script.extend_from_slice(&key_hash);
+                (network.p2sh_version, hash160(&redeem_script))
+            };
+            if payload.len() != 21 || payload[0] != version || payload[1..] != hash {
+                return Err(anyhow!(
+                    "does not decode to version {:#04x} + the expected hash160",
+                    version
+                ));
+            }
+            let mut full = payload.clone();
+            full.extend_from_slice(&double_sha256(&payload)[..4]);
+            let mut out = String::new();
+            encode_base58(&full, &mut out);
+            out
+        }
+        AddressFormat::Bech32 | AddressFormat::P2wsh => {
+            let (hrp, data, variant) =
+                bech32::decode(address).map_err(|e| anyhow!("Invalid Bech32: {e}"))?;
+            let expected_variant = if witness_version == 0 {
+                Variant::Bech32
+            } else {
+                Variant::Bech32m
+            };
+            if hrp != network.hrp
+                || variant != expected_variant
+                || data.first().map(|v| v.to_u8()) != Some(witness_version)
+            {
+                return Err(anyhow!(
+                    "decodes to HRP {} version {:?} ({:?})",
+                    hrp,
+                    data.first().map(|v| v.to_u8()),
+                    variant
+                ));
+            }
+            let program = Vec::<u8>::from_base32(&data[1..])
+                .map_err(|e| anyhow!("Invalid witness program: {e}"))?;
+            let expected = if format == AddressFormat::P2wsh {
+                let mut witness_script = vec![0x21];
+                witness_script.extend_from_slice(&public.serialize());
+                witness_script.push(0xac);
+                Sha256::digest(&witness_script).to_vec()
+            } else if witness_version == 0 {
+                key_hash.to_vec()
+            } else {
+                taproot_output_key(&public.x_only_public_key().0)?
+                    .serialize()
+                    .to_vec()
+            };
+            if program != expected {
+                return Err(anyhow!("witness program does not commit to the key"));
+            }
+            bech32::encode(&hrp, data, variant).map_err(|e| anyhow!("Bech32 re-encode: {e}"))?
+        }
+    };
+    if reencoded != address {
+        return Err(anyhow!("re-encodes to {}", reencoded));
+    }
+    Ok(())
+}
+
+fn hash160(data: &[u8]) -> [u8; 20] {
+    Ripemd160::digest(Sha256::digest(data)).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_case_passes() {
+        for report in run(&KeyStream::from_seed(5), 2) {
+            if let Err(err) = report.result {
+                panic!("{}: {:#}", report.label, err);
+            }
+        }
+    }
+}
@@ -0,0 +1,343 @@
+//! Re-check saved results against the encoders: every stored key must reproduce its address.
+
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context, Result};
+use bip32::DerivationPath;
+use secp256k1::{PublicKey, Scalar, SecretKey};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{
+    address::{
+        address_payload, encode_public_address, p2wsh_witness_script, wif_from_secret,
+        AddressFormat, Network, NetworkParams, SECP256K1,
+    },
+    descriptor::{descriptor, ranged_key},
+    keys::{extended_keys, mnemonic_master, mnemonic_xprv, KeyMode},
+    output::{is_search_miss, OtherCompression},
+};
+
+// The subset of `VanityResult` needed to re-derive an address. Fields added in later
+// versions default so older result files still verify.
+#[derive(Deserialize)]
+struct StoredEntry {
+    address: String,
+    format: String,
+    #[serde(default)]
+    witness_version: Option<u8>,
+    #[serde(default = "default_compressed")]
+    compressed: bool,
+    #[serde(default)]
+    network: Option<String>,
+    #[serde(default)]
+    network_params: Option<NetworkParams>,
+    #[serde(default)]
+    private_key_hex: Option<String>,
+    #[serde(default)]
+    wif: Option<String>,
+    #[serde(default)]
+    public_key_hex: Option<String>,
+    #[serde(default)]
+    witness_script: Option<String>,
+    #[serde(default)]
+    address_hash: Option<String>,
+    #[serde(default)]
+    split_key_offset: Option<String>,
+    #[serde(default)]
+    split_key_base: Option<String>,
+    #[serde(default)]
+    mnemonic: Option<String>,
+    #[serde(default)]
+    hd_path: Option<String>,
+    #[serde(default)]
+    passphrase_used: bool,
+    #[serde(default)]
+    xprv: Option<String>,
+    #[serde(default)]
+    xpub: Option<String>,
+    #[serde(default)]
+    descriptor: Option<String>,
+    #[serde(default)]
+    other_compression: Option<OtherCompression>,
+}
+
+fn default_compressed() -> bool {
+    true
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Pass,
+    // Nothing in the entry can be checked without a secret we don't have.
+    Skipped(&'static str),
+}
+
+/// `Err` describes the first mismatch. `passphrase` is the BIP-39 passphrase for entries
+/// recorded with `passphrase_used`.
+pub fn verify_entry(entry: &Value, passphrase: Option<&str>) -> Result<Verdict> {
+    if is_search_miss(entry) {
+        return Ok(Verdict::Skipped("no key: a --record-misses session record"));
+    }
+    let entry: StoredEntry =
+        serde_json::from_value(entry.clone()).context("Not a vanity_bitcoin result entry")?;
+    let format = match entry.format.as_str() {
+        "P2pkh" => AddressFormat::P2pkh,
+        "P2shP2wpkh" => AddressFormat::P2shP2wpkh,
+        "Bech32" => AddressFormat::Bech32,
+        "P2wsh" => AddressFormat::P2wsh,
+        other => return Err(anyhow!("Unknown format '{}'", other)),
+    };
+    let params = match entry.network.as_deref().unwrap_or("Mainnet") {
+        "Mainnet" => Network::Mainnet.params(),
+        "Testnet" => Network::Testnet.params(),
+        "Regtest" => Network::Regtest.params(),
+        "Custom" => entry
+            .network_params
+            .clone()
+            .ok_or_else(|| anyhow!("Custom network entry without network_params"))?,
+        other => return Err(anyhow!("Unknown network '{}'", other)),
+    };
+    // Extended keys and descriptors only know Bitcoin's networks, so on any other they
+    // would describe a different coin's key than the address.
+    if params.preset().is_none()
+        && (entry.xprv.is_some() || entry.xpub.is_some() || entry.descriptor.is_some())
+    {
+        return Err(anyhow!(
+            "custom-network entry carries xprv/xpub/descriptor, which only encode Bitcoin networks"
+        ));
+    }
+    let witness_version = entry.witness_version.unwrap_or(0);
+
+    let (public, secret) = if let Some(hex) = &entry.private_key_hex {
+        let secret = parse_secret_hex(hex).context("Invalid private_key_hex")?;
+        (
+            PublicKey::from_secret_key(&SECP256K1, &secret),
+            Some(secret),
+        )
+    } else if let (Some(offset), Some(base)) = (&entry.split_key_offset, &entry.split_key_base) {
+        let offset = parse_secret_hex(offset).context("Invalid split_key_offset")?;
+        let base = hex::decode(base)
+            .ok()
+            .and_then(|bytes| PublicKey::from_slice(&bytes).ok())
+            .ok_or_else(|| anyhow!("Invalid split_key_base"))?;
+        let public = base
+            .add_exp_tweak(&SECP256K1, &Scalar::from(offset))
+            .map_err(|_| anyhow!("split_key_base + offset is not a valid point"))?;
+        (public, None)
+    } else {
+        return Ok(Verdict::Skipped("no plaintext key (BIP-38 only?)"));
+    };
+
+    let mut address = String::with_capacity(64);
+    encode_public_address(
+        &public,
+        format,
+        witness_version,
+        entry.compressed,
+        &params,
+        &mut address,
+    )?;
+    if address != entry.address {
+        return Err(anyhow!("key encodes to {}, not {}", address, entry.address));
+    }
+    if let Some(stored) = &entry.public_key_hex {
+        let expected = if entry.compressed {
+            hex::encode(public.serialize())
+        } else {
+            hex::encode(public.serialize_uncompressed())
+        };
+        if *stored != expected {
+            return Err(anyhow!("public_key_hex does not match the key"));
+        }
+    }
+    if let Some(stored) = &entry.witness_script {
+        if *stored != hex::encode(p2wsh_witness_script(&public)) {
+            return Err(anyhow!("witness_script does not match the key"));
+        }
+    }
+    if let Some(stored) = &entry.address_hash {
+        let mut payload = [0u8; 32];
+        let len = address_payload(
+            &public,
+            format,
+            witness_version,
+            entry.compressed,
+            &mut payload,
+        )?;
+        if *stored != hex::encode(&payload[..len]) {
+            return Err(anyhow!("address_hash does not match the key"));
+        }
+    }
+    if let Some(other) = &entry.other_compression {
+        let mut address = String::with_capacity(64);
+        encode_public_address(
+            &public,
+            AddressFormat::P2pkh,
+            0,
+            other.compressed,
+            &params,
+            &mut address,
+        )?;
+        let expected = if other.compressed {
+            hex::encode(public.serialize())
+        } else {
+            hex::encode(public.serialize_uncompressed())
+        };
+        if other.address != address || other.public_key_hex != expected {
+            return Err(anyhow!("other_compression does not match the key"));
+        }
+        let wif = secret.map(|secret| wif_from_secret(&secret, &params, other.compressed));
+        if other.wif.is_some() && other.wif != wif {
+            return Err(anyhow!("other_compression wif does not match the key"));
+        }
+    }
+    let Some(secret) = secret else {
+        return Ok(Verdict::Pass);
+    };
+    if let Some(wif) = &entry.wif {
+        if *wif != wif_from_secret(&secret, &params, entry.compressed) {
+            return Err(anyhow!("wif does not match private_key_hex"));
+        }
+        // Compressed mnemonic entries describe their branch instead, checked below.
+        if entry.mnemonic.is_none() || !entry.compressed {
+            check_descriptor(&entry, format, witness_version, wif)?;
+        }
+    }
+    if let Some(phrase) = &entry.mnemonic {
+        if entry.passphrase_used && passphrase.is_none() {
+            return Err(anyhow!(
+                "mnemonic was used with a passphrase; pass --passphrase to check it"
+            ));
+        }
+        let path_string = entry
+            .hd_path
+            .clone()
+            .ok_or_else(|| anyhow!("mnemonic entry has no hd_path"))?;
+        let mode = KeyMode::Mnemonic {
+            path: DerivationPath::from_str(&path_string)
+                .map_err(|e| anyhow!("Invalid hd_path: {e}"))?,
+            path_string,
+            words: phrase.split_whitespace().count(),
+            passphrase: passphrase.unwrap_or_default().to_string(),
+            scan_indices: 1,
+        };
+        let xprv = mnemonic_xprv(phrase, &mode, None).ok_or_else(|| anyhow!("Invalid mnemonic"))?;
+        if SecretKey::from_slice(&xprv.private_key().to_bytes()).ok() != Some(secret) {
+            return Err(anyhow!("mnemonic + hd_path derive a different key"));
+        }
+        let master = mnemonic_master(phrase, &mode).filter(|_| entry.compressed);
+        if let (Some(master), KeyMode::Mnemonic { path, .. }, Some(network)) =
+            (master, &mode, params.preset())
+        {
+            let key = ranged_key(&extended_keys(&master, network).0, path);
+            check_descriptor(&entry, format, witness_version, &key)?;
+        }
+    }
+    Ok(Verdict::Pass)
+}
+
+fn check_descriptor(
+    entry: &StoredEntry,
+    format: AddressFormat,
+    witness_version: u8,
+    key: &str,
+) -> Result<()> {
+    match &entry.descriptor {
+        Some(stored) if descriptor(format, witness_version, key).as_ref() != Some(stored) => {
+            Err(anyhow!("descriptor does not match the key"))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn parse_secret_hex(value: &str) -> Result<SecretKey> {
+    let bytes = hex::decode(value.trim_start_matches("0x"))?;
+    SecretKey::from_slice(&bytes).map_err(|_| anyhow!("not a valid secp256k1 secret"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn checks_key_against_address() {
+        let entry = json!({
+            "address": "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4",
+            "format": "Bech32",
+            "witness_version": 0,
+            "network": "Mainnet",
+            "private_key_hex": format!("0x{:064x}", 1),
+            "wif": "KwDiBf89QgGbjEhKnhXJuH7LrciVrZi3qYjgd9M7rFU73sVHnoWn",
+            "descriptor": "wpkh(KwDiBf89QgGbjEhKnhXJuH7LrciVrZi3qYjgd9M7rFU73sVHnoWn)#gul0776m",
+        });
+        assert_eq!(verify_entry(&entry, None).unwrap(), Verdict::Pass);
+
+        let mut wrong_script = entry.clone();
+        wrong_script["descriptor"] =
+            json!("pkh(KwDiBf89QgGbjEhKnhXJuH7LrciVrZi3qYjgd9M7rFU73sVHnoWn)#yj0ctua6");
+        assert!(verify_entry(&wrong_script, None).is_err());
+
+        let mut tampered = entry.clone();
+        tampered["address"] = json!("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t5");
+        assert!(verify_entry(&tampered, None).is_err());
+        let mut wrong_key = entry;
+        wrong_key["private_key_hex"] = json!(format!("0x{:064x}", 2));
+        assert!(verify_entry(&wrong_key, None).is_err());
+    }
+
+    #[test]
+    fn custom_networks_carry_no_bitcoin_extended_keys() {
+        let params = NetworkParams {
+            p2pkh_version: 0x30,
+            wif_version: 0xb0,
+            ..Network::Mainnet.params()
+        };
+        let secret = SecretKey::from_slice(&[1; 32]).unwrap();
+        let mut address = String::new();
+        crate::address::encode_address(
+            &secret,
+            AddressFormat::P2pkh,
+            0,
+            true,
+            &params,
+            &mut address,
+        )
+        .unwrap();
+        let entry = json!({
+            "address": address,
+            "format": "P2pkh",
+            "network": "Custom",
+            "network_params": params,
+            "private_key_hex": format!("0x{}", hex::encode(secret.secret_bytes())),
+            "wif": wif_from_secret(&secret, &params, true),
+        });
+        assert_eq!(verify_entry(&entry, None).unwrap(), Verdict::Pass);
+        for field in ["xprv", "xpub", "descriptor"] {
+            let mut mixed = entry.clone();
+            mixed[field] = json!("x");
+            assert!(verify_entry(&mixed, None).is_err(), "{field}");
+        }
+    }
+
+    #[test]
+    fn checks_the_other_compression() {
+        let entry = json!({
+            "address": "1BgGZ9tcN4rm9KBzDn7KprQz87SZ26SAMH",
+            "format": "P2pkh",
+            "private_key_hex": format!("0x{:064x}", 1),
+            "other_compression": {
+                "compressed": false,
+                "address": "1EHNa6Q4Jz2uvNExL497mE43ikXhwF6kZm",
+                "public_key_hex": "0479be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798483ada7726a3c4655da4fbfc0e1108a8fd17b448a68554199c47d08ffb10d4b8",
+                "wif": "5HpHagT65TZzG1PH3CSu63k8DbpvD8s5ip4nEB3kEsreAnchuDf",
+            },
+        });
+        assert_eq!(verify_entry(&entry, None).unwrap(), Verdict::Pass);
+
+        let mut swapped = entry;
+        swapped["other_compression"]["compressed"] = json!(true);
+        assert!(verify_entry(&swapped, None).is_err());
+    }
+}
@@ -0,0 +1,411 @@
+//! End-to-end runs of `vanity_bitcoin`: a fixed `--seed`, an easy pattern and the result
+//! file checked against the library encoders.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use serde_json::Value;
+use vanity::address::{decode_wif, encode_address, AddressFormat, Network};
+use vanity::verify::{verify_entry, Verdict};
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("vanity-cli-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// One thread keeps the first match (and so the whole result) a function of the seed.
+fn run(args: &[&str]) -> Output {
+    let output = Command::new(env!("CARGO_BIN_EXE_vanity_bitcoin"))
+        .args(["--threads", "1", "--quiet"])
+        .args(args)
+        .env("RUST_BACKTRACE", "0")
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "vanity_bitcoin {args:?} failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    output
+}
+
+fn only_entry(path: &Path) -> Value {
+    let entries: Vec<Value> = serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+    assert_eq!(entries.len(), 1, "{entries:?}");
+    entries.into_iter().next().unwrap()
+}
+
+// The stored address is what the encoders make of the stored WIF, and the whole entry
+// re-derives under `verify`.
+fn check_entry(entry: &Value, format: AddressFormat, witness_version: u8, prefix: &str) {
+    let address = entry["address"].as_str().unwrap();
+    assert!(address.starts_with(prefix), "{address} lacks {prefix}");
+    let (_, secret, compressed) = decode_wif(entry["wif"].as_str().unwrap()).unwrap();
+    let mut encoded = String::new();
+    encode_address(
+        &secret,
+        format,
+        witness_version,
+        compressed,
+        &Network::Mainnet.params(),
+        &mut encoded,
+    )
+    .unwrap();
+    assert_eq!(encoded, address);
+    assert_eq!(verify_entry(entry, None).unwrap(), Verdict::Pass);
+}
+
+#[test]
+fn raw_searches_find_verifiable_keys_in_every_format() {
+    let dir = scratch_dir("raw");
+    for (name, format, version, prefix) in [
+        ("p2pkh", AddressFormat::P2pkh, 0, "1a"),
+        ("p2sh-p2wpkh", AddressFormat::P2shP2wpkh, 0, "3A"),
+        ("bech32", AddressFormat::Bech32, 0, "bc1qa"),
+        ("bech32", AddressFormat::Bech32, 1, "bc1pa"),
+        ("p2wsh", AddressFormat::P2wsh, 0, "bc1qa"),
+    ] {
+        let result = dir.join(format!("{name}-v{version}.json"));
+        let version_arg = version.to_string();
+        run(&[
+            "--seed",
+            "1",
+            "--format",
+            name,
+            "--witness-version",
+            &version_arg,
+            "--prefix",
+            prefix,
+            "--output",
+            result.to_str().unwrap(),
+        ]);
+        check_entry(&only_entry(&result), format, version, prefix);
+    }
+    let p2pkh = only_entry(&dir.join("p2pkh-v0.json"));
+    assert_eq!(p2pkh["address"], "1a2JMjh49ANK91FQZqgN6Y6mzTdk5n1iG");
+    assert_eq!(p2pkh["attempt_index"], 1024);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn mnemonic_searches_find_verifiable_keys() {
+    let dir = scratch_dir("mnemonic");
+    let result = dir.join("result.json");
+    run(&[
+        "--seed",
+        "1",
+        "--mnemonic",
+        "--prefix",
+        "1a",
+        "--output",
+        result.to_str().unwrap(),
+    ]);
+    let entry = only_entry(&result);
+    check_entry(&entry, AddressFormat::P2pkh, 0, "1a");
+    assert_eq!(entry["hd_path"], "m/44'/0'/0'/0/0");
+    assert_eq!(entry["mnemonic"].as_str().unwrap().split(' ').count(), 24);
+    // The bin's own verifier agrees.
+    run(&["verify", result.to_str().unwrap()]);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn resumed_searches_finish_where_an_uninterrupted_one_would() {
+    let dir = scratch_dir("resume");
+    let (checkpoint, result) = (dir.join("checkpoint.json"), dir.join("result.json"));
+    let (checkpoint, result) = (checkpoint.to_str().unwrap(), result.to_str().unwrap());
+    run(&[
+        "--seed",
+        "1",
+        "--prefix",
+        "1a",
+        "--attempts",
+        "500",
+        "--checkpoint",
+        checkpoint,
+        "--output",
+        result,
+    ]);
+    assert!(!Path::new(result).exists());
+    let saved: Value = serde_json::from_str(&fs::read_to_string(checkpoint).unwrap()).unwrap();
+    assert_eq!(saved["next_attempt"], 500);
+
+    run(&["--prefix", "1a", "--resume", checkpoint, "--output", result]);
+    let entry = only_entry(Path::new(result));
+    check_entry(&entry, AddressFormat::P2pkh, 0, "1a");
+    assert_eq!(entry["address"], "1a2JMjh49ANK91FQZqgN6Y6mzTdk5n1iG");
+    assert_eq!(entry["attempt_index"], 1024);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// A kill can land after a match is saved but before the checkpoint passes it: the resumed
+// search finds it again, counts it once and doesn't save it twice.
+#[test]
+fn matches_saved_past_the_checkpoint_are_not_saved_again() {
+    let dir = scratch_dir("resume-saved");
+    let (checkpoint, result) = (dir.join("checkpoint.json"), dir.join("result.json"));
+    let (checkpoint, result) = (checkpoint.to_str().unwrap(), result.to_str().unwrap());
+    run(&[
+        "--seed",
+        "1",
+        "--prefix",
+        "1a",
+        "--checkpoint",
+        checkpoint,
+        "--output",
+        result,
+    ]);
+    let mut saved: Value = serde_json::from_str(&fs::read_to_string(checkpoint).unwrap()).unwrap();
+    saved["next_attempt"] = 0.into();
+    saved["matches_found"] = 0.into();
+    fs::write(checkpoint, saved.to_string()).unwrap();
+
+    run(&[
+        "--prefix", "1a", "--count", "2", "--resume", checkpoint, "--output", result,
+    ]);
+    let entries: Vec<Value> = serde_json::from_str(&fs::read_to_string(result).unwrap()).unwrap();
+    let indices: Vec<_> = entries.iter().map(|e| e["attempt_index"].clone()).collect();
+    assert_eq!(indices.len(), 2, "{entries:?}");
+    assert_eq!(indices[0], 1024);
+    assert!(indices[1].as_u64().unwrap() > 1024);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn unwritable_outputs_fail_before_the_search() {
+    let dir = scratch_dir("unwritable");
+    // A regular file where the output directory should be.
+    let blocker = dir.join("results");
+    fs::write(&blocker, b"").unwrap();
+    let result = blocker.join("result.json");
+    let output = Command::new(env!("CARGO_BIN_EXE_vanity_bitcoin"))
+        .args(["--seed", "1", "--prefix", "1a", "--attempts", "1"])
+        .arg("--output")
+        .arg(&result)
+        .env("RUST_BACKTRACE", "0")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Invalid output path"), "{stderr}");
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("attempts"));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn a_result_that_cannot_be_saved_fails_the_run_below_its_attempt() {
+    let dir = scratch_dir("unsaved");
+    let (checkpoint, result) = (dir.join("checkpoint.json"), dir.join("result.json"));
+    // Not a json array, so appending the match fails once it's found.
+    fs::write(&result, b"not json").unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_vanity_bitcoin"))
+        .args(["--threads", "1", "--quiet", "--seed", "1", "--prefix", "1a"])
+        .args(["--summary-json", "--checkpoint"])
+        .arg(&checkpoint)
+        .arg("--output")
+        .arg(&result)
+        .env("RUST_BACKTRACE", "0")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("1a2JMjh49ANK91FQZqgN6Y6mzTdk5n1iG"),
+        "{stdout}"
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let summary: Value = stderr
+        .lines()
+        .find_map(|line| serde_json::from_str(line).ok())
+        .unwrap();
+    assert_eq!(summary["found"], false);
+    assert_eq!(summary["stop_reason"], "save_failed");
+    let saved: Value = serde_json::from_str(&fs::read_to_string(&checkpoint).unwrap()).unwrap();
+    assert_eq!(saved["next_attempt"], 1024);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn recorded_misses_log_the_session_without_a_key() {
+    let dir = scratch_dir("misses");
+    let result = dir.join("result.json");
+    let result = result.to_str().unwrap();
+    let args = ["--prefix", "1aaaa", "--record-misses", "--output", result];
+    run(&[&["--seed", "1", "--attempts", "300"][..], &args].concat());
+    let miss = only_entry(Path::new(result));
+    assert_eq!(miss["found"], false);
+    assert_eq!(miss["stop_reason"], "exhausted");
+    assert_eq!(miss["attempts"], 300);
+    assert_eq!(miss["next_attempt"], 300);
+    assert!(miss.get("wif").is_none());
+    // The bin's verifier skips it rather than failing.
+    let verified = run(&["verify", result]);
+    assert!(String::from_utf8_lossy(&verified.stdout).contains("1 skipped"));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn derive_material_shows_the_key_a_search_found() {
+    let output = run(&["derive-material", "--seed", "1", "--attempt", "1024"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Address   : 1a2JMjh49ANK91FQZqgN6Y6mzTdk5n1iG"),
+        "{stdout}"
+    );
+    let material = stdout
+        .lines()
+        .find_map(|line| line.strip_prefix("Material  : "))
+        .and_then(|rest| rest.split(' ').next())
+        .unwrap();
+    let secret = stdout
+        .lines()
+        .find_map(|line| line.strip_prefix("SecretHex : 0x"))
+        .and_then(|rest| rest.split(' ').next())
+        .unwrap();
+    assert_eq!(material, secret);
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("64-bit seed"));
+}
+
+#[test]
+fn searches_from_a_u64_seed_warn_that_the_keys_are_guessable() {
+    let dir = scratch_dir("seed-warning");
+    let result = dir.join("result.json");
+    let output = run(&[
+        "--seed",
+        "1",
+        "--prefix",
+        "1a",
+        "--output",
+        result.to_str().unwrap(),
+    ]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("64-bit seed") && stderr.contains("--seed-hex"),
+        "{stderr}"
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn fixed_mnemonic_results_keep_the_phrase_out() {
+    let dir = scratch_dir("fixed");
+    let result = dir.join("result.json");
+    let phrase = [&["abandon"; 11][..], &["about"]].concat().join(" ");
+    run(&[
+        "--mnemonic",
+        "--fixed-mnemonic",
+        &phrase,
+        "--hd-path",
+        "m/84'/0'/0'/0/0",
+        "--format",
+        "bech32",
+        "--prefix",
+        "bc1qa",
+        "--output",
+        result.to_str().unwrap(),
+    ]);
+    let entry = only_entry(&result);
+    check_entry(&entry, AddressFormat::Bech32, 0, "bc1qa");
+    let index = entry["hd_index"].as_u64().unwrap();
+    assert_eq!(entry["attempt_index"], index);
+    assert_eq!(entry["hd_path"], format!("m/84'/0'/0'/0/{index}"));
+    assert!(entry["mnemonic"].is_null());
+    assert!(entry.get("xprv").is_none());
+    let descriptor = entry["descriptor"].as_str().unwrap();
+    assert!(descriptor.contains(entry["wif"].as_str().unwrap()));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// `vanity_bitcoin` with --encrypt-output and the password in the environment.
+fn run_encrypted(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_vanity_bitcoin"))
+        .args([
+            "--threads",
+            "1",
+            "--quiet",
+            "--seed",
+            "1",
+            "--encrypt-output",
+        ])
+        .args(args)
+        .env("VANITY_BITCOIN_PASSWORD", "hunter2")
+        .env("RUST_BACKTRACE", "0")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn encrypted_runs_keep_the_key_off_stdout_and_out_of_checkpoints() {
+    let dir = scratch_dir("encrypted");
+    let result = dir.join("result.json");
+    let output = run_encrypted(&["--prefix", "1a", "--output", result.to_str().unwrap()]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("1a2JMjh49ANK91FQZqgN6Y6mzTdk5n1iG"),
+        "{stdout}"
+    );
+    assert!(
+        stdout.contains("in the encrypted result file only"),
+        "{stdout}"
+    );
+    assert!(
+        !stdout.contains("SecretHex") && !stdout.contains("WIF"),
+        "{stdout}"
+    );
+
+    // A checkpoint would hold the seed root in the clear, so it needs an explicit opt-in.
+    let checkpoint = dir.join("checkpoint.json");
+    let output = run_encrypted(&[
+        "--prefix",
+        "1a",
+        "--output",
+        dir.join("again.json").to_str().unwrap(),
+        "--checkpoint",
+        checkpoint.to_str().unwrap(),
+    ]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--plaintext-checkpoint"), "{stderr}");
+    assert!(!checkpoint.exists());
+    let output = run_encrypted(&[
+        "--prefix",
+        "1a",
+        "--output",
+        dir.join("again.json").to_str().unwrap(),
+        "--checkpoint",
+        checkpoint.to_str().unwrap(),
+        "--plaintext-checkpoint",
+    ]);
+    assert!(output.status.success());
+    assert!(checkpoint.exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn encrypted_runs_write_no_secret_qr_codes() {
+    let dir = scratch_dir("encrypted-qr");
+    let result = dir.join("result.json");
+    let output = run_encrypted(&[
+        "--mnemonic",
+        "--prefix",
+        "1a",
+        "--qr",
+        "png",
+        "--qr-mnemonic",
+        "--output",
+        result.to_str().unwrap(),
+    ]);
+    assert!(output.status.success());
+    let pngs: Vec<String> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .filter(|name| name.ends_with(".png"))
+        .collect();
+    assert_eq!(pngs.len(), 1, "{pngs:?}");
+    assert!(pngs[0].ends_with("-address.png"), "{pngs:?}");
+    fs::remove_dir_all(&dir).unwrap();
+}